@@ -0,0 +1,141 @@
+//! Rasterizer benchmarks, on a plain array and through the backends.
+//!
+//! Run with `cargo bench --features wasmi,wasmer` to include the backends.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use wasmstation::core::{framebuffer, utils, wasm4::BLIT_2BPP};
+
+/// A 16x16 2bpp sprite using all four draw colors.
+const SPRITE: [u8; 64] = [0b00_01_10_11; 64];
+
+/// Where the benchmark carts keep [`SPRITE`], right after the framebuffer.
+#[cfg(any(feature = "wasmi", feature = "wasmer"))]
+const SPRITE_ADDR: usize = 0x19a0;
+
+/// The drawing done by each benchmark, as the body of a cart's `update`.
+#[cfg(any(feature = "wasmi", feature = "wasmer"))]
+const CARTS: [(&str, &str); 4] = [
+    // the backends clear the framebuffer before every update.
+    ("clear", ""),
+    (
+        "rect",
+        "(call $rect (i32.const 0) (i32.const 0) (i32.const 160) (i32.const 160))",
+    ),
+    (
+        "hline",
+        "(loop $rows
+            (call $hline (i32.const 1) (local.get $i) (i32.const 158))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br_if $rows (i32.lt_s (local.get $i) (i32.const 160))))",
+    ),
+    (
+        "blit_2bpp",
+        "(loop $sprites
+            (call $blit
+                (i32.const 0x19a0)
+                (i32.mul (i32.rem_s (local.get $i) (i32.const 10)) (i32.const 16))
+                (i32.mul (i32.div_s (local.get $i) (i32.const 10)) (i32.const 16))
+                (i32.const 16) (i32.const 16) (i32.const 1))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br_if $sprites (i32.lt_s (local.get $i) (i32.const 100))))",
+    ),
+];
+
+fn rasterizer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rasterizer");
+    let mut fb = utils::default_framebuffer();
+
+    group.bench_function("clear", |b| b.iter(|| framebuffer::clear(&mut fb)));
+    group.bench_function("rect", |b| {
+        b.iter(|| framebuffer::rect(&mut fb, 0x2, 0, 0, black_box(160), 160))
+    });
+    group.bench_function("hline", |b| {
+        b.iter(|| {
+            for y in 0..160 {
+                framebuffer::hline(&mut fb, 0x2, 1, y, black_box(158));
+            }
+        })
+    });
+    group.bench_function("blit_2bpp", |b| {
+        b.iter(|| {
+            for i in 0..100 {
+                framebuffer::blit_sub(
+                    &mut fb,
+                    &SPRITE,
+                    (i % 10) * 16,
+                    (i / 10) * 16,
+                    16,
+                    16,
+                    0,
+                    0,
+                    16,
+                    BLIT_2BPP,
+                    0x4321,
+                );
+            }
+        })
+    });
+
+    group.finish();
+}
+
+/// Build a cart running `body` on every update.
+#[cfg(any(feature = "wasmi", feature = "wasmer"))]
+fn cart(body: &str) -> Vec<u8> {
+    let sprite: String = SPRITE.iter().map(|b| format!("\\{b:02x}")).collect();
+
+    wat::parse_str(format!(
+        r#"(module
+            (import "env" "memory" (memory 1 1))
+            (import "env" "rect" (func $rect (param i32 i32 i32 i32)))
+            (import "env" "hline" (func $hline (param i32 i32 i32)))
+            (import "env" "blit" (func $blit (param i32 i32 i32 i32 i32 i32)))
+            (data (i32.const {SPRITE_ADDR}) "{sprite}")
+            (func (export "update") (local $i i32)
+                (i32.store16 (i32.const 0x14) (i32.const 0x4321))
+                {body}))"#
+    ))
+    .expect("benchmark cart should be valid")
+}
+
+#[cfg(any(feature = "wasmi", feature = "wasmer"))]
+fn backend<B: wasmstation::Backend>(
+    c: &mut Criterion,
+    name: &str,
+    load: impl Fn(&[u8], &wasmstation::Console) -> B,
+) {
+    let console = wasmstation::Console::new(Box::new(|_| ()));
+    let mut group = c.benchmark_group(name);
+
+    for (op, body) in CARTS {
+        let mut backend = load(&cart(body), &console);
+        backend.call_start();
+
+        group.bench_function(op, |b| b.iter(|| backend.call_update()));
+    }
+
+    group.finish();
+}
+
+#[cfg(feature = "wasmi")]
+fn wasmi(c: &mut Criterion) {
+    backend(c, "wasmi", |bytes, console| {
+        wasmstation::WasmiBackend::from_bytes(bytes, console).unwrap()
+    });
+}
+
+#[cfg(feature = "wasmer")]
+fn wasmer(c: &mut Criterion) {
+    backend(c, "wasmer", |bytes, console| {
+        wasmstation::WasmerBackend::from_bytes(bytes, console).unwrap()
+    });
+}
+
+#[cfg(not(feature = "wasmi"))]
+fn wasmi(_: &mut Criterion) {}
+
+#[cfg(not(feature = "wasmer"))]
+fn wasmer(_: &mut Criterion) {}
+
+criterion_group!(benches, rasterizer, wasmi, wasmer);
+criterion_main!(benches);
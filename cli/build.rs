@@ -0,0 +1,17 @@
+use std::{env, fs, path::Path};
+
+/// The web runtime for `wasmstation bundle`, built by `web/build.sh`.
+const RUNTIME: [&str; 2] = ["wasmstation_web.js", "wasmstation_web_bg.wasm"];
+
+fn main() {
+    let pkg = Path::new("../web/pkg");
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    println!("cargo:rerun-if-changed=../web/pkg");
+
+    // without a built runtime the files are left empty, and bundling fails.
+    for file in RUNTIME {
+        let bytes = fs::read(pkg.join(file)).unwrap_or_default();
+        fs::write(Path::new(&out_dir).join(file), bytes).unwrap();
+    }
+}
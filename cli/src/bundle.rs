@@ -0,0 +1,101 @@
+//! Single file HTML players for carts, made by `wasmstation bundle`.
+
+use wasmstation::core::{meta::CartMeta, utils};
+
+/// The web runtime, see `web/`.
+const RUNTIME_JS: &str = include_str!(concat!(env!("OUT_DIR"), "/wasmstation_web.js"));
+const RUNTIME_WASM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/wasmstation_web_bg.wasm"));
+
+/// Make a page that plays `cart`, keeping its saves in `localStorage` under
+/// `save_key`.
+///
+/// The cart is stored like `w4 bundle --html` does, so the page can be read
+/// by [`CartMeta::from_bundle`].
+pub fn html(cart: &[u8], meta: &CartMeta, save_key: &str) -> anyhow::Result<String> {
+    if RUNTIME_WASM.is_empty() {
+        return Err(anyhow::anyhow!(
+            "this build has no web runtime, run web/build.sh and build the CLI again"
+        ));
+    }
+
+    let cart_json = format!(
+        "{{\"WASM4_CART\":\"{}\",\"WASM4_CART_SIZE\":{}}}",
+        utils::to_base64(cart),
+        cart.len()
+    );
+
+    let title = escape(meta.title.as_deref().unwrap_or(save_key));
+    let description = escape(meta.description.as_deref().unwrap_or_default());
+
+    Ok(fill(
+        include_str!("bundle.html"),
+        &[
+            ("runtime_js", RUNTIME_JS),
+            ("runtime_wasm", &utils::to_base64(RUNTIME_WASM)),
+            ("cart_json", &cart_json),
+            ("save_key", &js_string(save_key)),
+            ("description", &description),
+            ("title", &title),
+        ],
+    ))
+}
+
+/// Replace each `{name}` in `template` with its value. It's done in one pass,
+/// so text from the cart is never mistaken for a placeholder, and any other
+/// braces are kept.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let placeholder = rest.find('}').and_then(|end| {
+            let (_, value) = values.iter().find(|(name, _)| rest[1..end] == **name)?;
+            Some((end, value))
+        });
+
+        match placeholder {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Quote `text` as a JavaScript string that can't end its `<script>`.
+fn js_string(text: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '<' => out.push_str("\\u003c"),
+            c if c.is_control() || c == '\u{2028}' || c == '\u{2029}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
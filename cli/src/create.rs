@@ -0,0 +1,265 @@
+//! Rust projects that wrap a cart into a native executable, made by
+//! `wasmstation create`.
+
+use std::{fs, path::Path};
+
+use wasmstation::{core::meta::CartMeta, wasmer_backend::Compiler};
+
+use crate::{bundle, BackendType, RendererType};
+
+/// What goes into a project.
+pub struct Project<'a> {
+    /// The crate's name, also used for the cart and the save directory.
+    pub name: &'a str,
+    /// The cart, with its metadata already written to it.
+    pub cart: &'a [u8],
+    pub meta: &'a CartMeta,
+    pub backend: BackendType,
+    /// Precompiles the cart, with the wasmer backend.
+    pub compiler: Compiler,
+    pub renderer: RendererType,
+    /// Also build for the web with [trunk](https://trunkrs.dev).
+    pub web: bool,
+    pub display_scale: u32,
+    /// The wasmstation source the project depends on.
+    pub wasmstation: &'a Path,
+}
+
+/// How a project creates its backend.
+struct Engine {
+    /// What `main` imports from wasmstation for the backend, and its cargo
+    /// feature.
+    backend: &'static str,
+    feature: &'static str,
+    /// Creates the backend in `main`, from `MODULE` or `CART`.
+    load: String,
+    /// How `build.rs` compiles `CART`, for precompiled backends.
+    precompile: Option<Precompile>,
+}
+
+struct Precompile {
+    /// What `build.rs` imports from wasmstation, with the `feature` enabled.
+    imports: &'static str,
+    feature: &'static str,
+    /// The expression compiling `CART`.
+    expr: String,
+}
+
+impl Project<'_> {
+    /// Write the project to `dir`.
+    pub fn write(&self, dir: &Path) -> anyhow::Result<()> {
+        let engine = engine(self.backend, self.compiler)?;
+
+        fs::create_dir_all(dir.join("src"))?;
+        fs::write(
+            dir.join("Cargo.toml"),
+            include_str!("../../template/Cargo.toml")
+                .replace("{crate_name}", self.name)
+                .replace("{dependencies}", &self.dependencies(&engine)),
+        )?;
+        if let Some(precompile) = &engine.precompile {
+            fs::write(
+                dir.join("build.rs"),
+                include_str!("../../template/build.rs")
+                    .replace("{crate_name}", self.name)
+                    .replace("{engine}", precompile.imports)
+                    .replace("{precompile}", &precompile.expr),
+            )?;
+        }
+        fs::write(dir.join("src").join("main.rs"), self.main_rs(&engine))?;
+        if self.web {
+            let title = self.meta.title.as_deref().unwrap_or(self.name);
+            fs::write(
+                dir.join("index.html"),
+                include_str!("../../template/index.html")
+                    .replace("{title}", &bundle::escape(title)),
+            )?;
+        }
+        fs::write(dir.join(format!("{}.wasm", self.name)), self.cart)?;
+
+        Ok(())
+    }
+
+    fn dependencies(&self, engine: &Engine) -> String {
+        let path = toml_string(&self.wasmstation.to_string_lossy());
+        let renderer = match self.renderer {
+            RendererType::Sdl2 => "sdl2-renderer",
+            RendererType::Gpu => "gpu-renderer",
+            RendererType::Term => "term-renderer",
+        };
+
+        let mut dependencies = String::new();
+        if let Some(precompile) = &engine.precompile {
+            dependencies += &format!(
+                "\n[build-dependencies]\nwasmstation = {{ path = {path}, features = [\"{}\"] }}\n",
+                precompile.feature
+            );
+        }
+
+        let wasmstation = format!(
+            "wasmstation = {{ path = {path}, features = [\"{}\", \"{renderer}\"] }}\n",
+            engine.feature
+        );
+        if self.web {
+            let web = toml_string(&self.wasmstation.join("web").to_string_lossy());
+
+            dependencies += &format!(
+                "\n[target.'cfg(not(target_arch = \"wasm32\"))'.dependencies]\n{wasmstation}\n\
+                 [target.'cfg(target_arch = \"wasm32\")'.dependencies]\nwasmstation-web = {{ path = {web} }}\n"
+            );
+        } else {
+            dependencies += &format!("\n[dependencies]\n{wasmstation}");
+        }
+
+        dependencies
+    }
+
+    fn main_rs(&self, engine: &Engine) -> String {
+        // wasmstation is only a dependency of native builds, the web has its own runtime.
+        let native = if self.web {
+            "#[cfg(not(target_arch = \"wasm32\"))]\n"
+        } else {
+            ""
+        };
+        let (name, scale) = (self.name, self.display_scale);
+
+        let (renderer, launch) = match self.renderer {
+            RendererType::Sdl2 => (
+                "sdl2_renderer",
+                format!(
+                    "    sdl2_renderer::launch_desktop_with_save_file(backend, &save_file, &meta, {scale}).unwrap();"
+                ),
+            ),
+            RendererType::Gpu => (
+                "gpu_renderer",
+                format!(
+                    "    gpu_renderer::launch_desktop_with_save_file(backend, &save_file, &meta, {scale}).unwrap();"
+                ),
+            ),
+            RendererType::Term => (
+                "term_renderer",
+                "    let colors = term_renderer::ColorMode::detect();\n    \
+                 term_renderer::launch_with_save_file(backend, &save_file, &meta, colors).unwrap();"
+                    .to_string(),
+            ),
+        };
+
+        let mut main = format!(
+            "{native}use wasmstation::{{core::meta::CartMeta, dirs, {renderer}, Console, {}}};\n\n",
+            engine.backend
+        );
+
+        let meta = if engine.precompile.is_some() {
+            main += &format!(
+                "{native}const MODULE: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/cart.module\"));\n\
+                 {native}const META: &str = include_str!(concat!(env!(\"OUT_DIR\"), \"/cart.meta\"));\n"
+            );
+            "CartMeta::parse(META)"
+        } else {
+            "CartMeta::from_wasm(CART).unwrap()"
+        };
+        if engine.precompile.is_none() || self.web {
+            let web_only = if engine.precompile.is_some() {
+                "#[cfg(target_arch = \"wasm32\")]\n"
+            } else {
+                ""
+            };
+            main += &format!("{web_only}const CART: &[u8] = include_bytes!(\"../{name}.wasm\");\n");
+        }
+
+        main += &format!(
+            "\n{native}fn main() {{\n    let backend = {}.unwrap();\n    let meta = {meta};\n    \
+             let save_file = dirs::data_dir(\"{name}\").unwrap().join(\"{name}.disk\");\n\n{launch}\n}}\n",
+            engine.load
+        );
+
+        if self.web {
+            main += &format!(
+                "\n#[cfg(target_arch = \"wasm32\")]\nfn main() {{\n    \
+                 wasmstation_web::run(\"wasmstation\", CART, \"{name}\").unwrap();\n}}\n"
+            );
+        }
+
+        main
+    }
+}
+
+fn engine(backend: BackendType, compiler: Compiler) -> anyhow::Result<Engine> {
+    if compiler != Compiler::default() && !matches!(backend, BackendType::Wasmer) {
+        return Err(anyhow::anyhow!(
+            "the compiler can only be picked for the wasmer backend"
+        ));
+    }
+
+    match backend {
+        BackendType::Wasmer => Ok(match compiler {
+            Compiler::Cranelift => Engine {
+                backend: "WasmerBackend",
+                feature: "wasmer",
+                load: "WasmerBackend::precompiled(MODULE, &Console::default())".to_string(),
+                precompile: Some(Precompile {
+                    imports: "core::features::WasmFeatures, WasmerBackend",
+                    feature: "wasmer",
+                    expr: "WasmerBackend::precompile(CART, &WasmFeatures::default(), false)\n            \
+                           .unwrap()"
+                        .to_string(),
+                }),
+            },
+            Compiler::Singlepass | Compiler::Llvm => {
+                let (variant, feature) = match compiler {
+                    Compiler::Singlepass => ("Singlepass", "wasmer-singlepass"),
+                    _ => ("Llvm", "wasmer-llvm"),
+                };
+
+                // the module is only loaded if it was compiled with the same
+                // builder settings.
+                Engine {
+                    backend: "wasmer_backend::Compiler, WasmerBackend",
+                    feature: "wasmer",
+                    load: format!(
+                        "WasmerBackend::builder()\n        \
+                         .compiler(Compiler::{variant})\n        \
+                         .precompiled(MODULE, &Console::default())"
+                    ),
+                    precompile: Some(Precompile {
+                        imports: "core::features::WasmFeatures, wasmer_backend::Compiler, WasmerBackend",
+                        feature,
+                        expr: format!(
+                            "WasmerBackend::builder()\n            \
+                             .compiler(Compiler::{variant})\n            \
+                             .precompile(CART, &WasmFeatures::default(), false)\n            \
+                             .unwrap()"
+                        ),
+                    }),
+                }
+            }
+        }),
+        BackendType::Wasmtime => Ok(Engine {
+            backend: "WasmtimeBackend",
+            feature: "wasmtime",
+            load: "WasmtimeBackend::precompiled(MODULE, &Console::default())".to_string(),
+            precompile: Some(Precompile {
+                imports: "core::features::WasmFeatures, WasmtimeBackend",
+                feature: "wasmtime",
+                expr: "WasmtimeBackend::engine(&WasmFeatures::default())\n            \
+                       .unwrap()\n            \
+                       .precompile_module(CART)\n            \
+                       .unwrap()"
+                    .to_string(),
+            }),
+        }),
+        BackendType::Wasmi => Ok(Engine {
+            backend: "WasmiBackend",
+            feature: "wasmi",
+            load: "WasmiBackend::from_bytes(CART, &Console::default())".to_string(),
+            precompile: None,
+        }),
+        BackendType::Wasm3 => Err(anyhow::anyhow!(
+            "projects can use the wasmer, wasmtime or wasmi backend"
+        )),
+    }
+}
+
+fn toml_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,386 @@
+//! A terminal inspector for cart memory, registers and host calls.
+//!
+//! Commands are read line by line from stdin, type `h` for a list.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write as _},
+    mem,
+    ops::Range,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use wasmstation::{
+    core::{
+        utils,
+        wasm4::{self, HostCall, HostCallCounts},
+        Disk,
+    },
+    Backend,
+};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const HEX_ROWS: usize = 16;
+const HEX_COLUMNS: usize = 16;
+
+const HELP: &str = "commands: m <addr> (memory view), d [offset] (disk view), \
+                    w <addr> [len] (watch), u <addr> (unwatch), p (pause), c (continue), s (step)";
+
+/// A [`Backend`] that draws a live view of the cart's state to the terminal.
+///
+/// Watchpoints pause the cart as soon as the watched bytes differ
+/// between two frames.
+pub struct Devtools<B> {
+    backend: B,
+    memory: Vec<u8>,
+    previous: Vec<u8>,
+    host_calls: HostCallCounts,
+    disk: Option<Disk>,
+    watchpoints: Vec<Range<usize>>,
+    view: View,
+    paused: bool,
+    step: bool,
+    frame: u64,
+    status: String,
+    commands: Receiver<String>,
+    last_draw: Instant,
+    redraw: bool,
+}
+
+#[derive(Clone, Copy)]
+enum View {
+    Memory(usize),
+    Disk(usize),
+}
+
+impl<B: Backend> Devtools<B> {
+    pub fn new(backend: B) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in io::stdin().lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut memory = vec![0; wasm4::MEMORY_SIZE];
+        backend.read_memory(0, &mut memory);
+
+        Self {
+            backend,
+            previous: memory.clone(),
+            memory,
+            host_calls: [0; 17],
+            disk: None,
+            watchpoints: Vec::new(),
+            view: View::Memory(wasm4::FRAMEBUFFER_ADDR),
+            paused: false,
+            step: false,
+            frame: 0,
+            status: HELP.to_string(),
+            commands: rx,
+            last_draw: Instant::now(),
+            redraw: true,
+        }
+    }
+
+    fn handle_commands(&mut self) {
+        while let Ok(line) = self.commands.try_recv() {
+            let mut words = line.split_whitespace();
+            let (command, arg1, arg2) = (words.next(), words.next(), words.next());
+
+            self.status = match (command, arg1.and_then(parse_number)) {
+                (Some("m"), Some(addr)) if addr < wasm4::MEMORY_SIZE => {
+                    self.view = View::Memory(addr & !(HEX_COLUMNS - 1));
+                    format!("viewing memory at {addr:#06x}")
+                }
+                (Some("d"), offset) => {
+                    self.view = View::Disk(offset.unwrap_or(0).min(1023) & !(HEX_COLUMNS - 1));
+                    "viewing disk".to_string()
+                }
+                (Some("w"), Some(addr)) if addr < wasm4::MEMORY_SIZE => {
+                    let len = arg2.and_then(parse_number).unwrap_or(1).max(1);
+                    let range = addr..addr.saturating_add(len).min(wasm4::MEMORY_SIZE);
+                    let len = range.len();
+                    self.watchpoints.push(range);
+                    format!("watching {len} byte(s) at {addr:#06x}")
+                }
+                (Some("u"), Some(addr)) => {
+                    self.watchpoints.retain(|range| range.start != addr);
+                    format!("removed watchpoint at {addr:#06x}")
+                }
+                (Some("p"), _) => {
+                    self.paused = true;
+                    "paused".to_string()
+                }
+                (Some("c"), _) => {
+                    self.paused = false;
+                    "running".to_string()
+                }
+                (Some("s"), _) => {
+                    self.paused = true;
+                    self.step = true;
+                    "stepped one frame".to_string()
+                }
+                _ => HELP.to_string(),
+            };
+
+            self.redraw = true;
+        }
+    }
+
+    /// Snapshot memory after a frame and check the watchpoints.
+    fn inspect(&mut self) {
+        self.frame += 1;
+        self.host_calls = self.backend.take_host_calls();
+
+        mem::swap(&mut self.previous, &mut self.memory);
+        self.backend.read_memory(0, &mut self.memory);
+
+        for range in &self.watchpoints {
+            let (old, new) = (&self.previous[range.clone()], &self.memory[range.clone()]);
+
+            if old != new {
+                self.paused = true;
+                self.redraw = true;
+                self.status = format!(
+                    "watchpoint {:#06x} changed in frame {}: {} -> {}",
+                    range.start,
+                    self.frame,
+                    utils::to_hex(old),
+                    utils::to_hex(new)
+                );
+            }
+        }
+    }
+
+    fn draw(&mut self) {
+        if !mem::take(&mut self.redraw) && self.last_draw.elapsed() < REDRAW_INTERVAL {
+            return;
+        }
+        self.last_draw = Instant::now();
+
+        let mut out = String::from("\x1b[H\x1b[2J");
+        let mem = &self.memory;
+        let state = if self.paused { "paused" } else { "running" };
+
+        let _ = writeln!(
+            out,
+            "wasmstation devtools - frame {} ({state})\n",
+            self.frame
+        );
+
+        let _ = write!(out, "PALETTE       ");
+        for color in mem[wasm4::PALETTE_ADDR..wasm4::PALETTE_ADDR + 16].chunks_exact(4) {
+            let (r, g, b) = (color[2], color[1], color[0]);
+            let _ = write!(
+                out,
+                "\x1b[48;2;{r};{g};{b}m    \x1b[0m #{r:02x}{g:02x}{b:02x} "
+            );
+        }
+
+        let draw_colors = u16::from_le_bytes([
+            mem[wasm4::DRAW_COLORS_ADDR],
+            mem[wasm4::DRAW_COLORS_ADDR + 1],
+        ]);
+        let _ = write!(out, "\nDRAW_COLORS   {draw_colors:#06x} ");
+        for n in 0..4 {
+            let _ = write!(out, " {}:{}", n + 1, (draw_colors >> (n * 4)) & 0xf);
+        }
+
+        let _ = write!(out, "\nGAMEPADS     ");
+        for (n, addr) in (wasm4::GAMEPAD1_ADDR..=wasm4::GAMEPAD4_ADDR).enumerate() {
+            let _ = write!(out, " {}:[{}]", n + 1, gamepad(mem[addr]));
+        }
+
+        let mouse_x = i16::from_le_bytes([mem[wasm4::MOUSE_X_ADDR], mem[wasm4::MOUSE_X_ADDR + 1]]);
+        let mouse_y = i16::from_le_bytes([mem[wasm4::MOUSE_Y_ADDR], mem[wasm4::MOUSE_Y_ADDR + 1]]);
+        let buttons = mem[wasm4::MOUSE_BUTTONS_ADDR];
+        let _ = write!(
+            out,
+            "\nMOUSE         x:{mouse_x} y:{mouse_y} buttons:[{}{}{}]",
+            flag(buttons, wasm4::MOUSE_LEFT, 'L'),
+            flag(buttons, wasm4::MOUSE_MIDDLE, 'M'),
+            flag(buttons, wasm4::MOUSE_RIGHT, 'R'),
+        );
+
+        let flags = mem[wasm4::SYSTEM_FLAGS_ADDR];
+        let _ = write!(out, "\nSYSTEM_FLAGS  {flags:#04x}");
+        if flags & wasm4::SYSTEM_PRESERVE_FRAMEBUFFER != 0 {
+            out.push_str(" PRESERVE_FRAMEBUFFER");
+        }
+        if flags & wasm4::SYSTEM_HIDE_GAMEPAD_OVERLAY != 0 {
+            out.push_str(" HIDE_GAMEPAD_OVERLAY");
+        }
+
+        let netplay = mem[wasm4::NETPLAY_ADDR];
+        let _ = writeln!(
+            out,
+            "\nNETPLAY       {netplay:#04x} player:{} active:{}\n",
+            (netplay & 0b11) + 1,
+            netplay & 0b100 != 0
+        );
+
+        let _ = write!(out, "HOST CALLS   ");
+        for call in HostCall::ALL {
+            let count = self.host_calls[call as usize];
+            if count > 0 {
+                let _ = write!(out, " {}:{count}", call.name());
+            }
+        }
+        out.push_str("\nWATCHPOINTS  ");
+        for range in &self.watchpoints {
+            let _ = write!(out, " {:#06x}+{}", range.start, range.len());
+        }
+        out.push_str("\n\n");
+
+        match self.view {
+            View::Memory(offset) => {
+                let _ = writeln!(out, "MEMORY");
+                hex_dump(&mut out, mem, Some(&self.previous), offset);
+            }
+            View::Disk(offset) => {
+                let _ = writeln!(out, "DISK");
+                let disk = self.disk.unwrap_or_default();
+                hex_dump(&mut out, disk.as_bytes(), None, offset);
+            }
+        }
+
+        let _ = write!(out, "\n{}\n> ", self.status);
+
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+impl<B: Backend> Backend for Devtools<B> {
+    fn call_update(&mut self) {
+        self.handle_commands();
+
+        if !self.paused || mem::take(&mut self.step) {
+            self.backend.call_update();
+            self.inspect();
+        }
+
+        self.draw();
+    }
+
+    fn call_start(&mut self) {
+        self.backend.call_start();
+    }
+
+    fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
+        self.backend.read_screen(framebuffer, palette);
+    }
+
+    fn read_system_flags(&self) -> u8 {
+        self.backend.read_system_flags()
+    }
+
+    fn set_gamepad(&mut self, gamepad: u32) {
+        self.backend.set_gamepad(gamepad);
+    }
+
+    fn set_mouse(&mut self, x: i16, y: i16, buttons: u8) {
+        self.backend.set_mouse(x, y, buttons);
+    }
+
+    fn write_save_cache(&mut self) -> Option<Disk> {
+        let disk = self.backend.write_save_cache();
+        if disk.is_some() {
+            self.disk = disk;
+        }
+
+        disk
+    }
+
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.disk = Some(disk);
+        self.backend.set_save_cache(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        self.backend.reset()?;
+
+        // the reset itself isn't a change for the watchpoints.
+        self.backend.read_memory(0, &mut self.memory);
+        self.previous.copy_from_slice(&self.memory);
+        self.redraw = true;
+
+        Ok(())
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        self.backend.read_memory(offset, buf);
+    }
+
+    fn take_host_calls(&mut self) -> HostCallCounts {
+        mem::take(&mut self.host_calls)
+    }
+}
+
+fn hex_dump(out: &mut String, data: &[u8], previous: Option<&[u8]>, offset: usize) {
+    let rows = data
+        .get(offset..)
+        .unwrap_or_default()
+        .chunks(HEX_COLUMNS)
+        .take(HEX_ROWS);
+
+    for (n, row) in rows.enumerate() {
+        let addr = offset + n * HEX_COLUMNS;
+        let _ = write!(out, "{addr:04x}  ");
+
+        for (i, byte) in row.iter().enumerate() {
+            // highlight bytes that changed during the last frame
+            match previous {
+                Some(previous) if previous[addr + i] != *byte => {
+                    let _ = write!(out, "\x1b[7m{byte:02x}\x1b[0m ");
+                }
+                _ => {
+                    let _ = write!(out, "{byte:02x} ");
+                }
+            }
+        }
+
+        let ascii: String = row
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect();
+        let _ = writeln!(out, " {ascii}");
+    }
+}
+
+fn gamepad(state: u8) -> String {
+    [
+        (wasm4::BUTTON_1, 'X'),
+        (wasm4::BUTTON_2, 'Z'),
+        (wasm4::BUTTON_LEFT, '<'),
+        (wasm4::BUTTON_RIGHT, '>'),
+        (wasm4::BUTTON_UP, '^'),
+        (wasm4::BUTTON_DOWN, 'v'),
+    ]
+    .into_iter()
+    .map(|(mask, c)| flag(state, mask, c))
+    .collect()
+}
+
+fn flag(state: u8, mask: u8, c: char) -> char {
+    if state & mask != 0 {
+        c
+    } else {
+        '-'
+    }
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal number.
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -1,7 +1,27 @@
-use std::{env, ffi::OsStr, fs, path::PathBuf, process, str::FromStr};
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
 
 use argh::FromArgs;
-use wasmstation::{gpu_renderer, sdl2_renderer, Console, WasmerBackend, WasmiBackend};
+use wasmstation::{
+    core::meta::{CartMeta, Icon},
+    gpu_renderer, sdl2_renderer, term_renderer,
+    wasmer_backend::{Compiler, ModuleCache},
+    Backend, Console, Wasm3Backend, WasmerBackend, WasmiBackend, WasmtimeBackend,
+};
+
+mod bundle;
+mod create;
+mod devtools;
+mod watch;
+
+use devtools::Devtools;
+use watch::Watched;
 
 #[derive(FromArgs)]
 #[argh(description = "Run wasm4 compatible games.")]
@@ -14,7 +34,11 @@ struct Args {
 #[argh(subcommand)]
 enum Subcommand {
     Run(Run),
+    Launch(Launch),
     Create(Create),
+    Bundle(Bundle),
+    Meta(Meta),
+    Cache(Cache),
 }
 
 fn main() {
@@ -24,7 +48,11 @@ fn main() {
 
     if let Err(err) = match args.subcommand {
         Subcommand::Run(args) => run(args),
+        Subcommand::Launch(args) => launch_library(args),
         Subcommand::Create(args) => create(args),
+        Subcommand::Bundle(args) => bundle(args),
+        Subcommand::Meta(args) => meta(args),
+        Subcommand::Cache(args) => cache(args),
     } {
         log::error!("Runtime Error: {err}");
         process::exit(1);
@@ -46,37 +74,132 @@ struct Run {
     /// renderer used for the window
     #[argh(option, short = 'r', default = "RendererType::default()")]
     renderer: RendererType,
+    /// reload the cart whenever the file changes on disk
+    #[argh(switch, short = 'w')]
+    watch: bool,
+    /// show a live inspector for memory, registers and host calls in the terminal
+    #[argh(switch, short = 'd')]
+    devtools: bool,
+    /// always compile the cart, instead of using and filling the module cache
+    #[argh(switch)]
+    no_cache: bool,
+    /// compiler of the wasmer backend: 'cranelift', 'singlepass' (quickest to start) or 'llvm'
+    #[argh(option, default = "Compiler::default()")]
+    compiler: Compiler,
 }
 
 fn run(args: Run) -> anyhow::Result<()> {
-    let wasm_bytes = fs::read(&args.path)?;
+    // both would take over the terminal, and devtools' prompt would fight the
+    // renderer over the keyboard.
+    if args.devtools && matches!(args.renderer, RendererType::Term) {
+        return Err(anyhow::anyhow!(
+            "--devtools can't be used with the term renderer, pick another one with -r"
+        ));
+    }
+
     let console = Console::default();
+    let builder = WasmerBackend::builder().compiler(args.compiler);
+
+    match args.backend {
+        BackendType::Wasmer if !args.no_cache => {
+            let cache = ModuleCache::user()?.with_builder(builder);
+            launch_cart(&args, move |bytes| cache.load(bytes, &console))
+        }
+        BackendType::Wasmer => launch_cart(&args, move |bytes| builder.build(bytes, &console)),
+        BackendType::Wasmi => launch_cart(&args, move |bytes| {
+            WasmiBackend::from_bytes(bytes, &console)
+        }),
+        BackendType::Wasmtime if args.path.extension() == Some(OsStr::new("cwasm")) => {
+            launch_cart(&args, move |bytes| {
+                WasmtimeBackend::precompiled(bytes, &console)
+            })
+        }
+        BackendType::Wasmtime => launch_cart(&args, move |bytes| {
+            WasmtimeBackend::from_bytes(bytes, &console)
+        }),
+        BackendType::Wasm3 => launch_cart(&args, move |bytes| {
+            Wasm3Backend::from_bytes(bytes, &console)
+        }),
+    }
+}
+
+fn launch_cart<B, F>(args: &Run, load: F) -> anyhow::Result<()>
+where
+    B: Backend + 'static,
+    F: Fn(&[u8]) -> anyhow::Result<B> + 'static,
+{
+    if args.watch {
+        launch(Watched::new(&args.path, load)?, args)
+    } else {
+        launch(load(&fs::read(&args.path)?)?, args)
+    }
+}
+
+fn launch(backend: impl Backend + 'static, args: &Run) -> anyhow::Result<()> {
+    if args.devtools {
+        render(Devtools::new(backend), args)
+    } else {
+        render(backend, args)
+    }
+}
+
+fn render(backend: impl Backend + 'static, args: &Run) -> anyhow::Result<()> {
+    // precompiled carts have no metadata.
+    let meta = CartMeta::read(&args.path).unwrap_or_default();
 
     match args.renderer {
-        RendererType::Sdl2 => match args.backend {
-            BackendType::Wasmer => sdl2_renderer::launch_desktop(
-                WasmerBackend::from_bytes(&wasm_bytes, &console)?,
-                &args.path,
-                args.display_scale,
-            ),
-            BackendType::Wasmi => sdl2_renderer::launch_desktop(
-                WasmiBackend::from_bytes(&wasm_bytes, &console)?,
-                &args.path,
-                args.display_scale,
-            ),
-        },
-        RendererType::Gpu => match args.backend {
-            BackendType::Wasmer => gpu_renderer::launch_desktop(
-                WasmerBackend::from_bytes(&wasm_bytes, &console)?,
-                "Wasmstation CLI",
-                args.display_scale,
-            ),
-            BackendType::Wasmi => gpu_renderer::launch_desktop(
-                WasmiBackend::from_bytes(&wasm_bytes, &console)?,
-                "Wasmstation CLI",
-                args.display_scale,
-            ),
-        },
+        RendererType::Sdl2 => {
+            sdl2_renderer::launch_desktop(backend, &args.path, &meta, args.display_scale)
+        }
+        RendererType::Gpu => {
+            let meta = CartMeta {
+                title: Some(meta.title.unwrap_or_else(|| file_stem(&args.path))),
+                ..meta
+            };
+            gpu_renderer::launch_desktop(backend, &args.path, &meta, args.display_scale)
+        }
+        RendererType::Term => term_renderer::launch(
+            backend,
+            &args.path,
+            &meta,
+            term_renderer::ColorMode::detect(),
+        ),
+    }
+}
+
+/// Browse a directory of WASM-4 games and play them.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "launch")]
+struct Launch {
+    #[argh(positional)]
+    dir: PathBuf,
+    /// default scale factor for the window
+    #[argh(option, short = 's', default = "3")]
+    display_scale: u32,
+    /// webassembly backend used for executing the carts
+    #[argh(option, short = 'b', default = "BackendType::default()")]
+    backend: BackendType,
+    /// system cart to use as the menu instead of the built-in grid
+    #[argh(option, short = 'm')]
+    menu: Option<PathBuf>,
+}
+
+fn launch_library(args: Launch) -> anyhow::Result<()> {
+    let (dir, scale, menu) = (&args.dir, args.display_scale, args.menu.as_deref());
+
+    match args.backend {
+        BackendType::Wasmer => {
+            sdl2_renderer::launch_library(dir, scale, menu, WasmerBackend::from_bytes)
+        }
+        BackendType::Wasmi => {
+            sdl2_renderer::launch_library(dir, scale, menu, WasmiBackend::from_bytes)
+        }
+        BackendType::Wasmtime => {
+            sdl2_renderer::launch_library(dir, scale, menu, WasmtimeBackend::from_bytes)
+        }
+        BackendType::Wasm3 => {
+            sdl2_renderer::launch_library(dir, scale, menu, Wasm3Backend::from_bytes)
+        }
     }
 }
 
@@ -85,6 +208,8 @@ enum BackendType {
     #[default]
     Wasmer,
     Wasmi,
+    Wasmtime,
+    Wasm3,
 }
 
 impl FromStr for BackendType {
@@ -94,7 +219,9 @@ impl FromStr for BackendType {
         match s.to_lowercase().as_str() {
             "wasmi" => Ok(Self::Wasmi),
             "wasmer" => Ok(Self::Wasmer),
-            _ => Err("backend type must be 'wasmi' or 'wasmer'".to_string()),
+            "wasmtime" => Ok(Self::Wasmtime),
+            "wasm3" => Ok(Self::Wasm3),
+            _ => Err("backend type must be 'wasmi', 'wasmer', 'wasmtime' or 'wasm3'".to_string()),
         }
     }
 }
@@ -104,6 +231,7 @@ enum RendererType {
     #[default]
     Sdl2,
     Gpu,
+    Term,
 }
 
 impl FromStr for RendererType {
@@ -115,12 +243,17 @@ impl FromStr for RendererType {
             "sdl" => Ok(Self::Sdl2),
             "gpu" => Ok(Self::Gpu),
             "pixels" => Ok(Self::Gpu),
-            _ => Err("backend type must be 'wasmi' or 'wasmer'".to_string()),
+            "term" => Ok(Self::Term),
+            "terminal" => Ok(Self::Term),
+            _ => Err("renderer type must be 'sdl2', 'gpu' or 'term'".to_string()),
         }
     }
 }
 
 /// Create a Rust project for wrapping a WASM-4 game into a native executable in the current directory.
+///
+/// Saves go to the user's data directory with the sdl2 and term renderers,
+/// and to localStorage on the web. The gpu renderer doesn't keep saves.
 #[derive(FromArgs)]
 #[argh(subcommand, name = "create")]
 struct Create {
@@ -130,37 +263,287 @@ struct Create {
     /// default scale factor of the window
     #[argh(option, short = 's', default = "3")]
     display_scale: u32,
+    /// webassembly backend of the executable: 'wasmer' or 'wasmtime' (both precompiled) or 'wasmi'
+    #[argh(option, short = 'b', default = "BackendType::default()")]
+    backend: BackendType,
+    /// compiler precompiling the cart with the wasmer backend: 'cranelift', 'singlepass' or 'llvm'
+    #[argh(option, default = "Compiler::default()")]
+    compiler: Compiler,
+    /// renderer used for the window
+    #[argh(option, short = 'r', default = "RendererType::default()")]
+    renderer: RendererType,
+    /// also build for the web with trunk, using an index.html
+    #[argh(switch)]
+    web: bool,
+    /// title of the window, the cart's title by default
+    #[argh(option)]
+    title: Option<String>,
+    /// icon of the window, a sprite file in the format `blit` takes
+    #[argh(option)]
+    icon: Option<PathBuf>,
+    /// size of the icon, like 16x16, square by default
+    #[argh(option, from_str_fn(parse_size))]
+    icon_size: Option<(u32, u32)>,
+    /// the icon uses 2 bits per pixel instead of 1
+    #[argh(switch)]
+    icon_2bpp: bool,
+    /// path to the wasmstation source, the one this CLI was built from by default
+    #[argh(option)]
+    wasmstation: Option<PathBuf>,
 }
 
 fn create(args: Create) -> anyhow::Result<()> {
-    // args.cart is guaranteed to be a file ideally so this shouldn't panic.
-    let name: String = args
-        .cart
-        .file_stem()
-        .map(|stem| stem.to_string_lossy().to_string())
-        .ok_or(anyhow::anyhow!("wasm name must have a file name"))?;
+    let cart = fs::read(&args.cart)?;
+    let mut meta = CartMeta::from_wasm(&cart)?;
+
+    if args.title.is_some() {
+        meta.title = args.title;
+    }
+    if let Some(icon) = &args.icon {
+        meta.icon = Some(read_icon(icon, args.icon_size, args.icon_2bpp)?);
+    }
+    let cart = meta.write_to_wasm(&cart)?;
 
+    let name = meta.slug().unwrap_or_else(|| file_stem(&args.cart));
     let base_dir = env::current_dir()?.join(&name);
 
-    let cargo_toml = include_str!("../../template/Cargo.toml").replace("{crate_name}", &name);
-    let build_rs = include_str!("../../template/build.rs").replace("{cart_name}", &name);
-    let main_rs = include_str!("../../template/main.rs")
-        .replace("{window_scale}", &args.display_scale.to_string());
+    let wasmstation = match args.wasmstation {
+        Some(path) => path,
+        None => {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+            fs::canonicalize(&path).unwrap_or(path)
+        }
+    };
 
-    fs::create_dir_all(base_dir.join("src"))?;
-    fs::write(base_dir.join("Cargo.toml"), cargo_toml)?;
-    fs::write(base_dir.join("build.rs"), build_rs)?;
-    fs::write(base_dir.join("src").join("main.rs"), main_rs)?;
-    fs::copy(&args.cart, base_dir.join(format!("{name}.wasm")))?;
+    create::Project {
+        name: &name,
+        cart: &cart,
+        meta: &meta,
+        backend: args.backend,
+        compiler: args.compiler,
+        renderer: args.renderer,
+        web: args.web,
+        display_scale: args.display_scale,
+        wasmstation: &wasmstation,
+    }
+    .write(&base_dir)?;
 
     println!(
         "Created your wasmstation project at {}\n\nBuild the cart by running:\n    $ cd {name}\n    $ cargo build --release",
         base_dir.to_string_lossy()
     );
+    if args.web {
+        println!("\nOr for the web, with trunk:\n    $ trunk build --release index.html");
+    }
 
     Ok(())
 }
 
+/// Make a single HTML file that plays a WASM-4 game in a browser, with saves kept in localStorage.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bundle")]
+struct Bundle {
+    /// path leading to the game's cartridge file (e.g. /path/to/cart.wasm)
+    #[argh(positional, from_str_fn(validate_wasm_path))]
+    cart: PathBuf,
+    /// where to write the page, next to the cart by default
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+fn bundle(args: Bundle) -> anyhow::Result<()> {
+    let cart = fs::read(&args.cart)?;
+    let meta = CartMeta::from_wasm(&cart)?;
+    let name = meta.slug().unwrap_or_else(|| file_stem(&args.cart));
+
+    let output = args
+        .output
+        .unwrap_or_else(|| args.cart.with_extension("html"));
+    fs::write(&output, bundle::html(&cart, &meta, &name)?)?;
+
+    println!("Bundled {name} into {}", output.to_string_lossy());
+
+    Ok(())
+}
+
+/// Show or edit the title, author, description, version and icon of a cart.
+///
+/// Carts exported with `w4 bundle --html` can be shown but not edited.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "meta")]
+struct Meta {
+    /// path leading to the game's cartridge file
+    #[argh(positional)]
+    cart: PathBuf,
+    /// set the title
+    #[argh(option)]
+    title: Option<String>,
+    /// set the author
+    #[argh(option)]
+    author: Option<String>,
+    /// set the description
+    #[argh(option)]
+    description: Option<String>,
+    /// set the version
+    #[argh(option)]
+    version: Option<String>,
+    /// set the icon to a sprite file, in the format `blit` takes
+    #[argh(option)]
+    icon: Option<PathBuf>,
+    /// size of the icon, like 16x16, square by default
+    #[argh(option, from_str_fn(parse_size))]
+    icon_size: Option<(u32, u32)>,
+    /// the icon uses 2 bits per pixel instead of 1
+    #[argh(switch)]
+    icon_2bpp: bool,
+    /// write the edited cart to this file instead of over the cart
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+fn meta(args: Meta) -> anyhow::Result<()> {
+    let mut meta = CartMeta::read(&args.cart)?;
+
+    let edits = [
+        (&mut meta.title, args.title),
+        (&mut meta.author, args.author),
+        (&mut meta.description, args.description),
+        (&mut meta.version, args.version),
+    ];
+    let mut edited = args.icon.is_some();
+    for (field, value) in edits {
+        if value.is_some() {
+            *field = value;
+            edited = true;
+        }
+    }
+
+    if let Some(icon) = &args.icon {
+        meta.icon = Some(read_icon(icon, args.icon_size, args.icon_2bpp)?);
+    }
+
+    if edited {
+        if args.cart.extension() == Some(OsStr::new("html")) {
+            return Err(anyhow::anyhow!(
+                "bundles can't be edited, edit the .wasm cart and bundle it again"
+            ));
+        }
+
+        let wasm = meta.write_to_wasm(&fs::read(&args.cart)?)?;
+        fs::write(args.output.as_ref().unwrap_or(&args.cart), wasm)?;
+    }
+
+    print_meta(&meta);
+
+    Ok(())
+}
+
+/// Read an icon from a sprite file, square unless it has a `size`.
+fn read_icon(path: &Path, size: Option<(u32, u32)>, two_bpp: bool) -> anyhow::Result<Icon> {
+    let sprite = fs::read(path)?;
+    let bpp = if two_bpp { 2 } else { 1 };
+    let (width, height) = match size {
+        Some(size) => size,
+        None => {
+            let side = ((sprite.len() * 8 / bpp) as f64).sqrt() as u32;
+            (side, side)
+        }
+    };
+
+    if u64::from(width) * u64::from(height) * bpp as u64 > sprite.len() as u64 * 8 {
+        return Err(anyhow::anyhow!(
+            "{} is too small for a {width}x{height} icon",
+            path.display()
+        ));
+    }
+
+    Ok(Icon {
+        width,
+        height,
+        flags: u32::from(two_bpp),
+        sprite,
+    })
+}
+
+fn print_meta(meta: &CartMeta) {
+    let fields = [
+        ("title", &meta.title),
+        ("author", &meta.author),
+        ("description", &meta.description),
+        ("version", &meta.version),
+    ];
+
+    for (key, value) in fields {
+        println!("{key:12}{}", value.as_deref().unwrap_or("-"));
+    }
+
+    let Some(icon) = &meta.icon else {
+        println!("{:12}-", "icon");
+        return;
+    };
+
+    println!(
+        "{:12}{}x{}, {}bpp",
+        "icon",
+        icon.width,
+        icon.height,
+        icon.bpp()
+    );
+    for y in 0..icon.height {
+        let row: String = (0..icon.width)
+            .map(|x| match icon.pixel(x, y) {
+                Some(1) => "██",
+                Some(2) => "▒▒",
+                Some(3) => "░░",
+                _ => "  ",
+            })
+            .collect();
+
+        println!("{:12}{row}", "");
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let error = || "size must look like 16x16".to_string();
+    let (width, height) = size.split_once('x').ok_or_else(error)?;
+
+    Ok((
+        width.parse().map_err(|_| error())?,
+        height.parse().map_err(|_| error())?,
+    ))
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "wasmstation".to_string())
+}
+
+/// Manage the cache of compiled carts used by `run` with the wasmer backend.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "cache")]
+struct Cache {
+    #[argh(subcommand)]
+    command: CacheCommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum CacheCommand {
+    Clear(CacheClear),
+}
+
+/// Remove every compiled cart from the cache.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "clear")]
+struct CacheClear {}
+
+fn cache(args: Cache) -> anyhow::Result<()> {
+    match args.command {
+        CacheCommand::Clear(CacheClear {}) => ModuleCache::user()?.clear(),
+    }
+}
+
 fn validate_wasm_path(path: &str) -> Result<PathBuf, String> {
     let path = PathBuf::from_str(path).map_err(|err| err.to_string())?;
 
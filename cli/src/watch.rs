@@ -0,0 +1,159 @@
+//! Hot-reloading of carts that change on disk.
+
+use std::{
+    fs, mem,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use wasmstation::{
+    core::{
+        wasm4::{self, HostCallCounts},
+        Disk,
+    },
+    Backend,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A [`Backend`] that reloads its cart whenever the `.wasm` file changes.
+///
+/// The disk contents are carried over to the reloaded cart.
+pub struct Watched<B, F> {
+    backend: B,
+    load: F,
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    last_poll: Instant,
+    /// The cart's disk, as of the last flush.
+    disk: Disk,
+    /// Whether `disk` was flushed out of the backend, but not yet handed out
+    /// by [`Backend::write_save_cache`] to be saved.
+    unsaved: bool,
+}
+
+impl<B, F> Watched<B, F>
+where
+    B: Backend,
+    F: Fn(&[u8]) -> anyhow::Result<B>,
+{
+    pub fn new(path: &Path, load: F) -> anyhow::Result<Self> {
+        let stamp = stamp(path);
+        let backend = load(&fs::read(path)?)?;
+
+        Ok(Self {
+            backend,
+            load,
+            path: path.to_path_buf(),
+            stamp,
+            last_poll: Instant::now(),
+            disk: Disk::default(),
+            unsaved: false,
+        })
+    }
+
+    /// Take whatever the cart wrote to its disk out of the backend, before
+    /// the backend is replaced or reset.
+    fn flush(&mut self) {
+        if let Some(disk) = self.backend.write_save_cache() {
+            self.disk = disk;
+            self.unsaved = true;
+        }
+    }
+
+    fn poll(&mut self) {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return;
+        }
+        self.last_poll = Instant::now();
+
+        let stamp = stamp(&self.path);
+        if stamp.is_none() || stamp == self.stamp {
+            return;
+        }
+        self.stamp = stamp;
+        self.flush();
+
+        // the file may still be in the middle of being written, in which case
+        // loading fails and we try again after the next modification.
+        let reloaded = fs::read(&self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| (self.load)(&bytes));
+
+        match reloaded {
+            Ok(mut backend) => {
+                backend.set_save_cache(self.disk);
+                backend.call_start();
+                self.backend = backend;
+
+                log::info!("reloaded {}", self.path.display());
+            }
+            Err(err) => log::error!("failed to reload {}: {err}", self.path.display()),
+        }
+    }
+}
+
+impl<B, F> Backend for Watched<B, F>
+where
+    B: Backend,
+    F: Fn(&[u8]) -> anyhow::Result<B>,
+{
+    fn call_update(&mut self) {
+        self.poll();
+        self.backend.call_update();
+    }
+
+    fn call_start(&mut self) {
+        self.backend.call_start();
+    }
+
+    fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
+        self.backend.read_screen(framebuffer, palette);
+    }
+
+    fn read_system_flags(&self) -> u8 {
+        self.backend.read_system_flags()
+    }
+
+    fn set_gamepad(&mut self, gamepad: u32) {
+        self.backend.set_gamepad(gamepad);
+    }
+
+    fn set_mouse(&mut self, x: i16, y: i16, buttons: u8) {
+        self.backend.set_mouse(x, y, buttons);
+    }
+
+    fn write_save_cache(&mut self) -> Option<Disk> {
+        self.flush();
+
+        mem::take(&mut self.unsaved).then_some(self.disk)
+    }
+
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.disk = disk;
+        self.backend.set_save_cache(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        self.flush();
+        self.backend.reset()?;
+        self.backend.set_save_cache(self.disk);
+
+        Ok(())
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        self.backend.read_memory(offset, buf);
+    }
+
+    fn take_host_calls(&mut self) -> HostCallCounts {
+        self.backend.take_host_calls()
+    }
+}
+
+/// Modification time and size of a file, used to detect changes.
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
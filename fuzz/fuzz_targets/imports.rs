@@ -0,0 +1,130 @@
+//! Call the WASM-4 imports with arbitrary arguments and memory contents.
+//!
+//! Run with `cargo +nightly fuzz run imports` from this directory.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use wasmstation::{
+    core::{host, host::GuestMemory, wasm4},
+    Console, Sink, Source,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// Copied into memory at `offset`.
+    memory: Vec<u8>,
+    offset: u16,
+    /// Run the calls without slice access to memory, like the wasmer backend.
+    view: bool,
+    calls: Vec<Call>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Call {
+    BeforeUpdate,
+    Trace(u32),
+    Tracef(u32, u32),
+    TraceUtf8(u32, u32),
+    TraceUtf16(u32, u32),
+    Blit(u32, i32, i32, u32, u32, u32),
+    BlitSub(u32, i32, i32, u32, u32, u32, u32, u32, u32),
+    Line(i32, i32, i32, i32),
+    HLine(i32, i32, u32),
+    VLine(i32, i32, u32),
+    Oval(i32, i32, u32, u32),
+    Rect(i32, i32, u32, u32),
+    Text(u32, i32, i32),
+    TextUtf8(u32, u32, i32, i32),
+    TextUtf16(u32, u32, i32, i32),
+    DiskR(u32, u32),
+    DiskW(u32, u32),
+    Tone(u32, u32, u32, u32),
+}
+
+fuzz_target!(|input: Input| {
+    let mut memory = vec![0; wasm4::MEMORY_SIZE];
+    let offset = input.offset as usize;
+    let len = input.memory.len().min(memory.len() - offset);
+    memory[offset..offset + len].copy_from_slice(&input.memory[..len]);
+
+    if input.view {
+        run(&mut View(memory), &input.calls);
+    } else {
+        run(&mut memory[..], &input.calls);
+    }
+});
+
+fn run<M: GuestMemory + ?Sized>(mem: &mut M, calls: &[Call]) {
+    let console = Console::new(Box::new(|_| ()));
+    let api = console.create_api();
+
+    for call in calls {
+        match *call {
+            Call::BeforeUpdate => host::before_update(mem),
+            Call::Trace(ptr) => host::trace(mem, &api, ptr),
+            Call::Tracef(fmt, args) => host::tracef(mem, &api, fmt, args),
+            Call::TraceUtf8(ptr, len) => host::trace_utf8(mem, &api, ptr, len),
+            Call::TraceUtf16(ptr, len) => host::trace_utf16(mem, &api, ptr, len),
+            Call::Blit(sprite, x, y, width, height, flags) => {
+                host::blit(mem, &api, sprite, x, y, width, height, flags)
+            }
+            Call::BlitSub(sprite, x, y, width, height, src_x, src_y, stride, flags) => {
+                host::blit_sub(
+                    mem, &api, sprite, x, y, width, height, src_x, src_y, stride, flags,
+                )
+            }
+            Call::Line(x1, y1, x2, y2) => host::line(mem, &api, x1, y1, x2, y2),
+            Call::HLine(x, y, len) => host::hline(mem, &api, x, y, len),
+            Call::VLine(x, y, len) => host::vline(mem, &api, x, y, len),
+            Call::Oval(x, y, width, height) => host::oval(mem, &api, x, y, width, height),
+            Call::Rect(x, y, width, height) => host::rect(mem, &api, x, y, width, height),
+            Call::Text(ptr, x, y) => host::text(mem, &api, ptr, x, y),
+            Call::TextUtf8(ptr, len, x, y) => host::text_utf8(mem, &api, ptr, len, x, y),
+            Call::TextUtf16(ptr, len, x, y) => host::text_utf16(mem, &api, ptr, len, x, y),
+            Call::DiskR(dest, len) => {
+                host::diskr(mem, &api, dest, len);
+            }
+            Call::DiskW(src, len) => {
+                host::diskw(mem, &api, src, len);
+            }
+            Call::Tone(frequency, duration, volume, flags) => {
+                host::tone(&api, frequency, duration, volume, flags)
+            }
+        }
+    }
+
+    console.update();
+    console.render_audio(44100, 2, &mut [0.0; 64]);
+}
+
+/// Memory that's only reachable through [`Source`] and [`Sink`], so the imports
+/// take their non-slice paths.
+struct View(Vec<u8>);
+
+impl Source<u8> for View {
+    fn item_at(&self, offset: usize) -> Option<u8> {
+        self.0.item_at(offset)
+    }
+
+    fn items_at<const L: usize>(&self, offset: usize) -> Option<[u8; L]> {
+        self.0.items_at(offset)
+    }
+}
+
+impl Sink<u8> for View {
+    fn set_item_at(&mut self, offset: usize, item: u8) {
+        self.0.set_item_at(offset, item)
+    }
+
+    fn fill(&mut self, item: u8) {
+        self.0.fill(item)
+    }
+}
+
+impl GuestMemory for View {
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
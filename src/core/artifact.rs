@@ -0,0 +1,292 @@
+//! Precompiled carts, wrapped in a header saying what they were compiled for.
+//!
+//! Engines load precompiled modules without checking them much, so a module
+//! from another engine version or target is undefined behavior. Artifacts are
+//! checked against the running engine before that, see [`Artifact::check`].
+//!
+//! An artifact is laid out as:
+//! - [`MAGIC`]
+//! - a [`content_hash`] of everything after it, as a checksum
+//! - the wasmstation version, engine, engine version, target triple and
+//!   compiler, each as a little-endian `u16` length and UTF-8
+//! - the [`WasmFeatures`] the module was compiled with, as little-endian
+//!   [`WasmFeatures::bits`]
+//! - the [`content_hash`] of the original cart
+//! - the module and, optionally, the original cart, each as a little-endian
+//!   `u64` length and the bytes
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use anyhow::anyhow;
+
+use crate::core::{features::WasmFeatures, utils::content_hash};
+
+/// The first bytes of every artifact.
+pub const MAGIC: [u8; 8] = *b"\0wsmodul";
+
+/// The version of wasmstation, written to the artifacts it makes.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What an artifact was compiled for, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header<'a> {
+    /// The wasmstation version that made the artifact.
+    pub wasmstation: &'a str,
+    pub engine: &'a str,
+    pub engine_version: &'a str,
+    pub target: &'a str,
+    /// The compiler and its settings, like `cranelift-speed`.
+    pub compiler: &'a str,
+    pub features: WasmFeatures,
+}
+
+impl fmt::Display for Header<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} on {} with {} and {} by wasmstation {}",
+            self.engine,
+            self.engine_version,
+            self.target,
+            self.compiler,
+            self.features,
+            self.wasmstation
+        )
+    }
+}
+
+/// A precompiled module with its [`Header`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Artifact<'a> {
+    pub header: Header<'a>,
+    /// The [`content_hash`] of the original `.wasm` cart.
+    pub cart_hash: [u8; 32],
+    /// The module, as serialized by the engine.
+    pub module: &'a [u8],
+    /// The original cart, to compile when the module can't be used.
+    pub wasm: Option<&'a [u8]>,
+}
+
+impl<'a> Artifact<'a> {
+    /// Read an artifact, checking that it isn't corrupted.
+    pub fn parse(bytes: &'a [u8]) -> anyhow::Result<Self> {
+        if bytes.get(..MAGIC.len()) != Some(&MAGIC) {
+            return Err(anyhow!(
+                "not a wasmstation artifact, precompile carts with wasmstation"
+            ));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            offset: MAGIC.len(),
+        };
+        let checksum = reader.hash()?;
+        if content_hash(&bytes[reader.offset..]) != checksum {
+            return Err(anyhow!(
+                "the artifact is corrupted, its checksum doesn't match"
+            ));
+        }
+
+        let header = Header {
+            wasmstation: reader.str()?,
+            engine: reader.str()?,
+            engine_version: reader.str()?,
+            target: reader.str()?,
+            compiler: reader.str()?,
+            features: WasmFeatures::from_bits(reader.u32()?),
+        };
+        let cart_hash = reader.hash()?;
+        let module = reader.bytes()?;
+        let wasm = Some(reader.bytes()?).filter(|wasm| !wasm.is_empty());
+
+        if wasm.is_some_and(|wasm| content_hash(wasm) != cart_hash) {
+            return Err(anyhow!("the artifact's cart doesn't match its hash"));
+        }
+
+        Ok(Self {
+            header,
+            cart_hash,
+            module,
+            wasm,
+        })
+    }
+
+    /// Write the artifact, with its checksum.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = &self.header;
+
+        let mut body = Vec::new();
+        for field in [
+            header.wasmstation,
+            header.engine,
+            header.engine_version,
+            header.target,
+            header.compiler,
+        ] {
+            body.extend_from_slice(&(field.len() as u16).to_le_bytes());
+            body.extend_from_slice(field.as_bytes());
+        }
+        body.extend_from_slice(&header.features.bits().to_le_bytes());
+        body.extend_from_slice(&self.cart_hash);
+        for bytes in [self.module, self.wasm.unwrap_or_default()] {
+            body.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            body.extend_from_slice(bytes);
+        }
+
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&content_hash(&body));
+        out.extend_from_slice(&body);
+
+        out
+    }
+
+    /// Check that the module was made for `expected`, with the same engine,
+    /// target, compiler settings and [`WasmFeatures`].
+    pub fn check(&self, expected: &Header) -> anyhow::Result<()> {
+        if self.header != *expected {
+            return Err(anyhow!(
+                "the artifact was made for {}, but this is {expected}",
+                self.header
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the module was compiled from the cart with `cart_hash`,
+    /// see [`content_hash`].
+    pub fn check_cart(&self, cart_hash: &[u8; 32]) -> anyhow::Result<()> {
+        if self.cart_hash != *cart_hash {
+            return Err(anyhow!("the artifact was compiled from another cart"));
+        }
+
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len());
+        let end = end.ok_or_else(|| anyhow!("the artifact is truncated"))?;
+
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        let bytes = self.take(8)?;
+
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn hash(&mut self) -> anyhow::Result<[u8; 32]> {
+        let bytes = self.take(32)?;
+
+        Ok(bytes.try_into().unwrap_or_default())
+    }
+
+    fn str(&mut self) -> anyhow::Result<&'a str> {
+        let len = self.take(2)?;
+        let len = u16::from_le_bytes([len[0], len[1]]);
+
+        core::str::from_utf8(self.take(len.into())?)
+            .map_err(|_| anyhow!("the artifact's header is not valid UTF-8"))
+    }
+
+    fn bytes(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = usize::try_from(self.u64()?).map_err(|_| anyhow!("the artifact is too large"))?;
+
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(engine_version: &str) -> Header<'_> {
+        Header {
+            wasmstation: VERSION,
+            engine: "wasmer",
+            engine_version,
+            target: "x86_64-unknown-linux-gnu",
+            compiler: "cranelift-speed",
+            features: WasmFeatures::default(),
+        }
+    }
+
+    #[test]
+    fn artifacts_round_trip() {
+        for wasm in [None, Some(&b"cart"[..])] {
+            let artifact = Artifact {
+                header: header("3.1.0"),
+                cart_hash: content_hash(b"cart"),
+                module: b"module",
+                wasm,
+            };
+            let bytes = artifact.to_bytes();
+
+            assert_eq!(Artifact::parse(&bytes).unwrap(), artifact);
+            assert!(artifact.check(&header("3.1.0")).is_ok());
+        }
+    }
+
+    #[test]
+    fn mismatches_are_rejected() {
+        let artifact = Artifact {
+            header: header("3.1.0"),
+            cart_hash: content_hash(b"cart"),
+            module: b"module",
+            wasm: Some(b"cart"),
+        };
+
+        let err = artifact.check(&header("3.2.0")).unwrap_err();
+        assert!(err.to_string().contains("wasmer 3.1.0"), "{err}");
+
+        let singlepass = Header {
+            compiler: "singlepass",
+            ..header("3.1.0")
+        };
+        assert!(artifact.check(&singlepass).is_err());
+        let simd = Header {
+            features: WasmFeatures {
+                simd: true,
+                ..Default::default()
+            },
+            ..header("3.1.0")
+        };
+        assert!(artifact.check(&simd).is_err());
+
+        assert!(artifact.check_cart(&content_hash(b"cart")).is_ok());
+        assert!(artifact.check_cart(&content_hash(b"other cart")).is_err());
+
+        let mut bytes = artifact.to_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(Artifact::parse(&bytes).is_err());
+        assert!(Artifact::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Artifact::parse(b"\0asm\x01\0\0\0").is_err());
+
+        let mismatched = Artifact {
+            wasm: Some(b"other cart"),
+            ..artifact
+        };
+        assert!(Artifact::parse(&mismatched.to_bytes()).is_err());
+    }
+}
@@ -1,9 +1,15 @@
+#[cfg(not(feature = "std"))]
+use alloc::rc::Rc;
+#[cfg(not(feature = "std"))]
+use core::cell::RefCell;
+#[cfg(feature = "std")]
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
     OutputCallbackInfo, Stream,
 };
+#[cfg(feature = "std")]
 use log::warn;
-use num_traits::*;
+#[cfg(feature = "std")]
 use std::sync::mpsc;
 
 use crate::core::wasm4::{self, TONE_PAN_LEFT, TONE_PAN_RIGHT};
@@ -11,19 +17,27 @@ use crate::core::wasm4::{self, TONE_PAN_LEFT, TONE_PAN_RIGHT};
 const TARGET_FPS: u32 = 60;
 const MAX_VOLUME: u16 = 100;
 
+/// Without `std`, there's no audio thread and tones are applied to the
+/// [`AudioProcessor`] directly, which the host drives with
+/// [`Console::render_audio`](crate::Console::render_audio).
 #[derive(Clone)]
 pub(crate) struct AudioInterface {
+    #[cfg(feature = "std")]
     command_sender: Option<mpsc::Sender<AudioCommand>>,
+    #[cfg(not(feature = "std"))]
+    processor: Rc<RefCell<AudioProcessor<()>>>,
 }
 
 type Sample = i32;
 type FrameCount = u32;
 
 pub(crate) struct AudioState {
+    #[cfg(feature = "std")]
     output: Option<CpalOutput>,
     api: AudioInterface,
 }
 
+#[cfg(feature = "std")]
 impl AudioState {
     pub(crate) fn new() -> Self {
         let (tx, rx) = mpsc::channel();
@@ -45,8 +59,14 @@ impl AudioState {
         s
     }
 
-    pub fn api(&self) -> &AudioInterface {
-        &self.api
+    /// An [`AudioState`] without an output, which drops every tone.
+    pub(crate) fn muted() -> Self {
+        Self {
+            output: None,
+            api: AudioInterface {
+                command_sender: None,
+            },
+        }
     }
 
     fn mk_output<P>(mut audio_processor: AudioProcessor<P>) -> anyhow::Result<CpalOutput>
@@ -73,7 +93,41 @@ impl AudioState {
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl AudioState {
+    /// The sample rate used until [`AudioState::render`] is called.
+    const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+    pub(crate) fn new() -> Self {
+        let mut processor = AudioProcessor::new(());
+        processor.set_sample_rate(Self::DEFAULT_SAMPLE_RATE as i32);
+
+        Self {
+            api: AudioInterface {
+                processor: Rc::new(RefCell::new(processor)),
+            },
+        }
+    }
+
+    /// Render interleaved samples for `channels` channels into `data`.
+    pub(crate) fn render(&self, sample_rate: u32, channels: u16, data: &mut [f32]) {
+        let mut processor = self.api.processor.borrow_mut();
+
+        if processor.sample_rate() != sample_rate as i32 {
+            processor.set_sample_rate(sample_rate as i32);
+        }
+        processor.render_audio(channels, data);
+    }
+}
+
+impl AudioState {
+    pub fn api(&self) -> &AudioInterface {
+        &self.api
+    }
+}
+
 impl AudioInterface {
+    #[cfg(feature = "std")]
     fn do_send(&self, cmd: AudioCommand) {
         if let Some(tx) = &self.command_sender {
             if let Err(e) = tx.send(cmd) {
@@ -82,6 +136,11 @@ impl AudioInterface {
         }
     }
 
+    #[cfg(not(feature = "std"))]
+    fn do_send(&self, cmd: AudioCommand) {
+        self.processor.borrow_mut().apply(cmd);
+    }
+
     pub fn tone(&self, frequency: u32, duration: u32, volume: u32, flags: u32) {
         self.do_send(AudioCommand::Tone(ToneSpec {
             frequency,
@@ -96,6 +155,7 @@ impl AudioInterface {
     }
 }
 
+#[cfg(feature = "std")]
 struct CpalOutput {
     _stream: Stream,
 }
@@ -126,10 +186,7 @@ impl<P: AudioCommandPoller> AudioProcessor<P> {
     fn render_audio(&mut self, audio_channels: u16, data: &mut [f32]) {
         // process commands and apply them
         while let Some(cmd) = self.command_receiver.poll() {
-            match cmd {
-                AudioCommand::NextFrame => self.current_frame += 1,
-                AudioCommand::Tone(spec) => self.apply_tone(&spec),
-            }
+            self.apply(cmd);
         }
 
         // fill all sample frames in data buffer. Note that the samples in
@@ -166,6 +223,13 @@ impl<P: AudioCommandPoller> AudioProcessor<P> {
         }
     }
 
+    fn apply(&mut self, cmd: AudioCommand) {
+        match cmd {
+            AudioCommand::NextFrame => self.current_frame += 1,
+            AudioCommand::Tone(spec) => self.apply_tone(&spec),
+        }
+    }
+
     fn apply_tone(&mut self, tone_spec: &ToneSpec) {
         // find out which channel this ToneSpec applies to
         let channel_idx = (tone_spec.flags & 0b11) as usize;
@@ -205,18 +269,32 @@ impl<P: AudioCommandPoller> AudioProcessor<P> {
             ch.state.sample_rate = sample_rate;
         }
     }
+
+    #[cfg(not(feature = "std"))]
+    fn sample_rate(&self) -> i32 {
+        self.channels[0].state.sample_rate
+    }
 }
 
 trait AudioCommandPoller {
     fn poll(&mut self) -> Option<AudioCommand>;
 }
 
+#[cfg(feature = "std")]
 impl AudioCommandPoller for mpsc::Receiver<AudioCommand> {
     fn poll(&mut self) -> Option<AudioCommand> {
         self.try_recv().ok()
     }
 }
 
+/// Commands are applied as they are sent, so there's nothing to poll.
+#[cfg(not(feature = "std"))]
+impl AudioCommandPoller for () {
+    fn poll(&mut self) -> Option<AudioCommand> {
+        None
+    }
+}
+
 #[derive(Default)]
 #[allow(clippy::enum_variant_names)] // there are no proper names I could come up with, so the variants are called like the type :)
 enum Mode {
@@ -475,13 +553,13 @@ impl Default for LcRng {
 struct NoiseCore {
     rng: LcRng,
     sample_value: Sample,
-    cycle: u32,
+    cycle: u64,
 }
 
 impl NoiseCore {
-    const FLIP_CYCLE_LIMIT: u32 = 1_000_000;
+    const FLIP_CYCLE_LIMIT: u64 = 1_000_000;
     fn render_sample(&mut self, channel: &AudioChannelState) -> Sample {
-        let f2 = (channel.current_freq * channel.current_freq) as u32;
+        let f2 = (channel.current_freq as u64).pow(2);
         self.cycle += f2;
 
         while self.cycle > Self::FLIP_CYCLE_LIMIT {
@@ -497,8 +575,11 @@ impl NoiseCore {
     }
 }
 
-fn lerp<N: Num + Copy>(y0: N, y1: N, x: N, x_max: N) -> N {
-    y0 + (y1 - y0) * x / x_max
+/// Interpolate between `y0` and `y1`, in i64 so long tones don't overflow.
+fn lerp(y0: i32, y1: i32, x: i32, x_max: i32) -> i32 {
+    let (y0, y1) = (y0 as i64, y1 as i64);
+
+    (y0 + (y1 - y0) * x as i64 / x_max as i64) as i32
 }
 
 impl AudioGenerator {
@@ -521,7 +602,7 @@ impl AudioGenerator {
     /// Renders a sample
     fn render_triangle_sample(state: &AudioChannelState) -> Sample {
         let n = 2 * (2 * state.phase - state.sample_rate).abs() - state.sample_rate;
-        n * state.current_volume / state.sample_rate
+        (n as i64 * state.current_volume as i64 / state.sample_rate as i64) as Sample
     }
 
     fn max_volume(&self) -> i32 {
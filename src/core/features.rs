@@ -0,0 +1,243 @@
+//! The WebAssembly proposals carts may use, see [`WasmFeatures`].
+
+use core::fmt;
+
+/// The WebAssembly proposals beyond 1.0 that carts may use, applied the same
+/// way by every backend. Set them with [`Console::with_features`](crate::Console::with_features).
+///
+/// The defaults match the official WASM-4 runtime: what Rust, Zig and clang
+/// emit by default, without SIMD or reference types.
+///
+/// Some engines can't turn some proposals off, e.g. sign extension is always
+/// on in wasmtime and wasmer, so a cart using a disabled proposal may still
+/// load there. Turning on a proposal a backend doesn't implement is an error
+/// when loading a cart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WasmFeatures {
+    pub mutable_global: bool,
+    pub sign_extension: bool,
+    pub saturating_float_to_int: bool,
+    pub multi_value: bool,
+    pub bulk_memory: bool,
+    pub reference_types: bool,
+    pub simd: bool,
+    pub tail_call: bool,
+    pub extended_const: bool,
+}
+
+impl WasmFeatures {
+    /// Only WebAssembly 1.0, without any proposals.
+    pub const MVP: Self = Self {
+        mutable_global: false,
+        sign_extension: false,
+        saturating_float_to_int: false,
+        multi_value: false,
+        bulk_memory: false,
+        reference_types: false,
+        simd: false,
+        tail_call: false,
+        extended_const: false,
+    };
+
+    /// Every proposal wasmstation knows about.
+    pub const ALL: Self = Self {
+        mutable_global: true,
+        sign_extension: true,
+        saturating_float_to_int: true,
+        multi_value: true,
+        bulk_memory: true,
+        reference_types: true,
+        simd: true,
+        tail_call: true,
+        extended_const: true,
+    };
+
+    /// The proposals, by name, and whether they're on.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("mutable-global", self.mutable_global),
+            ("sign-extension", self.sign_extension),
+            ("saturating-float-to-int", self.saturating_float_to_int),
+            ("multi-value", self.multi_value),
+            ("bulk-memory", self.bulk_memory),
+            ("reference-types", self.reference_types),
+            ("simd", self.simd),
+            ("tail-call", self.tail_call),
+            ("extended-const", self.extended_const),
+        ]
+        .into_iter()
+    }
+
+    /// The proposals as a bitmask, in the order of [`WasmFeatures::iter`].
+    pub fn bits(&self) -> u32 {
+        self.iter()
+            .enumerate()
+            .fold(0, |bits, (i, (_, on))| bits | (u32::from(on) << i))
+    }
+
+    /// The proposals in a bitmask made by [`WasmFeatures::bits`].
+    pub fn from_bits(bits: u32) -> Self {
+        let on = |i: u32| bits & (1 << i) != 0;
+
+        Self {
+            mutable_global: on(0),
+            sign_extension: on(1),
+            saturating_float_to_int: on(2),
+            multi_value: on(3),
+            bulk_memory: on(4),
+            reference_types: on(5),
+            simd: on(6),
+            tail_call: on(7),
+            extended_const: on(8),
+        }
+    }
+
+    /// Check that every proposal that is on is in `supported`, the proposals
+    /// `backend` implements, and that the proposals it builds on are on too.
+    pub fn check(&self, backend: &'static str, supported: &Self) -> Result<(), Unsupported> {
+        // reference types extend bulk memory's table instructions.
+        if self.reference_types && !self.bulk_memory {
+            return Err(Unsupported::Requires {
+                feature: "reference-types",
+                requires: "bulk-memory",
+            });
+        }
+
+        let missing = self
+            .iter()
+            .zip(supported.iter())
+            .find(|((_, on), (_, supported))| *on && !supported);
+
+        match missing {
+            Some(((feature, _), _)) => Err(Unsupported::Proposal { backend, feature }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for WasmFeatures {
+    fn default() -> Self {
+        Self {
+            mutable_global: true,
+            sign_extension: true,
+            saturating_float_to_int: true,
+            multi_value: true,
+            bulk_memory: true,
+            ..Self::MVP
+        }
+    }
+}
+
+/// Lists the proposals that are on, like `bulk-memory, simd`.
+impl fmt::Display for WasmFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut on = self.iter().filter(|(_, on)| *on).map(|(name, _)| name);
+
+        match on.next() {
+            Some(first) => {
+                f.write_str(first)?;
+                on.try_for_each(|name| write!(f, ", {name}"))
+            }
+            None => f.write_str("no proposals"),
+        }
+    }
+}
+
+/// [`WasmFeatures`] a backend can't load carts with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unsupported {
+    /// A proposal that is on, but that the backend doesn't implement.
+    Proposal {
+        backend: &'static str,
+        feature: &'static str,
+    },
+    /// A proposal that is on, without the proposal it builds on.
+    Requires {
+        feature: &'static str,
+        requires: &'static str,
+    },
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsupported::Proposal { backend, feature } => write!(
+                f,
+                "the {backend} backend doesn't support the WebAssembly {feature} proposal, turn it off in WasmFeatures"
+            ),
+            Unsupported::Requires { feature, requires } => write!(
+                f,
+                "the WebAssembly {feature} proposal needs {requires}, turn both on or off in WasmFeatures"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Unsupported {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_features_are_named() {
+        let supported = WasmFeatures {
+            simd: false,
+            ..WasmFeatures::ALL
+        };
+
+        assert!(WasmFeatures::default().check("wasmi", &supported).is_ok());
+        assert!(WasmFeatures::MVP.check("wasmi", &WasmFeatures::MVP).is_ok());
+
+        let simd = WasmFeatures {
+            simd: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            simd.check("wasmi", &supported),
+            Err(Unsupported::Proposal {
+                backend: "wasmi",
+                feature: "simd"
+            })
+        );
+    }
+
+    #[test]
+    fn reference_types_need_bulk_memory() {
+        let features = WasmFeatures {
+            bulk_memory: false,
+            reference_types: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            features.check("wasmtime", &WasmFeatures::ALL),
+            Err(Unsupported::Requires {
+                feature: "reference-types",
+                requires: "bulk-memory"
+            })
+        );
+    }
+
+    #[test]
+    fn features_round_trip_through_bits() {
+        for features in [
+            WasmFeatures::MVP,
+            WasmFeatures::default(),
+            WasmFeatures::ALL,
+        ] {
+            assert_eq!(WasmFeatures::from_bits(features.bits()), features);
+        }
+
+        assert_eq!(WasmFeatures::MVP.to_string(), "no proposals");
+        assert_eq!(
+            WasmFeatures {
+                simd: true,
+                ..WasmFeatures::MVP
+            }
+            .to_string(),
+            "simd"
+        );
+    }
+}
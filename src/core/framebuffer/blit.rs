@@ -1,11 +1,11 @@
 use core::ops::Range;
 
 use crate::core::{
-    wasm4::{BLIT_2BPP, BLIT_FLIP_X, BLIT_FLIP_Y, BLIT_ROTATE, SCREEN_SIZE},
+    wasm4::{BLIT_2BPP, BLIT_FLIP_X, BLIT_FLIP_Y, BLIT_ROTATE},
     Sink, Source,
 };
 
-use super::{remap_draw_color, set_pixel_unclipped};
+use super::{remap_draw_color, set_byte_masked, set_pixel_unclipped_impl, Screen, Wasm4Screen};
 
 #[derive(Clone, Copy)]
 pub(crate) enum PixelFormat {
@@ -44,13 +44,46 @@ pub fn blit_sub<S, T>(
 ) where
     S: Source<u8>,
     T: Source<u8> + Sink<u8>,
+{
+    Wasm4Screen { fb: target }.blit_sub(
+        sprite,
+        x,
+        y,
+        width,
+        height,
+        src_x,
+        src_y,
+        stride,
+        flags,
+        draw_colors,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn blit_sub_impl<T, S>(
+    screen: &mut T,
+    sprite: &S,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    src_x: u32,
+    src_y: u32,
+    stride: u32,
+    flags: u32,
+    draw_colors: u16,
+) where
+    T: Screen,
+    S: Source<u8>,
 {
     // we probably should change the signature to avoid signedness conversions
-    let src_x = src_x as i32;
-    let src_y = src_y as i32;
     let width = width as i32;
     let height = height as i32;
-    let stride = stride as i32;
+
+    // sprite coordinates can be anywhere in a u32, so they're kept in i64.
+    let src_x = src_x as i64;
+    let src_y = src_y as i64;
+    let stride = stride as i64;
 
     let flip_x = flags & BLIT_FLIP_X != 0;
     let flip_y = flags & BLIT_FLIP_Y != 0;
@@ -60,8 +93,8 @@ pub fn blit_sub<S, T>(
 
     // for now, this is clips to the screen edge.
     // but it doesn't need to be that way; we may clip smaller too
-    let clip_range_x = 0..(SCREEN_SIZE as i32);
-    let clip_range_y = 0..(SCREEN_SIZE as i32);
+    let clip_range_x = 0..(screen.width() as i32);
+    let clip_range_y = 0..(screen.height() as i32);
 
     // ranges within the target window, local to target
     // start coordinates x and y:
@@ -73,11 +106,47 @@ pub fn blit_sub<S, T>(
         w_range_y = calculate_target_range(x, width, clip_range_x);
     } else {
         w_range_x = calculate_target_range(x, width, clip_range_x);
-        w_range_y = calculate_target_range(x, height, clip_range_y);
+        w_range_y = calculate_target_range(y, height, clip_range_y);
     }
 
     let fmt = PixelFormat::from_blit_flags(flags);
 
+    if !rotate {
+        // rows of the sprite land on rows of the screen, so every framebuffer
+        // byte can be composed from up to four sprite pixels and written once.
+        let screen_width = screen.width() as usize;
+
+        for wy in w_range_y {
+            let sy = src_y + i64::from(if flip_y { height - wy - 1 } else { wy });
+            let row = screen_width * (y + wy) as usize;
+
+            let mut wx = w_range_x.start;
+            while wx < w_range_x.end {
+                let pixel = row + (x + wx) as usize;
+                let byte_end = i32::min(w_range_x.end, wx + 4 - (pixel & 0x3) as i32);
+
+                let mut byte = 0;
+                let mut mask = 0;
+                for (n, wx) in (wx..byte_end).enumerate() {
+                    let sx = src_x + i64::from(if flip_x { width - wx - 1 } else { wx });
+
+                    if let Some(color) = get_sprite_pixel_draw_color(sprite, fmt, sx, sy, stride)
+                        .and_then(|idx| remap_draw_color(idx, draw_colors))
+                    {
+                        let shift = ((pixel & 0x3) + n) << 1;
+                        byte |= (color & 0x3) << shift;
+                        mask |= 0x3 << shift;
+                    }
+                }
+
+                set_byte_masked(screen, pixel >> 2, byte, mask);
+                wx = byte_end;
+            }
+        }
+
+        return;
+    }
+
     for wy in w_range_y {
         for wx in w_range_x.clone() {
             // target coordinates where the sprite pixel will be written to,
@@ -93,35 +162,37 @@ pub fn blit_sub<S, T>(
                 if flip_x { width - wx - 1 } else { wx },
                 if flip_y { height - wy - 1 } else { wy },
             );
-            let sx = src_x + src_location.0;
-            let sy = src_y + src_location.1;
+            let sx = src_x + i64::from(src_location.0);
+            let sy = src_y + i64::from(src_location.1);
 
-            let draw_color_idx = get_sprite_pixel_draw_color(sprite, fmt, sx, sy, stride);
-            if let Some(color) = remap_draw_color(draw_color_idx, draw_colors) {
-                set_pixel_unclipped(target, tx, ty, color)
+            if let Some(color) = get_sprite_pixel_draw_color(sprite, fmt, sx, sy, stride)
+                .and_then(|idx| remap_draw_color(idx, draw_colors))
+            {
+                set_pixel_unclipped_impl(screen, tx, ty, color)
             }
         }
     }
 }
 
+/// The draw color index of a sprite pixel, or `None` if it's outside of the sprite.
 fn get_sprite_pixel_draw_color<T: Source<u8>>(
     sprite: &T,
     fmt: PixelFormat,
-    x: i32,
-    y: i32,
-    width: i32,
-) -> u8 {
-    let pixel_index = width * y + x;
+    x: i64,
+    y: i64,
+    width: i64,
+) -> Option<u8> {
+    let pixel_index = usize::try_from(width * y + x).ok()?;
     match fmt {
         PixelFormat::Blit1BPP => {
-            let mut byte = sprite.item_at((pixel_index >> 3) as usize).unwrap();
+            let mut byte = sprite.item_at(pixel_index >> 3)?;
             byte >>= 7 - (pixel_index & 0x07);
-            byte & 0x01
+            Some(byte & 0x01)
         }
         PixelFormat::Blit2BPP => {
-            let mut byte = sprite.item_at((pixel_index >> 2) as usize).unwrap();
+            let mut byte = sprite.item_at(pixel_index >> 2)?;
             byte >>= 6 - ((pixel_index & 0x03) << 1);
-            byte & 0x03
+            Some(byte & 0x03)
         }
         #[cfg(test)]
         PixelFormat::Framebuffer => panic!("invalid pixel format for reading sprite data"),
@@ -139,7 +210,7 @@ pub fn pixel_width_of_flags(flags: u32) -> u32 {
 
 fn calculate_target_range(tgt_coord: i32, tgt_extent: i32, clip_range: Range<i32>) -> Range<i32> {
     Range {
-        start: i32::max(clip_range.start, tgt_coord) - tgt_coord,
-        end: i32::min(tgt_extent, clip_range.end - tgt_coord),
+        start: i32::max(clip_range.start, tgt_coord).saturating_sub(tgt_coord),
+        end: i32::min(tgt_extent, clip_range.end.saturating_sub(tgt_coord)),
     }
 }
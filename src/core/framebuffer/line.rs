@@ -1,9 +1,7 @@
 use crate::core::{wasm4::FRAMEBUFFER_SIZE, Sink, Source};
 use core::mem;
 
-use super::{
-    remap_draw_color, set_pixel_impl, set_pixel_unclipped_impl, Screen, Wasm4Screen, DRAW_COLOR_1,
-};
+use super::{fill_pixels, set_pixel_impl, set_pixel_unclipped_impl, Screen, Wasm4Screen};
 
 /// Draw a line between two points.
 ///
@@ -17,13 +15,7 @@ pub fn line<T: Source<u8> + Sink<u8>>(
     x2: i32,
     y2: i32,
 ) {
-    let dc0: u8 = (draw_colors & 0xf) as u8;
-    if dc0 == 0 {
-        return;
-    }
-
-    let stroke_color: u8 = (dc0 - 1) & 0x3;
-    line_impl(&mut Wasm4Screen { fb }, stroke_color, x1, y1, x2, y2);
+    Wasm4Screen { fb }.line(draw_colors, x1, y1, x2, y2);
 }
 
 pub(crate) fn line_impl<T: Screen>(
@@ -39,9 +31,10 @@ pub(crate) fn line_impl<T: Screen>(
         mem::swap(&mut y1, &mut y2);
     }
 
-    let dx = (x2 - x1).abs();
+    // far apart points don't fit the differences in an i32.
+    let dx = (x2 as i64 - x1 as i64).abs();
     let sx = if x1 < x2 { 1 } else { -1 };
-    let dy = y2 - y1;
+    let dy = y2 as i64 - y1 as i64;
 
     let mut err = (if dx > dy { dx } else { -dy }) / 2;
 
@@ -72,60 +65,37 @@ pub(crate) fn line_impl<T: Screen>(
 ///
 /// Specifically, between `(x, y)` and `(x + len - 1, y)`.
 pub fn hline<T: Source<u8> + Sink<u8>>(fb: &mut T, draw_colors: u16, x: i32, y: i32, len: u32) {
-    if let Some(stroke) = remap_draw_color(DRAW_COLOR_1, draw_colors) {
-        hline_impl(&mut Wasm4Screen { fb }, stroke, x, y, len);
-    }
+    Wasm4Screen { fb }.hline(draw_colors, x, y, len);
 }
 
 pub(crate) fn hline_impl<T: Screen>(screen: &mut T, stroke: u8, x: i32, y: i32, len: u32) {
-    if y < 0 || y >= T::HEIGHT as i32 {
+    if y < 0 || y >= screen.height() as i32 {
         return;
     }
 
-    let mut start_x = x.max(0);
-    let end_x = (len as i32 + x).min(T::WIDTH as i32);
+    let start_x = x.max(0);
+    let end_x = x.saturating_add(len as i32).min(screen.width() as i32);
 
-    if start_x > end_x {
+    if start_x >= end_x {
         return;
     }
 
-    let fill_end = end_x - (end_x & 3);
-    let fill_start = fill_end.min((start_x + 3) & !3);
-
-    if fill_end - fill_start > 3 {
-        for x in start_x..fill_start {
-            set_pixel_impl(screen, x, y, stroke);
-        }
-
-        let from = ((T::WIDTH as i32 * y + fill_start) >> 2) as usize;
-        let to = ((T::WIDTH as i32 * y + fill_end) >> 2) as usize;
-        let byte_stroke = stroke * 0x55;
-
-        for idx in from..to {
-            screen.fb_mut().set_item_at(idx, byte_stroke);
-        }
-        start_x = fill_end;
-    }
-
-    for x in start_x..end_x {
-        set_pixel_impl(screen, x, y, stroke);
-    }
+    let row = screen.width() as usize * y as usize;
+    fill_pixels(screen, row + start_x as usize, row + end_x as usize, stroke);
 }
 
 /// Draw a vertical line.
 pub fn vline<T: Source<u8> + Sink<u8>>(fb: &mut T, draw_colors: u16, x: i32, y: i32, len: u32) {
-    if let Some(stroke) = remap_draw_color(DRAW_COLOR_1, draw_colors) {
-        vline_impl(&mut Wasm4Screen { fb }, stroke, x, y, len);
-    }
+    Wasm4Screen { fb }.vline(draw_colors, x, y, len);
 }
 
 pub(crate) fn vline_impl<T: Screen>(screen: &mut T, stroke: u8, x: i32, y: i32, len: u32) {
-    if y + len as i32 <= 0 || x < 0 || x >= T::WIDTH as i32 {
+    if y.saturating_add(len as i32) <= 0 || x < 0 || x >= screen.width() as i32 {
         return;
     }
 
     let start_y: i32 = y.max(0);
-    let end_y: i32 = (len as i32 + y).min(T::HEIGHT as i32);
+    let end_y: i32 = y.saturating_add(len as i32).min(screen.height() as i32);
 
     if start_y > end_y {
         return;
@@ -13,22 +13,188 @@ mod text;
 
 pub use blit::{blit_sub, pixel_width_of_flags};
 pub use line::{hline, line, vline};
-pub use oval::oval;
+pub use oval::{oval, MAX_OVAL_SIZE};
 pub use rect::rect;
-pub use text::text;
+pub use text::{charset_byte, text, FALLBACK_GLYPH};
 
-use line::{hline_impl, vline_impl};
+use blit::blit_sub_impl;
+use line::{hline_impl, line_impl, vline_impl};
+use oval::oval_impl;
+use rect::rect_impl;
+use text::text_impl;
 
 const DRAW_COLOR_1: u8 = 0;
 const DRAW_COLOR_2: u8 = 1;
 
-/// A common trait for index-based framebuffers.
-pub(crate) trait Screen {
+/// A 2bpp framebuffer of any size, packed like the WASM-4 framebuffer.
+///
+/// Every byte holds four pixels, the leftmost one in the lowest bits, and rows
+/// follow each other without padding. The drawing methods work exactly like
+/// the WASM-4 functions of this module, but clip to the screen's own size.
+pub trait Screen {
     type Framebuffer: Source<u8> + Sink<u8>;
-    const WIDTH: u32;
-    const HEIGHT: u32;
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
     fn fb(&self) -> &Self::Framebuffer;
     fn fb_mut(&mut self) -> &mut Self::Framebuffer;
+
+    /// See [`set_pixel`].
+    fn set_pixel(&mut self, x: i32, y: i32, color: u8)
+    where
+        Self: Sized,
+    {
+        set_pixel_impl(self, x, y, color)
+    }
+
+    /// See [`set_pixel_unclipped`].
+    fn set_pixel_unclipped(&mut self, x: i32, y: i32, color: u8)
+    where
+        Self: Sized,
+    {
+        set_pixel_unclipped_impl(self, x, y, color)
+    }
+
+    /// See [`clear`].
+    fn clear(&mut self) {
+        self.fb_mut().fill(0u8);
+    }
+
+    /// See [`line`].
+    fn line(&mut self, draw_colors: u16, x1: i32, y1: i32, x2: i32, y2: i32)
+    where
+        Self: Sized,
+    {
+        let dc0: u8 = (draw_colors & 0xf) as u8;
+        if dc0 != 0 {
+            line_impl(self, (dc0 - 1) & 0x3, x1, y1, x2, y2);
+        }
+    }
+
+    /// See [`hline`].
+    fn hline(&mut self, draw_colors: u16, x: i32, y: i32, len: u32)
+    where
+        Self: Sized,
+    {
+        if let Some(stroke) = remap_draw_color(DRAW_COLOR_1, draw_colors) {
+            hline_impl(self, stroke, x, y, len);
+        }
+    }
+
+    /// See [`vline`].
+    fn vline(&mut self, draw_colors: u16, x: i32, y: i32, len: u32)
+    where
+        Self: Sized,
+    {
+        if let Some(stroke) = remap_draw_color(DRAW_COLOR_1, draw_colors) {
+            vline_impl(self, stroke, x, y, len);
+        }
+    }
+
+    /// See [`oval`].
+    fn oval(&mut self, draw_colors: u16, x: i32, y: i32, width: u32, height: u32)
+    where
+        Self: Sized,
+    {
+        oval_impl(self, draw_colors, x, y, width, height)
+    }
+
+    /// See [`rect`].
+    fn rect(&mut self, draw_colors: u16, x: i32, y: i32, width: u32, height: u32)
+    where
+        Self: Sized,
+    {
+        rect_impl(self, draw_colors, x, y, width, height)
+    }
+
+    /// See [`blit_sub`].
+    #[allow(clippy::too_many_arguments)]
+    fn blit_sub<S: Source<u8>>(
+        &mut self,
+        sprite: &S,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        src_x: u32,
+        src_y: u32,
+        stride: u32,
+        flags: u32,
+        draw_colors: u16,
+    ) where
+        Self: Sized,
+    {
+        blit_sub_impl(
+            self,
+            sprite,
+            x,
+            y,
+            width,
+            height,
+            src_x,
+            src_y,
+            stride,
+            flags,
+            draw_colors,
+        )
+    }
+
+    /// See [`text`].
+    fn text<B: Copy + Into<usize>>(&mut self, text: &[B], x: i32, y: i32, draw_colors: u16)
+    where
+        Self: Sized,
+    {
+        text_impl(self, text, x, y, draw_colors)
+    }
+}
+
+/// A [`Screen`] over a borrowed framebuffer of the given size.
+///
+/// ```
+/// use wasmstation::core::framebuffer::{Canvas, Screen};
+///
+/// // a 320x240 canvas, at four pixels per byte
+/// let mut fb = vec![0u8; 320 * 240 / 4];
+/// let mut canvas = Canvas::new(&mut fb, 320, 240);
+///
+/// canvas.rect(0x21, 200, 100, 100, 100);
+/// canvas.text(b"Hello", 260, 120, 0x3);
+/// ```
+pub struct Canvas<'a, B: Sink<u8> + Source<u8>> {
+    fb: &'a mut B,
+    width: u32,
+    height: u32,
+}
+
+impl<'a, B: Sink<u8> + Source<u8>> Canvas<'a, B> {
+    /// Create a [`Canvas`] of `width` by `height` pixels.
+    ///
+    /// The width must be a multiple of 4, so every row starts on a new byte,
+    /// and `fb` must hold at least `width * height / 4` bytes.
+    pub fn new(fb: &'a mut B, width: u32, height: u32) -> Self {
+        assert!(width & 0x3 == 0, "canvas width must be a multiple of 4");
+
+        Self { fb, width, height }
+    }
+}
+
+impl<'a, B: Sink<u8> + Source<u8>> Screen for Canvas<'a, B> {
+    type Framebuffer = B;
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn fb(&self) -> &Self::Framebuffer {
+        self.fb
+    }
+
+    fn fb_mut(&mut self) -> &mut Self::Framebuffer {
+        self.fb
+    }
 }
 
 /// The WASM-4 specific implementation of [`Screen`].
@@ -38,8 +204,14 @@ struct Wasm4Screen<'a, B: Sink<u8> + Source<u8>> {
 
 impl<'a, B: Sink<u8> + Source<u8>> Screen for Wasm4Screen<'a, B> {
     type Framebuffer = B;
-    const WIDTH: u32 = SCREEN_SIZE;
-    const HEIGHT: u32 = SCREEN_SIZE;
+
+    fn width(&self) -> u32 {
+        SCREEN_SIZE
+    }
+
+    fn height(&self) -> u32 {
+        SCREEN_SIZE
+    }
 
     fn fb(&self) -> &Self::Framebuffer {
         self.fb
@@ -57,13 +229,66 @@ pub fn set_pixel<T: Source<u8> + Sink<u8>>(fb: &mut T, x: i32, y: i32, color: u8
 }
 
 pub(crate) fn set_pixel_impl<S: Screen>(s: &mut S, x: i32, y: i32, color: u8) {
-    let idx: usize = (S::WIDTH as usize * y as usize + x as usize) >> 2;
+    let Some(pixel) = usize::try_from(y)
+        .ok()
+        .and_then(|y| y.checked_mul(s.width() as usize))
+        .zip(usize::try_from(x).ok())
+        .and_then(|(row, x)| row.checked_add(x))
+    else {
+        return;
+    };
+
+    let idx = pixel >> 2;
     let shift = (x & 0x3) << 1;
     let mask = 0x3 << shift;
 
-    let fb_byte = s.fb().item_at(idx).unwrap();
-    s.fb_mut()
-        .set_item_at(idx, (color << shift) | (fb_byte & !mask));
+    if let Some(fb_byte) = s.fb().item_at(idx) {
+        s.fb_mut()
+            .set_item_at(idx, (color << shift) | (fb_byte & !mask));
+    }
+}
+
+/// Write the bits of `byte` selected by `mask` to the framebuffer byte at `idx`.
+///
+/// Whole bytes are written without reading them first.
+fn set_byte_masked<S: Screen>(s: &mut S, idx: usize, byte: u8, mask: u8) {
+    match mask {
+        0 => (),
+        0xff => s.fb_mut().set_item_at(idx, byte),
+        _ => {
+            if let Some(fb_byte) = s.fb().item_at(idx) {
+                s.fb_mut()
+                    .set_item_at(idx, (byte & mask) | (fb_byte & !mask));
+            }
+        }
+    }
+}
+
+/// Set the pixels `from..to`, counted from the start of the framebuffer, to
+/// `color`.
+///
+/// Pixels are filled a byte at a time, so only the bytes at either end of the
+/// range are read before being written.
+fn fill_pixels<S: Screen>(s: &mut S, from: usize, to: usize, color: u8) {
+    if from >= to {
+        return;
+    }
+
+    let byte = (color & 0x3) * 0x55;
+    let first = from >> 2;
+    let last = (to - 1) >> 2;
+
+    let head_mask = 0xff << ((from & 0x3) << 1);
+    let tail_mask = 0xff >> ((3 - ((to - 1) & 0x3)) << 1);
+
+    if first == last {
+        set_byte_masked(s, first, byte, head_mask & tail_mask);
+        return;
+    }
+
+    set_byte_masked(s, first, byte, head_mask);
+    s.fb_mut().fill_range(first + 1..last, byte);
+    set_byte_masked(s, last, byte, tail_mask);
 }
 
 /// Set a pixel on the screen as a color in the palette. (clipping if out of bounds)
@@ -73,7 +298,7 @@ pub fn set_pixel_unclipped<T: Source<u8> + Sink<u8>>(fb: &mut T, x: i32, y: i32,
 }
 
 pub(crate) fn set_pixel_unclipped_impl<S: Screen>(s: &mut S, x: i32, y: i32, color: u8) {
-    if x >= 0 && x < S::WIDTH as i32 && y >= 0 && y < S::HEIGHT as i32 {
+    if x >= 0 && x < s.width() as i32 && y >= 0 && y < s.height() as i32 {
         set_pixel_impl(s, x, y, color);
     }
 }
@@ -2,10 +2,18 @@ use crate::core::{Sink, Source};
 
 use super::{hline_impl, set_pixel_unclipped_impl, Screen, Wasm4Screen};
 
+/// The largest width or height of an [`oval`], in pixels.
+///
+/// Drawing takes time proportional to the size, even for parts that are off
+/// the screen.
+pub const MAX_OVAL_SIZE: u32 = 0xffff;
+
 /// Draw an oval (circle).
 ///
 /// An axis parallel ellipse centered around `x` and `y` with given `width` and
 /// `height`. The algorithm aligns with what is implemented in W4's framebuffer.c
+///
+/// Ovals wider or taller than [`MAX_OVAL_SIZE`] aren't drawn.
 pub fn oval<T: Sink<u8> + Source<u8>>(
     fb: &mut T,
     draw_colors: u16,
@@ -14,8 +22,7 @@ pub fn oval<T: Sink<u8> + Source<u8>>(
     width: u32,
     height: u32,
 ) {
-    let mut screen = Wasm4Screen { fb };
-    oval_impl(&mut screen, draw_colors, x, y, width, height)
+    Wasm4Screen { fb }.oval(draw_colors, x, y, width, height)
 }
 
 pub(crate) fn oval_impl<T: Screen>(
@@ -26,8 +33,14 @@ pub(crate) fn oval_impl<T: Screen>(
     width: u32,
     height: u32,
 ) {
-    let width = width as i32;
-    let height = height as i32;
+    if width > MAX_OVAL_SIZE || height > MAX_OVAL_SIZE {
+        return;
+    }
+
+    // the error terms grow with the cube of the size, so they're kept in i64.
+    let (x, y) = (x as i64, y as i64);
+    let width = width as i64;
+    let height = height as i64;
 
     let dc0 = draw_colors & 0xf;
     let dc1 = (draw_colors >> 4) & 0xf;
@@ -59,18 +72,18 @@ pub(crate) fn oval_impl<T: Screen>(
     b1 = 8 * b2;
 
     while west <= east {
-        set_pixel_unclipped_impl(screen, east, north, stroke);
-        set_pixel_unclipped_impl(screen, west, north, stroke);
-        set_pixel_unclipped_impl(screen, west, south, stroke);
-        set_pixel_unclipped_impl(screen, east, south, stroke);
+        plot(screen, east, north, stroke);
+        plot(screen, west, north, stroke);
+        plot(screen, west, south, stroke);
+        plot(screen, east, south, stroke);
 
         let start = west + 1;
 
         if dc0 != 0 && (east - start) > 0 {
-            let len = (east - west - 1) as u32;
+            let len = east - west - 1;
 
-            hline_impl(screen, fill, start, north, len);
-            hline_impl(screen, fill, start, south, len);
+            span(screen, fill, start, north, len);
+            span(screen, fill, start, south, len);
         }
 
         let err2 = 2 * err;
@@ -91,12 +104,26 @@ pub(crate) fn oval_impl<T: Screen>(
     }
 
     while north - south < height {
-        set_pixel_unclipped_impl(screen, west - 1, north, stroke);
-        set_pixel_unclipped_impl(screen, east + 1, north, stroke);
+        plot(screen, west - 1, north, stroke);
+        plot(screen, east + 1, north, stroke);
         north += 1;
 
-        set_pixel_unclipped_impl(screen, west - 1, south, stroke);
-        set_pixel_unclipped_impl(screen, east + 1, south, stroke);
+        plot(screen, west - 1, south, stroke);
+        plot(screen, east + 1, south, stroke);
         south -= 1;
     }
 }
+
+/// Set a pixel of the outline, unless it's too far off the screen for an i32.
+fn plot<T: Screen>(screen: &mut T, x: i64, y: i64, color: u8) {
+    if let (Ok(x), Ok(y)) = (i32::try_from(x), i32::try_from(y)) {
+        set_pixel_unclipped_impl(screen, x, y, color);
+    }
+}
+
+/// Draw a line of the fill, unless it's too far off the screen for an i32.
+fn span<T: Screen>(screen: &mut T, color: u8, x: i64, y: i64, len: i64) {
+    if let (Ok(x), Ok(y), Ok(len)) = (i32::try_from(x), i32::try_from(y), u32::try_from(len)) {
+        hline_impl(screen, color, x, y, len);
+    }
+}
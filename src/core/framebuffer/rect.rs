@@ -1,6 +1,9 @@
 use crate::core::{Sink, Source};
 
-use super::{hline_impl, remap_draw_color, vline_impl, Wasm4Screen, DRAW_COLOR_1, DRAW_COLOR_2};
+use super::{
+    fill_pixels, hline_impl, remap_draw_color, vline_impl, Screen, Wasm4Screen, DRAW_COLOR_1,
+    DRAW_COLOR_2,
+};
 
 /// Draw a rectangle.
 pub fn rect<T: Source<u8> + Sink<u8>>(
@@ -11,18 +14,60 @@ pub fn rect<T: Source<u8> + Sink<u8>>(
     width: u32,
     height: u32,
 ) {
-    let mut screen = Wasm4Screen { fb };
+    Wasm4Screen { fb }.rect(draw_colors, x, y, width, height)
+}
+
+pub(crate) fn rect_impl<T: Screen>(
+    screen: &mut T,
+    draw_colors: u16,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) {
     if let Some(fill_stroke) = remap_draw_color(DRAW_COLOR_1, draw_colors) {
-        let fx = x;
-        let flen = width;
-        for fy in y..y + (height as i32) {
-            hline_impl(&mut screen, fill_stroke, fx, fy, flen)
+        let start_x = x.max(0);
+        let end_x = x.saturating_add(width as i32).min(screen.width() as i32);
+        let start_y = y.max(0);
+        let end_y = y.saturating_add(height as i32).min(screen.height() as i32);
+
+        if start_x < end_x && start_y < end_y {
+            let screen_width = screen.width() as usize;
+            let (start_x, end_x) = (start_x as usize, end_x as usize);
+            let (start_y, end_y) = (start_y as usize, end_y as usize);
+
+            if end_x - start_x == screen_width {
+                // full rows follow each other, so they can be filled in one go.
+                fill_pixels(
+                    screen,
+                    screen_width * start_y,
+                    screen_width * end_y,
+                    fill_stroke,
+                );
+            } else {
+                for fy in start_y..end_y {
+                    let row = screen_width * fy;
+                    fill_pixels(screen, row + start_x, row + end_x, fill_stroke);
+                }
+            }
         }
     }
     if let Some(line_stroke) = remap_draw_color(DRAW_COLOR_2, draw_colors) {
-        hline_impl(&mut screen, line_stroke, x, y, width);
-        hline_impl(&mut screen, line_stroke, x, y + (height as i32) - 1, width);
-        vline_impl(&mut screen, line_stroke, x, y, height);
-        vline_impl(&mut screen, line_stroke, x + (width as i32) - 1, y, height);
+        hline_impl(screen, line_stroke, x, y, width);
+        hline_impl(
+            screen,
+            line_stroke,
+            x,
+            y.saturating_add(height as i32).saturating_sub(1),
+            width,
+        );
+        vline_impl(screen, line_stroke, x, y, height);
+        vline_impl(
+            screen,
+            line_stroke,
+            x.saturating_add(width as i32).saturating_sub(1),
+            y,
+            height,
+        );
     }
 }
@@ -8,9 +8,10 @@ use crate::core::{
         blit::PixelFormat,
         line::{hline_impl, line_impl, vline_impl},
         oval::oval_impl,
+        rect::rect_impl,
         Screen,
     },
-    wasm4::BLIT_2BPP,
+    wasm4::{BLIT_2BPP, BLIT_FLIP_X},
 };
 
 #[derive(PartialEq)]
@@ -37,6 +38,9 @@ impl<const N: usize, const W: u32> std::fmt::Debug for ArrayScreen<N, W> {
 }
 
 impl<const N: usize, const W: u32> ArrayScreen<N, W> {
+    const WIDTH: u32 = W;
+    const HEIGHT: u32 = N as u32 * 4 / W;
+
     fn new() -> ArrayScreen<N, W> {
         ArrayScreen::<N, W> { fb: [0u8; N] }
     }
@@ -55,8 +59,14 @@ impl<const N: usize, const W: u32> ArrayScreen<N, W> {
 
 impl<const N: usize, const W: u32> Screen for ArrayScreen<N, W> {
     type Framebuffer = [u8; N];
-    const WIDTH: u32 = W;
-    const HEIGHT: u32 = N as u32 * 4 / W;
+
+    fn width(&self) -> u32 {
+        Self::WIDTH
+    }
+
+    fn height(&self) -> u32 {
+        Self::HEIGHT
+    }
 
     fn fb(&self) -> &Self::Framebuffer {
         &self.fb
@@ -255,6 +265,41 @@ fn test_blit_sub_impl_1byte_misaligned() {
     assert_eq!(as_fb_line(&fb), as_fb_line(&expected_fb))
 }
 
+#[test]
+fn test_blit_sub_clipped_left() {
+    // a sprite hanging over the left edge must still draw all of its rows
+    let mut screen = ArrayScreen::<4, 8>::new();
+    let sprite = vec![0xff_u8, 0xff_u8];
+
+    screen.blit_sub(&sprite, -4, 0, 8, 2, 0, 0, 8, 0, 0x20);
+
+    assert_eq!(screen.fb, [0b_01_01_01_01, 0, 0b_01_01_01_01, 0]);
+}
+
+#[test]
+fn test_blit_sub_flipped_misaligned() {
+    // a flipped 2bpp sprite straddling a byte boundary, with a transparent pixel
+    let mut screen =
+        ArrayScreen::<2, 8>::new_with_fb_lines(&[as_fb_vec(0b_01_01_01_01_01_01_01_01__u16)]);
+    let sprite = vec![0b_00_01_10_11_u8];
+
+    screen.blit_sub(
+        &sprite,
+        3,
+        0,
+        4,
+        1,
+        0,
+        0,
+        4,
+        BLIT_2BPP | BLIT_FLIP_X,
+        0x4320,
+    );
+
+    let expected = ArrayScreen::new_with_fb_lines(&[as_fb_vec(0b_01_01_01_11_10_01_01_01__u16)]);
+    assert_eq!(screen, expected);
+}
+
 #[test]
 fn test_blit_sub_atlas() {
     let draw_colors = 0x4321;
@@ -337,6 +382,21 @@ fn test_hline() {
     assert_eq!(screen, expected);
 }
 
+#[test]
+fn test_rect() {
+    let mut screen = ArrayScreen::<6, 8>::new();
+    let expected = ArrayScreen::new_with_fb_lines(&[
+        as_fb_vec(0b_00_01_01_01_01_01_01_00__u16),
+        as_fb_vec(0b_10_10_10_10_10_10_10_10__u16),
+        as_fb_vec(0b_00_01_01_01_01_01_01_00__u16),
+    ]);
+
+    rect_impl(&mut screen, 0x2, 1, 0, 6, 3);
+    rect_impl(&mut screen, 0x3, -2, 1, 20, 1);
+
+    assert_eq!(screen, expected);
+}
+
 #[test]
 fn test_vline() {
     let mut screen = ArrayScreen::<7, 4>::new();
@@ -1,6 +1,6 @@
 use crate::core::{Sink, Source};
 
-use super::blit_sub;
+use super::{blit::blit_sub_impl, Screen, Wasm4Screen};
 
 const CHARSET_WIDTH: u32 = 128;
 const CHARSET_FLAGS: u32 = 0; // BLIT_1BPP
@@ -119,6 +119,17 @@ const CHARSET: [u8; 1792] = [
     0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x83, 0x3f, 0x83,
 ];
 
+/// Drawn in place of characters that aren't in the charset.
+pub const FALLBACK_GLYPH: u8 = b'?';
+
+/// The byte that draws `c` with [`text`].
+///
+/// The charset covers Latin-1, so characters up to `U+00FF` are drawn by the
+/// byte of the same value. Anything beyond that is drawn as [`FALLBACK_GLYPH`].
+pub fn charset_byte(c: char) -> u8 {
+    u8::try_from(c).unwrap_or(FALLBACK_GLYPH)
+}
+
 /// Draw text using the built-in WASM-4 font.
 ///
 /// - The string may contain new-line (`\\n`) characters.
@@ -129,6 +140,16 @@ pub fn text<T: Source<u8> + Sink<u8>, B: Copy + Into<usize>>(
     x: i32,
     y: i32,
     draw_colors: u16,
+) {
+    Wasm4Screen { fb }.text(text, x, y, draw_colors)
+}
+
+pub(crate) fn text_impl<T: Screen, B: Copy + Into<usize>>(
+    screen: &mut T,
+    text: &[B],
+    x: i32,
+    y: i32,
+    draw_colors: u16,
 ) {
     let (mut tx, mut ty) = (x, y);
 
@@ -142,7 +163,7 @@ pub fn text<T: Source<u8> + Sink<u8>, B: Copy + Into<usize>>(
             }
             10 => {
                 // line feed
-                ty += 8;
+                ty = ty.saturating_add(8);
                 tx = x;
             }
             32..=255 => {
@@ -150,9 +171,9 @@ pub fn text<T: Source<u8> + Sink<u8>, B: Copy + Into<usize>>(
                 let src_x = (((c - 32) & 0x0f) * 8) as u32;
                 let src_y = (((c - 32) >> 4) * 8) as u32;
 
-                blit_sub(
-                    fb,
-                    &Vec::from(CHARSET),
+                blit_sub_impl(
+                    screen,
+                    &CHARSET,
                     tx,
                     ty,
                     8,
@@ -163,10 +184,10 @@ pub fn text<T: Source<u8> + Sink<u8>, B: Copy + Into<usize>>(
                     CHARSET_FLAGS,
                     draw_colors,
                 );
-                tx += 8;
+                tx = tx.saturating_add(8);
             }
             _ => {
-                tx += 8;
+                tx = tx.saturating_add(8);
             }
         }
     }
@@ -0,0 +1,586 @@
+//! The WASM-4 imports, independent of the WebAssembly engine.
+//!
+//! Every import is implemented once here, over the cart's [`GuestMemory`], so
+//! a [`Backend`](crate::Backend) only has to link these functions under their
+//! WASM-4 names. Pointers and lengths come straight from the cart: anything
+//! out of bounds is logged and the call does nothing.
+//!
+//! See <https://wasm4.org/docs/reference/functions>.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::ops::Range;
+
+use crate::core::{
+    framebuffer, trace,
+    wasm4::{self, HostCall},
+    Api, Disk, Sink, Source,
+};
+
+/// The linear memory of a running cart.
+///
+/// Offsets are addresses in the cart's memory. Engines that can lend out their
+/// memory as a slice get this for free, since it's implemented for `[u8]`.
+pub trait GuestMemory: Source<u8> + Sink<u8> {
+    /// The size of the memory in bytes.
+    fn size(&self) -> usize;
+}
+
+impl GuestMemory for [u8] {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<const N: usize> GuestMemory for [u8; N] {
+    fn size(&self) -> usize {
+        N
+    }
+}
+
+impl GuestMemory for Vec<u8> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Clear the framebuffer before `update`, unless the cart asked to preserve it.
+pub fn before_update<M: GuestMemory + ?Sized>(mem: &mut M) {
+    let flags = mem.item_at(wasm4::SYSTEM_FLAGS_ADDR).unwrap_or_default();
+
+    if wasm4::SYSTEM_PRESERVE_FRAMEBUFFER & flags == 0 {
+        framebuffer::clear(&mut fb(mem));
+    }
+}
+
+pub fn trace<M: GuestMemory + ?Sized>(mem: &M, api: &Api, ptr: u32) {
+    api.count_call(HostCall::Trace);
+
+    if let Some(msg) = c_str(mem, ptr, "trace") {
+        api.print(&msg.iter().map(|b| *b as char).collect::<String>());
+    }
+}
+
+pub fn tracef<M: GuestMemory + ?Sized>(mem: &M, api: &Api, fmt: u32, args: u32) {
+    api.count_call(HostCall::Tracef);
+
+    api.print(&trace::tracef(fmt as usize, args as usize, mem));
+}
+
+pub fn trace_utf8<M: GuestMemory + ?Sized>(mem: &M, api: &Api, ptr: u32, len: u32) {
+    api.count_call(HostCall::TraceUtf8);
+
+    if let Some(msg) = bytes(mem, ptr, len, "traceUtf8") {
+        api.print(&String::from_utf8_lossy(&msg));
+    }
+}
+
+pub fn trace_utf16<M: GuestMemory + ?Sized>(mem: &M, api: &Api, ptr: u32, len: u32) {
+    api.count_call(HostCall::TraceUtf16);
+
+    if let Some(msg) = bytes(mem, ptr, len, "traceUtf16") {
+        api.print(&String::from_utf16_lossy(&utf16(&msg)));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn blit<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    sprite: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    flags: u32,
+) {
+    api.count_call(HostCall::Blit);
+
+    blit_sub_impl(mem, sprite, x, y, width, height, 0, 0, width, flags);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn blit_sub<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    sprite: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    src_x: u32,
+    src_y: u32,
+    stride: u32,
+    flags: u32,
+) {
+    api.count_call(HostCall::BlitSub);
+
+    blit_sub_impl(
+        mem, sprite, x, y, width, height, src_x, src_y, stride, flags,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn blit_sub_impl<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    sprite: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    src_x: u32,
+    src_y: u32,
+    stride: u32,
+    flags: u32,
+) {
+    // up to 66 bits, for the largest arguments.
+    let num_bits = stride as u128
+        * (height as u128 + src_y as u128)
+        * framebuffer::pixel_width_of_flags(flags) as u128;
+
+    // the sprite may overlap with the framebuffer, so it's copied first.
+    let Some(sprite) = u32::try_from(num_bits.div_ceil(8))
+        .ok()
+        .and_then(|len| bytes(mem, sprite, len, "blit"))
+    else {
+        return;
+    };
+
+    let dc = draw_colors(mem);
+    framebuffer::blit_sub(
+        &mut fb(mem),
+        &sprite,
+        x,
+        y,
+        width,
+        height,
+        src_x,
+        src_y,
+        stride,
+        flags,
+        dc,
+    );
+}
+
+pub fn line<M: GuestMemory + ?Sized>(mem: &mut M, api: &Api, x1: i32, y1: i32, x2: i32, y2: i32) {
+    api.count_call(HostCall::Line);
+
+    let dc = draw_colors(mem);
+    framebuffer::line(&mut fb(mem), dc, x1, y1, x2, y2);
+}
+
+pub fn hline<M: GuestMemory + ?Sized>(mem: &mut M, api: &Api, x: i32, y: i32, len: u32) {
+    api.count_call(HostCall::HLine);
+
+    let dc = draw_colors(mem);
+    framebuffer::hline(&mut fb(mem), dc, x, y, len);
+}
+
+pub fn vline<M: GuestMemory + ?Sized>(mem: &mut M, api: &Api, x: i32, y: i32, len: u32) {
+    api.count_call(HostCall::VLine);
+
+    let dc = draw_colors(mem);
+    framebuffer::vline(&mut fb(mem), dc, x, y, len);
+}
+
+pub fn oval<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) {
+    api.count_call(HostCall::Oval);
+
+    let dc = draw_colors(mem);
+    framebuffer::oval(&mut fb(mem), dc, x, y, width, height);
+}
+
+pub fn rect<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) {
+    api.count_call(HostCall::Rect);
+
+    let dc = draw_colors(mem);
+    framebuffer::rect(&mut fb(mem), dc, x, y, width, height);
+}
+
+pub fn text<M: GuestMemory + ?Sized>(mem: &mut M, api: &Api, ptr: u32, x: i32, y: i32) {
+    api.count_call(HostCall::Text);
+
+    if let Some(text) = c_str(mem, ptr, "text") {
+        let dc = draw_colors(mem);
+        framebuffer::text(&mut fb(mem), &text, x, y, dc);
+    }
+}
+
+/// Draw `len` bytes of UTF-8 at `ptr`, with characters outside of Latin-1
+/// drawn as [`FALLBACK_GLYPH`](framebuffer::FALLBACK_GLYPH).
+pub fn text_utf8<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    ptr: u32,
+    len: u32,
+    x: i32,
+    y: i32,
+) {
+    api.count_call(HostCall::TextUtf8);
+
+    if let Some(text) = bytes(mem, ptr, len, "textUtf8") {
+        let text: Vec<u8> = String::from_utf8_lossy(&text)
+            .chars()
+            .map(framebuffer::charset_byte)
+            .collect();
+
+        let dc = draw_colors(mem);
+        framebuffer::text(&mut fb(mem), &text, x, y, dc);
+    }
+}
+
+/// Like [`text_utf8`], but for `len` bytes of UTF-16.
+pub fn text_utf16<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    ptr: u32,
+    len: u32,
+    x: i32,
+    y: i32,
+) {
+    api.count_call(HostCall::TextUtf16);
+
+    if let Some(text) = bytes(mem, ptr, len, "textUtf16") {
+        let text: Vec<u8> = char::decode_utf16(utf16(&text))
+            .map(|c| c.map_or(framebuffer::FALLBACK_GLYPH, framebuffer::charset_byte))
+            .collect();
+
+        let dc = draw_colors(mem);
+        framebuffer::text(&mut fb(mem), &text, x, y, dc);
+    }
+}
+
+/// Copy up to `len` bytes of the disk to `dest`, returning how many were copied.
+pub fn diskr<M: GuestMemory + ?Sized>(mem: &mut M, api: &Api, dest: u32, len: u32) -> u32 {
+    api.count_call(HostCall::DiskR);
+
+    let disk = api.save_cache.get();
+    let len = u32::min(len, disk.len() as u32);
+    if !in_bounds(mem, dest, len, "diskr") {
+        return 0;
+    }
+
+    mem.write_from(dest as usize, &disk.as_bytes()[..len as usize]);
+
+    len
+}
+
+/// Replace the disk with up to [`Disk::CAPACITY`] bytes at `src`, returning
+/// how many were written.
+pub fn diskw<M: GuestMemory + ?Sized>(mem: &M, api: &Api, src: u32, len: u32) -> u32 {
+    api.count_call(HostCall::DiskW);
+
+    let len = u32::min(len, Disk::CAPACITY as u32);
+    let Some(src) = bytes(mem, src, len, "diskw") else {
+        return 0;
+    };
+
+    api.save_cache.set(Disk::new(&src));
+    api.needs_write.set(true);
+
+    len
+}
+
+pub fn tone(api: &Api, frequency: u32, duration: u32, volume: u32, flags: u32) {
+    api.count_call(HostCall::Tone);
+
+    api.tone(frequency, duration, volume, flags);
+}
+
+/// The framebuffer region of a cart's memory.
+fn fb<M: GuestMemory + ?Sized>(mem: &mut M) -> Region<'_, M> {
+    Region {
+        mem,
+        range: wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE,
+    }
+}
+
+fn draw_colors<M: GuestMemory + ?Sized>(mem: &M) -> u16 {
+    mem.items_at(wasm4::DRAW_COLORS_ADDR)
+        .map(u16::from_le_bytes)
+        .unwrap_or_default()
+}
+
+/// Check that `len` bytes at `ptr` are inside of the memory, or log an error.
+pub(crate) fn in_bounds<M: GuestMemory + ?Sized>(mem: &M, ptr: u32, len: u32, name: &str) -> bool {
+    let in_bounds = matches!(
+        (ptr as usize).checked_add(len as usize),
+        Some(end) if end <= mem.size()
+    );
+
+    if !in_bounds {
+        log::error!("{name}: {len} bytes at {ptr:#x} are out of bounds");
+    }
+
+    in_bounds
+}
+
+/// Read `len` bytes at `ptr`, or log an error if they're out of bounds.
+pub(crate) fn bytes<M: GuestMemory + ?Sized>(
+    mem: &M,
+    ptr: u32,
+    len: u32,
+    name: &str,
+) -> Option<Vec<u8>> {
+    if !in_bounds(mem, ptr, len, name) {
+        return None;
+    }
+
+    let mut buf = vec![0; len as usize];
+    if !mem.read_into(ptr as usize, &mut buf) {
+        log::error!("{name}: couldn't read {len} bytes at {ptr:#x}");
+        return None;
+    }
+
+    Some(buf)
+}
+
+/// Read a null terminated string at `ptr`, or log an error if it's out of bounds.
+fn c_str<M: GuestMemory + ?Sized>(mem: &M, ptr: u32, name: &str) -> Option<Vec<u8>> {
+    if let Some(mem) = mem.as_slice() {
+        let tail = mem.get(ptr as usize..).unwrap_or_default();
+
+        if let Some(len) = tail.iter().position(|b| *b == 0) {
+            return Some(tail[..len].to_vec());
+        }
+    } else {
+        let mut text = Vec::new();
+
+        for addr in ptr as usize..mem.size() {
+            match mem.item_at(addr) {
+                Some(0) => return Some(text),
+                Some(b) => text.push(b),
+                None => break,
+            }
+        }
+    }
+
+    log::error!("{name}: string at {ptr:#x} is not null terminated");
+    None
+}
+
+fn utf16(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// A part of a [`GuestMemory`], with offsets relative to its start.
+struct Region<'a, M: ?Sized> {
+    mem: &'a mut M,
+    range: Range<usize>,
+}
+
+impl<'a, M: GuestMemory + ?Sized> Region<'a, M> {
+    /// The addresses of `len` values at `offset`, if they're inside the region.
+    fn addrs(&self, offset: usize, len: usize) -> Option<Range<usize>> {
+        let start = self.range.start.checked_add(offset)?;
+        let end = start.checked_add(len)?;
+
+        (end <= self.range.end).then_some(start..end)
+    }
+}
+
+impl<'a, M: GuestMemory + ?Sized> Source<u8> for Region<'a, M> {
+    fn item_at(&self, offset: usize) -> Option<u8> {
+        self.mem.item_at(self.addrs(offset, 1)?.start)
+    }
+
+    fn items_at<const L: usize>(&self, offset: usize) -> Option<[u8; L]> {
+        self.mem.items_at(self.addrs(offset, L)?.start)
+    }
+
+    fn read_into(&self, offset: usize, buf: &mut [u8]) -> bool {
+        match self.addrs(offset, buf.len()) {
+            Some(addrs) => self.mem.read_into(addrs.start, buf),
+            None => false,
+        }
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        self.mem.as_slice()?.get(self.range.clone())
+    }
+}
+
+impl<'a, M: GuestMemory + ?Sized> Sink<u8> for Region<'a, M> {
+    fn set_item_at(&mut self, offset: usize, item: u8) {
+        if let Some(addrs) = self.addrs(offset, 1) {
+            self.mem.set_item_at(addrs.start, item);
+        }
+    }
+
+    fn fill(&mut self, item: u8) {
+        self.mem.fill_range(self.range.clone(), item);
+    }
+
+    fn write_from(&mut self, offset: usize, items: &[u8]) {
+        if let Some(addrs) = self.addrs(offset, items.len()) {
+            self.mem.write_from(addrs.start, items);
+        }
+    }
+
+    fn fill_range(&mut self, range: Range<usize>, item: u8) {
+        if let Some(addrs) = self.addrs(range.start, range.len()) {
+            self.mem.fill_range(addrs, item);
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        self.mem.as_mut_slice()?.get_mut(self.range.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        blit, blit_sub, diskr, diskw, hline, line, oval, rect, text, text_utf16, text_utf8, tone,
+        vline,
+    };
+    use crate::core::{
+        wasm4::{self, HostCall},
+        Api, Console,
+    };
+
+    #[test]
+    fn out_of_bounds_calls_are_skipped() {
+        let console = Console::new(Box::new(|_| ()));
+        let api = console.create_api();
+
+        let mut mem = vec![0u8; wasm4::MEMORY_SIZE];
+        mem[wasm4::DRAW_COLORS_ADDR] = 0x21;
+        mem[0x2000..0x2003].copy_from_slice(b"hi\0");
+
+        assert_eq!(diskw(&mem, &api, 0x2000, 2000), 1024);
+        assert_eq!(diskr(&mut mem, &api, 0xffff, 2), 0);
+        assert_eq!(diskr(&mut mem, &api, 0x3000, 3), 3);
+        assert_eq!(&mem[0x3000..0x3003], b"hi\0");
+
+        // a string running off the end of memory and a sprite that doesn't fit
+        mem[0xffff] = b'x';
+        text(&mut mem, &api, 0xffff, 0, 0);
+        blit(&mut mem, &api, 0xfff0, 0, 0, 160, 160, 0);
+
+        let fb = wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE;
+        assert!(mem[fb].iter().all(|b| *b == 0));
+        assert_eq!(api.take_host_calls()[HostCall::DiskR as usize], 2);
+    }
+
+    /// Draw `text` at the top left with `draw`, and return the framebuffer.
+    fn render(text: &[u8], draw: impl FnOnce(&mut Vec<u8>, &Api, u32, u32)) -> Vec<u8> {
+        let console = Console::new(Box::new(|_| ()));
+        let api = console.create_api();
+
+        let mut mem = vec![0u8; wasm4::MEMORY_SIZE];
+        mem[wasm4::DRAW_COLORS_ADDR] = 0x21;
+        mem[0x2000..0x2000 + text.len()].copy_from_slice(text);
+
+        draw(&mut mem, &api, 0x2000, text.len() as u32);
+        mem[wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE].to_vec()
+    }
+
+    fn render_text(bytes: &[u8]) -> Vec<u8> {
+        render(&[bytes, b"\0"].concat(), |mem, api, ptr, _| {
+            text(mem, api, ptr, 0, 0)
+        })
+    }
+
+    #[test]
+    fn text_utf8_draws_latin1() {
+        let utf8 = render("é!\nü".as_bytes(), |mem, api, ptr, len| {
+            text_utf8(mem, api, ptr, len, 0, 0)
+        });
+
+        assert_eq!(utf8, render_text(b"\xe9!\n\xfc"));
+        assert_ne!(utf8, render_text(b"\xc3\xa9!\n\xc3\xbc"));
+    }
+
+    #[test]
+    fn text_utf8_falls_back_for_other_characters() {
+        // a character beyond Latin-1 and an invalid byte
+        let utf8 = render(b"\xe2\x82\xac\xff", |mem, api, ptr, len| {
+            text_utf8(mem, api, ptr, len, 0, 0)
+        });
+
+        assert_eq!(utf8, render_text(b"??"));
+    }
+
+    #[test]
+    fn text_utf16_decodes_surrogate_pairs() {
+        // "é", "😀" as a surrogate pair, a lone surrogate and "a"
+        let units: Vec<u8> = [0xe9, 0xd83d, 0xde00, 0xd800, 0x61]
+            .iter()
+            .flat_map(|unit: &u16| unit.to_le_bytes())
+            .collect();
+        let utf16 = render(&units, |mem, api, ptr, len| {
+            text_utf16(mem, api, ptr, len, 0, 0)
+        });
+
+        assert_eq!(utf16, render_text(b"\xe9??a"));
+    }
+
+    #[test]
+    fn extreme_arguments_are_clipped() {
+        let console = Console::new(Box::new(|_| ()));
+        let api = console.create_api();
+
+        // both draw colors show up on the cleared framebuffer.
+        let mut mem = vec![0u8; wasm4::MEMORY_SIZE];
+        mem[wasm4::DRAW_COLORS_ADDR] = 0x32;
+        let fb = wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE;
+        let pixel = |mem: &[u8], x: usize, y: usize| {
+            let index = y * wasm4::SCREEN_SIZE as usize + x;
+            (mem[wasm4::FRAMEBUFFER_ADDR + index / 4] >> ((index % 4) * 2)) & 0b11
+        };
+
+        // off screen, or sizes past i32::MAX, which are negative in WASM-4.
+        hline(&mut mem, &api, i32::MAX, 0, u32::MAX);
+        vline(&mut mem, &api, 0, i32::MIN, u32::MAX);
+        oval(&mut mem, &api, i32::MAX, i32::MAX, 0xffff, 0xffff);
+        oval(&mut mem, &api, 0, 0, u32::MAX, u32::MAX);
+        rect(&mut mem, &api, i32::MIN, i32::MAX, u32::MAX, u32::MAX);
+        text(&mut mem, &api, 0x2000, i32::MAX, i32::MAX);
+        blit_sub(
+            &mut mem,
+            &api,
+            0x2000,
+            i32::MIN,
+            i32::MAX,
+            u32::MAX,
+            u32::MAX,
+            u32::MAX,
+            u32::MAX,
+            u32::MAX,
+            u32::MAX,
+        );
+        assert!(mem[fb.clone()].iter().all(|b| *b == 0));
+
+        // a line across the whole i32 range is cut short instead of hanging.
+        line(&mut mem, &api, i32::MIN, i32::MIN, i32::MAX, i32::MAX);
+        assert!(mem[fb].iter().all(|b| *b == 0));
+
+        // a sub-sprite reaching past the end of its atlas: its last row only
+        // has 8 pixels left in the atlas, the rest is skipped.
+        blit_sub(&mut mem, &api, 0xff00, 0, 0, 16, 16, 8, 8, 16, 0);
+        assert_eq!(pixel(&mem, 0, 0), 1);
+        assert_eq!(pixel(&mem, 15, 14), 1);
+        assert_eq!(pixel(&mem, 7, 15), 1);
+        assert_eq!(pixel(&mem, 8, 15), 0);
+        assert_eq!(pixel(&mem, 16, 0), 0);
+
+        tone(&api, u32::MAX, u32::MAX, u32::MAX, u32::MAX);
+    }
+}
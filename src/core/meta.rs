@@ -0,0 +1,514 @@
+//! Cart metadata, like the title and author of a cart.
+//!
+//! Metadata is stored in a custom section of the cart named [`SECTION`], as
+//! UTF-8 lines of `key=value`. Carts exported with `w4 bundle --html` are
+//! read too, see [`CartMeta::from_bundle`].
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use anyhow::anyhow;
+
+use crate::core::{utils, wasm4::BLIT_2BPP};
+
+/// The name of the custom section holding a cart's metadata.
+pub const SECTION: &str = "wasmstation.meta";
+
+/// Information about a cart, all of it optional.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CartMeta {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    pub icon: Option<Icon>,
+}
+
+/// A sprite to show for a cart, in the format `blit` takes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Icon {
+    pub width: u32,
+    pub height: u32,
+    /// The `blit` flags of the sprite, only [`BLIT_2BPP`] matters.
+    pub flags: u32,
+    pub sprite: Vec<u8>,
+}
+
+impl CartMeta {
+    /// Read the metadata in a `.wasm` cart, or an empty one if it has none.
+    pub fn from_wasm(wasm: &[u8]) -> anyhow::Result<Self> {
+        for section in sections(wasm)? {
+            if let Section::Custom { name, content } = section? {
+                if name == SECTION {
+                    let text = core::str::from_utf8(content)
+                        .map_err(|_| anyhow!("{SECTION} is not valid UTF-8"))?;
+
+                    return Ok(Self::parse(text));
+                }
+            }
+        }
+
+        Ok(Self::default())
+    }
+
+    /// Read the metadata of a cart exported with `w4 bundle --html`.
+    ///
+    /// The title and description of the page are used where the bundled cart
+    /// has none of its own.
+    pub fn from_bundle(html: &str) -> anyhow::Result<Self> {
+        let mut meta = Self::from_wasm(&bundled_cart(html)?)?;
+
+        if meta.title.is_none() {
+            meta.title = element(html, "title").map(unescape_html);
+        }
+        if meta.description.is_none() {
+            meta.description = meta_content(html, "description").map(unescape_html);
+        }
+
+        Ok(meta)
+    }
+
+    /// Parse the contents of the metadata section. Unknown keys and lines
+    /// that don't parse are skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut meta = Self::default();
+
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let value = unescape(value);
+
+            match key {
+                "title" => meta.title = Some(value),
+                "author" => meta.author = Some(value),
+                "description" => meta.description = Some(value),
+                "version" => meta.version = Some(value),
+                "icon" => meta.icon = Icon::parse(&value),
+                _ => (),
+            }
+        }
+
+        meta
+    }
+
+    /// Replace the metadata section of `wasm` with this metadata.
+    pub fn write_to_wasm(&self, wasm: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut out = wasm.get(..8).unwrap_or_default().to_vec();
+
+        // every section is copied as it is, except for old metadata.
+        let mut start = 8;
+        for section in sections(wasm)? {
+            let (end, keep) = match section? {
+                Section::Custom { name, content } => (
+                    content.as_ptr() as usize - wasm.as_ptr() as usize + content.len(),
+                    name != SECTION,
+                ),
+                Section::Other { end } => (end, true),
+            };
+
+            if keep {
+                out.extend_from_slice(&wasm[start..end]);
+            }
+            start = end;
+        }
+
+        let content = self.to_string();
+        let mut payload = Vec::new();
+        utils::write_leb128(&mut payload, SECTION.len() as u32);
+        payload.extend_from_slice(SECTION.as_bytes());
+        payload.extend_from_slice(content.as_bytes());
+
+        out.push(0);
+        utils::write_leb128(&mut out, payload.len() as u32);
+        out.extend_from_slice(&payload);
+
+        Ok(out)
+    }
+
+    /// The title in a form that works as a file or crate name, like
+    /// `tiny-golf` for "Tiny Golf!".
+    pub fn slug(&self) -> Option<String> {
+        let title = self.title.as_ref()?.to_lowercase();
+
+        let slug = title
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+
+        (!slug.is_empty()).then_some(slug)
+    }
+}
+
+#[cfg(feature = "std")]
+impl CartMeta {
+    /// Read the metadata of the cart at `path`, a `.wasm` file or a page
+    /// exported with `w4 bundle --html`.
+    pub fn read(path: &std::path::Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+
+        if path.extension() == Some(std::ffi::OsStr::new("html")) {
+            Self::from_bundle(&String::from_utf8_lossy(&bytes))
+        } else {
+            Self::from_wasm(&bytes)
+        }
+    }
+
+    /// Where to keep the disk of the cart at `path`. That's next to the cart,
+    /// named after its file and its title if it has one, so carts sharing a
+    /// title don't share a disk.
+    pub fn save_file(&self, path: &std::path::Path) -> std::path::PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        match self.slug() {
+            Some(slug) if slug != stem => path.with_file_name(format!("{stem}.{slug}.disk")),
+            _ => path.with_extension("disk"),
+        }
+    }
+
+    /// Read the disk of the cart at `path` from its [`CartMeta::save_file`],
+    /// or from `<cart>.disk`, where it was kept before carts had titles.
+    pub fn read_save(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        match std::fs::read(self.save_file(path)) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                std::fs::read(path.with_extension("disk"))
+            }
+            save => save,
+        }
+    }
+}
+
+impl fmt::Display for CartMeta {
+    /// Write the metadata as the contents of its section.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("title", &self.title),
+            ("author", &self.author),
+            ("description", &self.description),
+            ("version", &self.version),
+        ];
+
+        for (key, value) in fields {
+            if let Some(value) = value {
+                writeln!(f, "{key}={}", escape(value))?;
+            }
+        }
+
+        if let Some(icon) = &self.icon {
+            writeln!(f, "icon={icon}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Icon {
+    /// Bits per pixel of the sprite.
+    pub fn bpp(&self) -> u32 {
+        if self.flags & BLIT_2BPP != 0 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The draw color index of the pixel at `x` and `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u8> {
+        let idx = usize::try_from(y.checked_mul(self.width)?.checked_add(x)?).ok()?;
+
+        match self.bpp() {
+            2 => Some((self.sprite.get(idx / 4)? >> (6 - (idx % 4) * 2)) & 0x3),
+            _ => Some((self.sprite.get(idx / 8)? >> (7 - idx % 8)) & 0x1),
+        }
+    }
+
+    /// The icon as RGBA pixels, row by row. Pixels are drawn with the default
+    /// palette: 0 is transparent and 1 to 3 go from the darkest color to the
+    /// lightest.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let palette = utils::default_palette();
+
+        let mut rgba = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                match self.pixel(x, y).unwrap_or(0) {
+                    0 => rgba.extend_from_slice(&[0; 4]),
+                    color => {
+                        let idx = (4 - color as usize) * 4;
+                        rgba.extend_from_slice(&[
+                            palette[idx + 2],
+                            palette[idx + 1],
+                            palette[idx],
+                            0xff,
+                        ]);
+                    }
+                }
+            }
+        }
+
+        rgba
+    }
+
+    /// Parse an icon written as `<width>x<height> <bpp>bpp <hex sprite>`.
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split(' ');
+
+        let (width, height) = parts.next()?.split_once('x')?;
+        let flags = match parts.next()? {
+            "1bpp" => 0,
+            "2bpp" => BLIT_2BPP,
+            _ => return None,
+        };
+        let sprite = utils::from_hex(parts.next()?)?;
+
+        Some(Self {
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+            flags,
+            sprite,
+        })
+    }
+}
+
+impl fmt::Display for Icon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} {}bpp ", self.width, self.height, self.bpp())?;
+
+        f.write_str(&utils::to_hex(&self.sprite))
+    }
+}
+
+/// Extract the `.wasm` cart from a page exported with `w4 bundle --html`.
+///
+/// The cart is stored as base64 in the `WASM4_CART` field of the JSON in the
+/// `wasm4-cart-json` script.
+pub fn bundled_cart(html: &str) -> anyhow::Result<Vec<u8>> {
+    let not_a_bundle = || anyhow!("not a page exported with `w4 bundle --html`");
+
+    let script = &html[html.find("wasm4-cart-json").ok_or_else(not_a_bundle)?..];
+    let script = &script[..script.find("</script>").ok_or_else(not_a_bundle)?];
+
+    let field = &script[script.find("\"WASM4_CART\"").ok_or_else(not_a_bundle)?..];
+    let start = field.find(':').and_then(|colon| {
+        let quote = field[colon..].find('"')?;
+        Some(colon + quote + 1)
+    });
+    let start = start.ok_or_else(not_a_bundle)?;
+    let len = field[start..].find('"').ok_or_else(not_a_bundle)?;
+
+    utils::from_base64(&field[start..start + len])
+        .ok_or_else(|| anyhow!("the bundled cart is not valid base64"))
+}
+
+enum Section<'a> {
+    Custom {
+        name: &'a str,
+        content: &'a [u8],
+    },
+    /// Any other section, ending at this offset in the module.
+    Other {
+        end: usize,
+    },
+}
+
+/// Iterate over the sections of a WebAssembly module.
+fn sections(wasm: &[u8]) -> anyhow::Result<impl Iterator<Item = anyhow::Result<Section<'_>>>> {
+    if wasm.get(..4) != Some(b"\0asm") {
+        return Err(anyhow!("not a WebAssembly module"));
+    }
+
+    let mut offset = 8;
+    Ok(core::iter::from_fn(move || {
+        if offset >= wasm.len() {
+            return None;
+        }
+
+        let section = read_section(wasm, &mut offset);
+        if section.is_err() {
+            // stop after the first error.
+            offset = wasm.len();
+        }
+
+        Some(section)
+    }))
+}
+
+fn read_section<'a>(wasm: &'a [u8], offset: &mut usize) -> anyhow::Result<Section<'a>> {
+    let malformed = || anyhow!("malformed section at {:#x}", *offset);
+
+    let id = wasm[*offset];
+    let mut pos = *offset + 1;
+    let size = utils::read_leb128(wasm, &mut pos).ok_or_else(malformed)? as usize;
+    let end = pos.checked_add(size).filter(|end| *end <= wasm.len());
+    let end = end.ok_or_else(malformed)?;
+
+    if id != 0 {
+        *offset = end;
+        return Ok(Section::Other { end });
+    }
+
+    let name_len = utils::read_leb128(wasm, &mut pos).ok_or_else(malformed)? as usize;
+    let name = wasm
+        .get(pos..pos.saturating_add(name_len))
+        .filter(|_| pos + name_len <= end)
+        .and_then(|name| core::str::from_utf8(name).ok())
+        .ok_or_else(malformed)?;
+
+    *offset = end;
+    Ok(Section::Custom {
+        name,
+        content: &wasm[pos + name_len..end],
+    })
+}
+
+/// Escape a value so it fits on one line.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// The text inside the first `<tag>` of `html`.
+fn element<'a>(html: &'a str, tag: &str) -> Option<&'a str> {
+    let start = html.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let len = html[start..].find(&format!("</{tag}>"))?;
+
+    Some(html[start..start + len].trim()).filter(|text| !text.is_empty())
+}
+
+/// The `content` of `<meta name="{name}">` in `html`.
+fn meta_content<'a>(html: &'a str, name: &str) -> Option<&'a str> {
+    let tag = &html[html.find(&format!("name=\"{name}\""))?..];
+    let tag = &tag[..tag.find('>')?];
+
+    let start = tag.find("content=\"")? + 9;
+    let len = tag[start..].find('"')?;
+
+    Some(&tag[start..start + len]).filter(|text| !text.is_empty())
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(module)` with a `name` custom section.
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0\0\x06\x04name\0";
+
+    fn meta() -> CartMeta {
+        CartMeta {
+            title: Some("Tiny Golf!".to_string()),
+            author: Some("someone".to_string()),
+            description: Some("two lines\nof \\ text".to_string()),
+            version: None,
+            icon: Some(Icon {
+                width: 8,
+                height: 2,
+                flags: 0,
+                sprite: vec![0xf0, 0x0f],
+            }),
+        }
+    }
+
+    #[test]
+    fn metadata_round_trips_through_a_custom_section() {
+        let wasm = meta().write_to_wasm(MODULE).unwrap();
+        assert_eq!(CartMeta::from_wasm(&wasm).unwrap(), meta());
+
+        // writing again replaces the section and keeps the others.
+        let wasm = CartMeta::default().write_to_wasm(&wasm).unwrap();
+        assert_eq!(CartMeta::from_wasm(&wasm).unwrap(), CartMeta::default());
+        assert_eq!(&wasm[..MODULE.len()], MODULE);
+        assert_eq!(wasm.len(), MODULE.len() + 2 + SECTION.len() + 1);
+    }
+
+    #[test]
+    fn metadata_is_read_from_bundles() {
+        let html = r#"<html><head>
+            <title>Bundled &amp; Golf</title>
+            <meta name="description" content="A golf game">
+            <script id="wasm4-cart-json" type="application/json">{"WASM4_CART":"AGFzbQEAAAA=","WASM4_CART_SIZE":8}</script>
+            </head></html>"#;
+
+        assert_eq!(bundled_cart(html).unwrap(), b"\0asm\x01\0\0\0");
+        assert_eq!(
+            CartMeta::from_bundle(html).unwrap(),
+            CartMeta {
+                title: Some("Bundled & Golf".to_string()),
+                description: Some("A golf game".to_string()),
+                ..CartMeta::default()
+            }
+        );
+        assert!(bundled_cart("<html></html>").is_err());
+    }
+
+    #[test]
+    fn slugs_only_keep_letters_and_digits() {
+        assert_eq!(meta().slug().as_deref(), Some("tiny-golf"));
+        assert_eq!(CartMeta::default().slug(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn saves_are_named_after_the_cart_and_its_title() {
+        let path = std::path::Path::new("carts/golf.wasm");
+        assert_eq!(
+            meta().save_file(path),
+            std::path::Path::new("carts/golf.tiny-golf.disk")
+        );
+        assert_eq!(
+            CartMeta::default().save_file(path),
+            std::path::Path::new("carts/golf.disk")
+        );
+
+        let path = std::path::Path::new("carts/tiny-golf.wasm");
+        assert_eq!(
+            meta().save_file(path),
+            std::path::Path::new("carts/tiny-golf.disk")
+        );
+    }
+
+    #[test]
+    fn icons_read_their_pixels() {
+        let icon = meta().icon.unwrap();
+
+        assert_eq!(icon.pixel(0, 0), Some(1));
+        assert_eq!(icon.pixel(4, 0), Some(0));
+        assert_eq!(icon.pixel(4, 1), Some(1));
+        assert_eq!(icon.pixel(0, 2), None);
+
+        let rgba = icon.to_rgba();
+        assert_eq!(rgba.len(), 8 * 2 * 4);
+        assert_eq!(rgba[..4], [0x07, 0x18, 0x21, 0xff]);
+        assert_eq!(rgba[16..20], [0; 4]);
+    }
+}
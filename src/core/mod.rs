@@ -1,15 +1,23 @@
 //! Core engine functionality.
 
-use core::cell::Cell;
-use std::sync::Arc;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{cell::Cell, ops::Range};
 
+pub mod artifact;
 mod audio;
+pub mod features;
 pub mod framebuffer;
+pub mod host;
+pub mod meta;
+pub mod system;
 pub mod trace;
 pub mod utils;
 pub mod wasm4;
 
 use audio::{AudioInterface, AudioState};
+use features::WasmFeatures;
+use system::System;
+use wasm4::{HostCall, HostCallCounts};
 
 #[doc(inline)]
 pub use framebuffer::{blit_sub, hline, line, oval, rect, text, vline};
@@ -38,9 +46,20 @@ pub trait Backend {
     /// registers, where the cart will read mouse input from.
     fn set_mouse(&mut self, x: i16, y: i16, buttons: u8);
     /// Tell the renderer to save the cache to disk.
-    fn write_save_cache(&mut self) -> Option<[u8; 1024]>;
+    fn write_save_cache(&mut self) -> Option<Disk>;
     /// Set the backend's save cache.
-    fn set_save_cache(&mut self, data: [u8; 1024]);
+    fn set_save_cache(&mut self, disk: Disk);
+    /// Re-instantiate the cart from its original module.
+    ///
+    /// Memory, palette, draw colors and framebuffer are initialized like in
+    /// the backend's constructor, but the save cache (disk) is kept. The cart's
+    /// `start()` function is not called.
+    fn reset(&mut self) -> anyhow::Result<()>;
+    /// Read the cart's linear memory starting at `offset` into `buf`.
+    fn read_memory(&self, offset: usize, buf: &mut [u8]);
+    /// Return how often each [`HostCall`] was made since the last call
+    /// to this function, and restart counting.
+    fn take_host_calls(&mut self) -> HostCallCounts;
 }
 
 /// Common methods for reading from game memory.
@@ -59,6 +78,37 @@ where
 
     /// Like [`item_at`](Source::item_at), but for multiple values.
     fn items_at<const L: usize>(&self, offset: usize) -> Option<[T; L]>;
+
+    /// Fill `buf` with the values starting at `offset`.
+    ///
+    /// Returns `false` if any of them is outside of the subregion, in which
+    /// case `buf` may be partially written.
+    fn read_into(&self, offset: usize, buf: &mut [T]) -> bool {
+        if let Some(slice) = self.as_slice() {
+            let end = offset.saturating_add(buf.len());
+            return match slice.get(offset..end) {
+                Some(items) => {
+                    buf.copy_from_slice(items);
+                    true
+                }
+                None => false,
+            };
+        }
+
+        for (n, item) in buf.iter_mut().enumerate() {
+            match offset.checked_add(n).and_then(|addr| self.item_at(addr)) {
+                Some(value) => *item = value,
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// The whole subregion as a slice, if it can be accessed directly.
+    fn as_slice(&self) -> Option<&[T]> {
+        None
+    }
 }
 
 impl<T: Copy> Source<T> for Vec<T> {
@@ -67,9 +117,13 @@ impl<T: Copy> Source<T> for Vec<T> {
     }
 
     fn items_at<const L: usize>(&self, offset: usize) -> Option<[T; L]> {
-        self.get(offset..(offset + L))
+        self.get(offset..offset.checked_add(L)?)
             .map(|s| s.try_into().unwrap())
     }
+
+    fn as_slice(&self) -> Option<&[T]> {
+        Some(self)
+    }
 }
 
 impl<const N: usize, T: Copy> Source<T> for [T; N] {
@@ -78,9 +132,28 @@ impl<const N: usize, T: Copy> Source<T> for [T; N] {
     }
 
     fn items_at<const L: usize>(&self, offset: usize) -> Option<[T; L]> {
-        self.get(offset..(offset + L))
+        self.get(offset..offset.checked_add(L)?)
+            .map(|s| s.try_into().unwrap())
+    }
+
+    fn as_slice(&self) -> Option<&[T]> {
+        Some(self)
+    }
+}
+
+impl<T: Copy> Source<T> for [T] {
+    fn item_at(&self, offset: usize) -> Option<T> {
+        self.get(offset).copied()
+    }
+
+    fn items_at<const L: usize>(&self, offset: usize) -> Option<[T; L]> {
+        self.get(offset..offset.checked_add(L)?)
             .map(|s| s.try_into().unwrap())
     }
+
+    fn as_slice(&self) -> Option<&[T]> {
+        Some(self)
+    }
 }
 
 /// Common methods for writing to game memory.
@@ -98,6 +171,44 @@ where
     /// Fill the entire memory subregion with values of T by
     /// cloning `item`
     fn fill(&mut self, item: T);
+
+    /// Write `items` starting at `offset`.
+    ///
+    /// Items outside of the subregion are skipped.
+    fn write_from(&mut self, offset: usize, items: &[T]) {
+        if let Some(slice) = self.as_mut_slice() {
+            let start = offset.min(slice.len());
+            let end = offset.saturating_add(items.len()).min(slice.len());
+            slice[start..end].copy_from_slice(&items[..end - start]);
+            return;
+        }
+
+        for (n, item) in items.iter().enumerate() {
+            match offset.checked_add(n) {
+                Some(addr) => self.set_item_at(addr, *item),
+                None => break,
+            }
+        }
+    }
+
+    /// Like [`fill`](Sink::fill), but only for the values in `range`.
+    fn fill_range(&mut self, range: Range<usize>, item: T) {
+        if let Some(slice) = self.as_mut_slice() {
+            if let Some(target) = slice.get_mut(range) {
+                target.fill(item);
+            }
+            return;
+        }
+
+        for offset in range {
+            self.set_item_at(offset, item);
+        }
+    }
+
+    /// The whole subregion as a mutable slice, if it can be accessed directly.
+    fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        None
+    }
 }
 
 impl<T> Sink<T> for Vec<T>
@@ -105,12 +216,18 @@ where
     T: Copy,
 {
     fn set_item_at(&mut self, offset: usize, item: T) {
-        self[offset] = item
+        if let Some(slot) = self.get_mut(offset) {
+            *slot = item;
+        }
     }
 
     fn fill(&mut self, item: T) {
         <[T]>::fill(self, item)
     }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        Some(self)
+    }
 }
 
 impl<const N: usize, T> Sink<T> for [T; N]
@@ -118,12 +235,37 @@ where
     T: Copy,
 {
     fn set_item_at(&mut self, offset: usize, item: T) {
-        self[offset] = item
+        if let Some(slot) = self.get_mut(offset) {
+            *slot = item;
+        }
     }
 
     fn fill(&mut self, item: T) {
         <[T]>::fill(self, item)
     }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        Some(self)
+    }
+}
+
+impl<T> Sink<T> for [T]
+where
+    T: Copy,
+{
+    fn set_item_at(&mut self, offset: usize, item: T) {
+        if let Some(slot) = self.get_mut(offset) {
+            *slot = item;
+        }
+    }
+
+    fn fill(&mut self, item: T) {
+        <[T]>::fill(self, item)
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        Some(self)
+    }
 }
 
 /// The function called to print the various trace calls.
@@ -133,6 +275,8 @@ pub type PrintFn = Box<dyn Fn(&str) + Sync + Send + 'static>;
 pub struct Console {
     audio_state: AudioState,
     print: Arc<PrintFn>,
+    system: Option<Arc<dyn System>>,
+    features: WasmFeatures,
 }
 
 impl Console {
@@ -141,16 +285,51 @@ impl Console {
         Self {
             audio_state: AudioState::new(),
             print: Arc::new(print),
+            system: None,
+            features: WasmFeatures::default(),
         }
     }
 
+    /// Create a [`Console`] without audio output, e.g. for running carts
+    /// headlessly.
+    #[cfg(feature = "std")]
+    pub fn muted(print: PrintFn) -> Self {
+        Self {
+            audio_state: AudioState::muted(),
+            print: Arc::new(print),
+            system: None,
+            features: WasmFeatures::default(),
+        }
+    }
+
+    /// Load carts with this [`Console`] as system carts, which can import the
+    /// functions in [`system`](crate::core::system) to talk to `system`.
+    pub fn with_system(mut self, system: Arc<dyn System>) -> Self {
+        self.system = Some(system);
+        self
+    }
+
+    /// Load carts with this [`Console`] allowing the WebAssembly proposals
+    /// in `features`, instead of [`WasmFeatures::default`].
+    pub fn with_features(mut self, features: WasmFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// The WebAssembly proposals carts loaded with this [`Console`] may use.
+    pub fn features(&self) -> &WasmFeatures {
+        &self.features
+    }
+
     /// Create an [`Api`] for runtime function's access.
     pub fn create_api(&self) -> Api {
         Api {
             audio_api: self.audio_state.api().clone(),
-            save_cache: Cell::new([0; 1024]),
+            save_cache: Cell::new(Disk::default()),
             needs_write: Cell::new(false),
+            host_calls: Cell::new([0; 17]),
             print: self.print.clone(),
+            system: self.system.clone(),
         }
     }
 
@@ -158,9 +337,19 @@ impl Console {
     pub fn update(&self) {
         self.audio_state.api().update()
     }
+
+    /// Render the sound of all carts using this [`Console`] into `data`,
+    /// as interleaved samples for `channels` channels at `sample_rate`.
+    ///
+    /// Without the `std` feature there's no audio thread, so the host calls
+    /// this whenever its audio output needs more samples.
+    #[cfg(not(feature = "std"))]
+    pub fn render_audio(&self, sample_rate: u32, channels: u16, data: &mut [f32]) {
+        self.audio_state.render(sample_rate, channels, data)
+    }
 }
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
 impl Default for Console {
     fn default() -> Self {
         #[wasm_bindgen::prelude::wasm_bindgen(js_namespace = console)]
@@ -172,19 +361,73 @@ impl Default for Console {
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
 impl Default for Console {
     fn default() -> Self {
         Self::new(Box::new(|s| println!("{s}")))
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl Default for Console {
+    fn default() -> Self {
+        Self::new(Box::new(|s| log::info!("{s}")))
+    }
+}
+
+/// The contents of a cart's [disk](https://wasm4.org/docs/guides/diskw),
+/// up to [`Disk::CAPACITY`] bytes.
+///
+/// The disk remembers how much was written to it, so a cart reading it back
+/// never gets more than it wrote.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Disk {
+    data: [u8; Disk::CAPACITY],
+    len: usize,
+}
+
+impl Disk {
+    /// The most bytes a disk can hold.
+    pub const CAPACITY: usize = 1024;
+
+    /// Create a [`Disk`] holding `bytes`, cut off after [`Disk::CAPACITY`] bytes.
+    pub fn new(bytes: &[u8]) -> Self {
+        let len = bytes.len().min(Self::CAPACITY);
+        let mut data = [0; Self::CAPACITY];
+        data[..len].copy_from_slice(&bytes[..len]);
+
+        Self { data, len }
+    }
+
+    /// The bytes stored on the disk.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
 /// A [`Console`] helper for [`Backend`]s.
+#[derive(Clone)]
 pub struct Api {
     audio_api: AudioInterface,
     print: Arc<PrintFn>,
-    pub save_cache: Cell<[u8; 1024]>,
+    system: Option<Arc<dyn System>>,
+    pub save_cache: Cell<Disk>,
     pub needs_write: Cell<bool>,
+    host_calls: Cell<HostCallCounts>,
 }
 
 impl Api {
@@ -192,7 +435,7 @@ impl Api {
         self.audio_api.tone(frequency, duration, volume, flags)
     }
 
-    pub fn write_save(&self) -> Option<[u8; 1024]> {
+    pub fn write_save(&self) -> Option<Disk> {
         if self.needs_write.get() {
             self.needs_write.set(false);
             Some(self.save_cache.get())
@@ -204,4 +447,51 @@ impl Api {
     pub fn print(&self, msg: &str) {
         (self.print)(msg);
     }
+
+    /// Count a call to a host function, see [`Backend::take_host_calls`].
+    pub fn count_call(&self, call: HostCall) {
+        let mut counts = self.host_calls.get();
+        counts[call as usize] = counts[call as usize].saturating_add(1);
+        self.host_calls.set(counts);
+    }
+
+    pub fn take_host_calls(&self) -> HostCallCounts {
+        self.host_calls.replace([0; 17])
+    }
+
+    /// The [`System`] of a system cart, see [`Console::with_system`].
+    pub fn system(&self) -> Option<&dyn System> {
+        self.system.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A [`Sink`] without a slice, written an item at a time.
+    struct Items([u8; 4]);
+
+    impl Sink<u8> for Items {
+        fn set_item_at(&mut self, offset: usize, item: u8) {
+            self.0.set_item_at(offset, item);
+        }
+
+        fn fill(&mut self, item: u8) {
+            self.0.fill(item);
+        }
+    }
+
+    #[test]
+    fn writes_past_the_end_keep_what_fits() {
+        for (offset, expected) in [(2, [0, 0, 1, 2]), (4, [0; 4]), (usize::MAX, [0; 4])] {
+            let mut slice = [0; 4];
+            slice.write_from(offset, &[1, 2, 3]);
+            assert_eq!(slice, expected, "slice at {offset}");
+
+            let mut items = Items([0; 4]);
+            items.write_from(offset, &[1, 2, 3]);
+            assert_eq!(items.0, expected, "items at {offset}");
+        }
+    }
 }
@@ -0,0 +1,209 @@
+//! Extra imports for privileged system carts, like a launcher menu.
+//!
+//! A cart becomes a system cart by being loaded with a [`Console`](crate::Console)
+//! that was given a [`System`] with [`Console::with_system`](crate::Console::with_system).
+//! Only then do the backends link these functions into the `env` module,
+//! every other cart sees the standard WASM-4 imports and nothing else.
+//!
+//! Strings are passed as pointer and length, in UTF-8. Functions that fill a
+//! buffer return the full length of the value, so a cart can tell when its
+//! buffer was too small.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::core::{
+    host::{bytes, in_bounds, GuestMemory},
+    wasm4::FRAMEBUFFER_SIZE,
+    Api,
+};
+
+/// The names of the system imports, as linked into the `env` module.
+pub const IMPORTS: [&str; 6] = [
+    "cartCount",
+    "cartMeta",
+    "cartThumbnail",
+    "launch",
+    "getSetting",
+    "setSetting",
+];
+
+/// The size of a thumbnail written by `cartThumbnail`, a framebuffer
+/// followed by its palette.
+pub const THUMBNAIL_SIZE: usize = FRAMEBUFFER_SIZE + 16;
+
+/// The services a host offers to system carts.
+///
+/// Carts are identified by their index in the host's list of installed carts.
+pub trait System: Send + Sync {
+    /// The number of installed carts.
+    fn cart_count(&self) -> usize;
+
+    /// A piece of metadata about a cart, like its `"name"`.
+    fn cart_meta(&self, index: usize, key: &str) -> Option<String>;
+
+    /// The screen of a cart, to show as a preview.
+    fn cart_thumbnail(&self, index: usize) -> Option<Thumbnail>;
+
+    /// Start a cart once the system cart's current callback returns.
+    fn launch(&self, index: usize);
+
+    /// A runtime setting, shared by the host and its system carts.
+    fn setting(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Change a runtime setting.
+    fn set_setting(&self, key: &str, value: &[u8]);
+}
+
+/// A cart's screen, in the format of the WASM-4 framebuffer and palette.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub framebuffer: [u8; FRAMEBUFFER_SIZE],
+    pub palette: [u8; 16],
+}
+
+/// The number of installed carts.
+pub fn cart_count(api: &Api) -> u32 {
+    system(api, "cartCount").map_or(0, |system| system.cart_count() as u32)
+}
+
+/// Copy up to `len` bytes of a cart's metadata value for `key` to `dest`,
+/// returning the length of the whole value, or 0 if there is none.
+pub fn cart_meta<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    index: u32,
+    key: u32,
+    key_len: u32,
+    dest: u32,
+    len: u32,
+) -> u32 {
+    let Some(system) = system(api, "cartMeta") else {
+        return 0;
+    };
+    let Some(key) = string(mem, key, key_len, "cartMeta") else {
+        return 0;
+    };
+
+    match system.cart_meta(index as usize, &key) {
+        Some(value) => copy_out(mem, value.as_bytes(), dest, len, "cartMeta"),
+        None => 0,
+    }
+}
+
+/// Copy a cart's [`Thumbnail`] to `dest`, [`THUMBNAIL_SIZE`] bytes. Returns 1
+/// if the cart has one, and 0 otherwise.
+pub fn cart_thumbnail<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    index: u32,
+    dest: u32,
+) -> u32 {
+    let Some(system) = system(api, "cartThumbnail") else {
+        return 0;
+    };
+    if !in_bounds(mem, dest, THUMBNAIL_SIZE as u32, "cartThumbnail") {
+        return 0;
+    }
+
+    match system.cart_thumbnail(index as usize) {
+        Some(thumbnail) => {
+            let dest = dest as usize;
+            mem.write_from(dest, &thumbnail.framebuffer);
+            mem.write_from(dest + FRAMEBUFFER_SIZE, &thumbnail.palette);
+
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Start the cart at `index`, see [`System::launch`].
+pub fn launch(api: &Api, index: u32) {
+    if let Some(system) = system(api, "launch") {
+        system.launch(index as usize);
+    }
+}
+
+/// Copy up to `len` bytes of the setting `key` to `dest`, returning the
+/// length of the whole value, or 0 if it isn't set.
+pub fn get_setting<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    api: &Api,
+    key: u32,
+    key_len: u32,
+    dest: u32,
+    len: u32,
+) -> u32 {
+    let Some(system) = system(api, "getSetting") else {
+        return 0;
+    };
+    let Some(key) = string(mem, key, key_len, "getSetting") else {
+        return 0;
+    };
+
+    match system.setting(&key) {
+        Some(value) => copy_out(mem, &value, dest, len, "getSetting"),
+        None => 0,
+    }
+}
+
+/// Set the setting `key` to the `len` bytes at `value`.
+pub fn set_setting<M: GuestMemory + ?Sized>(
+    mem: &M,
+    api: &Api,
+    key: u32,
+    key_len: u32,
+    value: u32,
+    len: u32,
+) {
+    let Some(system) = system(api, "setSetting") else {
+        return;
+    };
+    let Some(key) = string(mem, key, key_len, "setSetting") else {
+        return;
+    };
+
+    if let Some(value) = bytes(mem, value, len, "setSetting") {
+        system.set_setting(&key, &value);
+    }
+}
+
+fn system<'a>(api: &'a Api, name: &str) -> Option<&'a dyn System> {
+    let system = api.system();
+
+    if system.is_none() {
+        log::error!("{name}: only system carts can call this function");
+    }
+
+    system
+}
+
+fn string<M: GuestMemory + ?Sized>(mem: &M, ptr: u32, len: u32, name: &str) -> Option<String> {
+    let bytes = bytes(mem, ptr, len, name)?;
+
+    match String::from_utf8(bytes) {
+        Ok(string) => Some(string),
+        Err(_) => {
+            log::error!("{name}: string at {ptr:#x} is not valid UTF-8");
+            None
+        }
+    }
+}
+
+/// Copy up to `len` bytes of `value` to `dest`, returning the length of `value`.
+fn copy_out<M: GuestMemory + ?Sized>(
+    mem: &mut M,
+    value: &[u8],
+    dest: u32,
+    len: u32,
+    name: &str,
+) -> u32 {
+    let len = usize::min(len as usize, value.len());
+    if !in_bounds(mem, dest, len as u32, name) {
+        return 0;
+    }
+
+    mem.write_from(dest as usize, &value[..len]);
+
+    value.len() as u32
+}
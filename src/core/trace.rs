@@ -1,98 +1,426 @@
 //! WASM-4 string parsing.
 
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
 use crate::core::Source;
 
+/// Printed in place of arguments that can't be read from the cart's memory.
+const INVALID_ARG: &str = "(invalid)";
+
+/// The largest field width or precision, so a cart can't make the host
+/// allocate gigabytes for a single conversion.
+const MAX_FIELD: usize = 1024;
+
 /// Parse a format string.
 ///
+/// This implements the subset of C's `printf` used by carts compiled with clang:
+///  - conversions: `d`, `i`, `u`, `o`, `x`, `X`, `c`, `s`, `p`, `f`, `F`, `e`, `E`, `g`, `G` and `%%`.
+///  - flags: `-`, `0`, `+`, ` ` and `#`.
+///  - field width and precision, including `*`, up to 1024.
+///  - length modifiers: `hh`, `h`, `l`, `ll`, `j`, `z` and `t`.
+///
+/// Arguments are read following the wasm32 varargs layout, where 8 byte
+/// values (`double` and `long long`) are 8 byte aligned.
+///
 /// Arguments:
 ///  - `fmt: usize`: a pointer to the format string (**null terminated ASCII**).
 ///  - `args: usize`: a pointer to the data arguments.
 ///  - `mem: &impl Source<u8>`: a reference to the game's memory.
-pub fn tracef<T: Source<u8>>(mut fmt: usize, mut args: usize, mem: &T) -> String {
-    let mut fmt_str = String::new();
+pub fn tracef<T: Source<u8> + ?Sized>(fmt: usize, args: usize, mem: &T) -> String {
+    let mut f = Formatter {
+        mem,
+        fmt,
+        args,
+        output: String::new(),
+    };
 
-    while let Some(b) = mem.item_at(fmt) {
-        if b == 0 {
-            break;
+    while let Some(b) = f.next_fmt() {
+        if b == b'%' {
+            if f.conversion().is_none() {
+                break;
+            }
+        } else {
+            f.output.push(b as char);
         }
+    }
+
+    f.output
+}
+
+/// A parsed conversion specification.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Length {
+    Char,
+    Short,
+    Int,
+    LongLong,
+}
+
+struct Formatter<'a, T: ?Sized> {
+    mem: &'a T,
+    fmt: usize,
+    args: usize,
+    output: String,
+}
 
-        fmt_str.push(char::from_u32(b.into()).unwrap_or('!'));
+impl<'a, T: Source<u8> + ?Sized> Formatter<'a, T> {
+    /// Read the next byte of the format string, `None` at its end.
+    fn next_fmt(&mut self) -> Option<u8> {
+        let b = self.mem.item_at(self.fmt).filter(|b| *b != 0)?;
+        self.fmt += 1;
 
-        fmt += 1;
+        Some(b)
     }
 
-    let mut fmt_chars = fmt_str.chars();
-    let mut output = String::new();
+    fn peek_fmt(&self) -> Option<u8> {
+        self.mem.item_at(self.fmt).filter(|b| *b != 0)
+    }
 
-    while let Some(ch) = fmt_chars.next() {
-        // break for null terminated string.
-        if ch == '\0' {
-            break;
+    /// Read the next `L` byte argument, always advancing past it.
+    fn arg<const L: usize>(&mut self) -> Option<[u8; L]> {
+        if L == 8 {
+            self.args = (self.args + 7) & !7;
         }
 
-        // characters other than the formatting character '%' are just added.
-        if ch != '%' {
-            output.push(ch);
-            continue;
+        let bytes = self.mem.items_at(self.args);
+        self.args += L;
+
+        bytes
+    }
+
+    fn arg_i32(&mut self) -> Option<i32> {
+        self.arg().map(i32::from_le_bytes)
+    }
+
+    /// Parse and format a conversion after a `%`, `None` if the format string ended.
+    fn conversion(&mut self) -> Option<()> {
+        let mut spec = Spec::default();
+
+        loop {
+            match self.peek_fmt()? {
+                b'-' => spec.left = true,
+                b'0' => spec.zero = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alt = true,
+                _ => break,
+            }
+            self.fmt += 1;
         }
 
-        // if we've hit the formatting character ('%') then move to the next
-        // char and check to see what it is and replace it with the value it references.
-        if let Some(ch) = fmt_chars.next() {
-            match ch {
-                'c' => {
-                    let val = match mem.items_at(args) {
-                        Some(val) => u32::from_le_bytes(val),
-                        None => continue,
-                    };
+        if self.peek_fmt()? == b'*' {
+            self.fmt += 1;
 
-                    output.push(val.try_into().unwrap_or('!'));
-                    args += 4;
+            let width = self.arg_i32().unwrap_or(0);
+            spec.left |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+        } else {
+            spec.width = self.number();
+        }
+        spec.width = spec.width.min(MAX_FIELD);
+
+        if self.peek_fmt()? == b'.' {
+            self.fmt += 1;
+
+            if self.peek_fmt()? == b'*' {
+                self.fmt += 1;
+                // a negative precision is taken as if it were omitted.
+                spec.precision = self
+                    .arg_i32()
+                    .and_then(|precision| usize::try_from(precision).ok());
+            } else {
+                spec.precision = Some(self.number());
+            }
+            spec.precision = spec.precision.map(|precision| precision.min(MAX_FIELD));
+        }
+
+        let length = match self.peek_fmt()? {
+            b'h' => {
+                self.fmt += 1;
+                if self.peek_fmt()? == b'h' {
+                    self.fmt += 1;
+                    Length::Char
+                } else {
+                    Length::Short
                 }
-                'd' | 'x' => {
-                    let val = match mem.items_at(args) {
-                        Some(bytes) => i32::from_le_bytes(bytes),
-                        None => continue,
-                    };
-
-                    output.push_str(&val.to_string());
-                    args += 4;
+            }
+            b'l' => {
+                self.fmt += 1;
+                if self.peek_fmt()? == b'l' {
+                    self.fmt += 1;
+                    Length::LongLong
+                } else {
+                    Length::Int
                 }
-                's' => {
-                    let mut str_ptr = match mem.items_at(args) {
-                        Some(val) => u32::from_le_bytes(val),
-                        None => continue,
-                    };
+            }
+            b'j' | b'q' => {
+                self.fmt += 1;
+                Length::LongLong
+            }
+            b'z' | b't' => {
+                self.fmt += 1;
+                Length::Int
+            }
+            _ => Length::Int,
+        };
 
-                    let mut nstr = String::new();
+        let conv = self.next_fmt()?;
 
-                    while let Some(byte) = mem.item_at(str_ptr as usize) {
-                        if byte == 0 {
-                            break;
-                        }
+        match conv {
+            b'%' => self.output.push('%'),
+            b'd' | b'i' => match self.arg_int(length, true) {
+                Some(val) => self.integer(&spec, conv, val.unsigned_abs(), val < 0),
+                None => self.invalid(&spec),
+            },
+            b'u' | b'o' | b'x' | b'X' => match self.arg_int(length, false) {
+                Some(val) => self.integer(&spec, conv, val as u64, false),
+                None => self.invalid(&spec),
+            },
+            b'p' => match self.arg::<4>() {
+                Some(val) => {
+                    let spec = Spec { alt: true, ..spec };
+                    self.integer(&spec, conv, u32::from_le_bytes(val).into(), false);
+                }
+                None => self.invalid(&spec),
+            },
+            b'c' => match self.arg_i32() {
+                Some(val) => {
+                    let c = char::from(val as u8).to_string();
+                    self.pad(&spec, "", &c, false);
+                }
+                None => self.invalid(&spec),
+            },
+            b's' => match self.arg::<4>() {
+                Some(ptr) => {
+                    let s = self.string(u32::from_le_bytes(ptr) as usize, spec.precision);
+                    self.pad(&spec, "", &s, false);
+                }
+                None => self.invalid(&spec),
+            },
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => match self.arg() {
+                Some(val) => self.float(&spec, conv, f64::from_le_bytes(val)),
+                None => self.invalid(&spec),
+            },
+            _ => {
+                self.output.push('%');
+                self.output.push(conv as char);
+            }
+        }
 
-                        nstr.push(byte.try_into().unwrap_or('!'));
-                        str_ptr += 1;
-                    }
+        Some(())
+    }
 
-                    output.push_str(&nstr);
-                    args += 4;
-                }
-                'f' => {
-                    let val = match mem.items_at(args) {
-                        Some(val) => f64::from_le_bytes(val),
-                        None => continue,
-                    };
-
-                    output.push_str(&val.to_string());
-                    args += 8;
+    /// Parse a decimal number in the format string, 0 if there is none.
+    fn number(&mut self) -> usize {
+        let mut n: usize = 0;
+
+        while let Some(digit @ b'0'..=b'9') = self.peek_fmt() {
+            n = n.saturating_mul(10).saturating_add((digit - b'0') as usize);
+            self.fmt += 1;
+        }
+
+        n
+    }
+
+    /// Read an integer argument, sign or zero extended to 64 bits.
+    fn arg_int(&mut self, length: Length, signed: bool) -> Option<i64> {
+        if length == Length::LongLong {
+            return self.arg().map(i64::from_le_bytes);
+        }
+
+        let val = self.arg_i32()?;
+
+        Some(match (length, signed) {
+            (Length::Char, true) => val as i8 as i64,
+            (Length::Char, false) => val as u8 as i64,
+            (Length::Short, true) => val as i16 as i64,
+            (Length::Short, false) => val as u16 as i64,
+            (_, true) => val as i64,
+            (_, false) => val as u32 as i64,
+        })
+    }
+
+    /// Read a null terminated string of at most `max` bytes.
+    fn string(&self, mut ptr: usize, max: Option<usize>) -> String {
+        let mut s = String::new();
+        let end = max.map_or(usize::MAX, |max| ptr.saturating_add(max));
+
+        while ptr < end {
+            match self.mem.item_at(ptr) {
+                Some(0) | None => break,
+                Some(b) => s.push(b as char),
+            }
+            ptr += 1;
+        }
+
+        s
+    }
+
+    fn integer(&mut self, spec: &Spec, conv: u8, val: u64, negative: bool) {
+        let mut digits = match conv {
+            b'o' => format!("{val:o}"),
+            b'x' | b'p' => format!("{val:x}"),
+            b'X' => format!("{val:X}"),
+            _ => val.to_string(),
+        };
+
+        if spec.precision == Some(0) && val == 0 {
+            digits.clear();
+        }
+
+        if let Some(precision) = spec.precision {
+            if digits.len() < precision {
+                digits.insert_str(0, &"0".repeat(precision - digits.len()));
+            }
+        }
+
+        let prefix = match conv {
+            b'd' | b'i' => sign(spec, negative),
+            b'o' if spec.alt && !digits.starts_with('0') => {
+                digits.insert(0, '0');
+                ""
+            }
+            b'x' | b'p' if spec.alt && val != 0 => "0x",
+            b'X' if spec.alt && val != 0 => "0X",
+            _ => "",
+        };
+
+        let zero = spec.zero && spec.precision.is_none();
+        self.pad(spec, prefix, &digits, zero);
+    }
+
+    fn float(&mut self, spec: &Spec, conv: u8, val: f64) {
+        let upper = conv.is_ascii_uppercase();
+        let prefix = sign(spec, val.is_sign_negative());
+        // `f64::abs` is only available with std
+        let val = f64::from_bits(val.to_bits() & !(1 << 63));
+
+        if !val.is_finite() {
+            let body = match (val.is_nan(), upper) {
+                (true, false) => "nan",
+                (true, true) => "NAN",
+                (false, false) => "inf",
+                (false, true) => "INF",
+            };
+
+            return self.pad(spec, prefix, body, false);
+        }
+
+        let precision = spec.precision.unwrap_or(6);
+
+        let mut body = match conv.to_ascii_lowercase() {
+            b'f' => format!("{val:.precision$}"),
+            b'e' => exponential(val, precision, upper),
+            _ => {
+                let precision = precision.max(1);
+                let exp = exponent(&format!("{val:.*e}", precision - 1));
+
+                let mut body = if exp < -4 || exp >= precision as i32 {
+                    exponential(val, precision - 1, upper)
+                } else {
+                    format!("{val:.*}", (precision as i32 - 1 - exp) as usize)
+                };
+
+                if !spec.alt {
+                    trim_fraction(&mut body);
                 }
-                _ => output.push(ch),
+
+                body
             }
+        };
+
+        if spec.alt && !body.contains('.') {
+            let at = body.find(['e', 'E']).unwrap_or(body.len());
+            body.insert(at, '.');
+        }
+
+        self.pad(spec, prefix, &body, spec.zero);
+    }
+
+    fn invalid(&mut self, spec: &Spec) {
+        self.pad(spec, "", INVALID_ARG, false);
+    }
+
+    /// Write `prefix` and `body` padded to the field width.
+    fn pad(&mut self, spec: &Spec, prefix: &str, body: &str, zero: bool) {
+        let len = prefix.len() + body.chars().count();
+        let fill = spec.width.saturating_sub(len);
+
+        if spec.left {
+            self.output.push_str(prefix);
+            self.output.push_str(body);
+            self.output.extend((0..fill).map(|_| ' '));
+        } else if zero {
+            self.output.push_str(prefix);
+            self.output.extend((0..fill).map(|_| '0'));
+            self.output.push_str(body);
+        } else {
+            self.output.extend((0..fill).map(|_| ' '));
+            self.output.push_str(prefix);
+            self.output.push_str(body);
         }
     }
+}
+
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Format like C's `%e`, with a signed exponent of at least two digits.
+fn exponential(val: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{val:.precision$e}");
+    let mantissa = &formatted[..formatted.find('e').unwrap_or(formatted.len())];
+    let exp = exponent(&formatted);
+
+    format!(
+        "{mantissa}{}{}{:02}",
+        if upper { 'E' } else { 'e' },
+        if exp < 0 { '-' } else { '+' },
+        exp.unsigned_abs()
+    )
+}
+
+/// The exponent of a number formatted with Rust's `{:e}`.
+fn exponent(formatted: &str) -> i32 {
+    formatted
+        .split_once('e')
+        .and_then(|(_, exp)| exp.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Remove trailing zeros of the fraction, as `%g` does.
+fn trim_fraction(body: &mut String) {
+    let exp_at = body.find(['e', 'E']).unwrap_or(body.len());
+    let exp = body.split_off(exp_at);
+
+    if body.contains('.') {
+        let trimmed = body.trim_end_matches('0').trim_end_matches('.').len();
+        body.truncate(trimmed);
+    }
 
-    output
+    body.push_str(&exp);
 }
 
 #[cfg(test)]
@@ -102,7 +430,7 @@ mod tests {
     #[test]
     fn tracef_float() {
         assert_eq!(
-            "0.473;0.856",
+            "0.473000;0.856000",
             tracef(
                 16,
                 0,
@@ -162,4 +490,146 @@ mod tests {
             )
         )
     }
+
+    enum Arg<'a> {
+        Int(i32),
+        LongLong(i64),
+        Double(f64),
+        Str(&'a str),
+    }
+
+    use Arg::*;
+
+    /// Lay out `args` like clang's wasm32 varargs at address 0 and run
+    /// [`tracef`], with the format string at 256 and strings from 512.
+    fn format(fmt: &str, args: &[Arg]) -> String {
+        let mut mem = vec![0u8; 1024];
+        let mut arg_ptr = 0;
+        let mut str_ptr = 512;
+
+        let mut push = |bytes: &[u8], align: usize| {
+            arg_ptr = (arg_ptr + align - 1) & !(align - 1);
+            mem[arg_ptr..arg_ptr + bytes.len()].copy_from_slice(bytes);
+            arg_ptr += bytes.len();
+        };
+
+        let mut strings = Vec::new();
+        for arg in args {
+            match arg {
+                Int(val) => push(&val.to_le_bytes(), 4),
+                LongLong(val) => push(&val.to_le_bytes(), 8),
+                Double(val) => push(&val.to_le_bytes(), 8),
+                Str(val) => {
+                    push(&(str_ptr as u32).to_le_bytes(), 4);
+                    strings.push((str_ptr, *val));
+                    str_ptr += val.len() + 1;
+                }
+            }
+        }
+
+        for (ptr, val) in strings {
+            mem[ptr..ptr + val.len()].copy_from_slice(val.as_bytes());
+        }
+        mem[256..256 + fmt.len()].copy_from_slice(fmt.as_bytes());
+
+        tracef(256, 0, &mem)
+    }
+
+    #[test]
+    fn tracef_printf_table() {
+        let table: &[(&str, &[Arg], &str)] = &[
+            // integers
+            ("%d", &[Int(-42)], "-42"),
+            ("%i", &[Int(7)], "7"),
+            ("%u", &[Int(-1)], "4294967295"),
+            ("%x", &[Int(255)], "ff"),
+            ("%X", &[Int(255)], "FF"),
+            ("%x", &[Int(-1)], "ffffffff"),
+            ("%#x", &[Int(255)], "0xff"),
+            ("%#X", &[Int(255)], "0XFF"),
+            ("%#x", &[Int(0)], "0"),
+            ("%o", &[Int(8)], "10"),
+            ("%#o", &[Int(8)], "010"),
+            ("%p", &[Int(0x1234)], "0x1234"),
+            // flags, width and precision
+            ("%5d", &[Int(42)], "   42"),
+            ("%-5d|", &[Int(42)], "42   |"),
+            ("%05d", &[Int(-42)], "-0042"),
+            ("%+d", &[Int(42)], "+42"),
+            ("% d", &[Int(42)], " 42"),
+            ("%.3d", &[Int(7)], "007"),
+            ("%.0d", &[Int(0)], ""),
+            ("%08.3d", &[Int(7)], "     007"),
+            ("%#06x", &[Int(255)], "0x00ff"),
+            ("%*d", &[Int(5), Int(42)], "   42"),
+            ("%*d|", &[Int(-4), Int(1)], "1   |"),
+            ("%.*f", &[Int(2), Double(1.23456)], "1.23"),
+            // length modifiers
+            ("%hhd", &[Int(200)], "-56"),
+            ("%hhu", &[Int(-1)], "255"),
+            ("%hd", &[Int(40000)], "-25536"),
+            ("%hu", &[Int(70000)], "4464"),
+            ("%ld", &[Int(-3)], "-3"),
+            ("%zu", &[Int(3)], "3"),
+            ("%lld", &[LongLong(-5_000_000_000)], "-5000000000"),
+            ("%llx", &[LongLong(0x1_0000_0000)], "100000000"),
+            // 8 byte arguments are 8 byte aligned
+            ("%d %lld", &[Int(1), LongLong(2)], "1 2"),
+            ("%d %f %d", &[Int(1), Double(0.5), Int(3)], "1 0.500000 3"),
+            // characters and strings
+            ("%c%c", &[Int('h' as i32), Int('i' as i32)], "hi"),
+            ("%3c|", &[Int('x' as i32)], "  x|"),
+            ("%s", &[Str("hello")], "hello"),
+            ("%.3s", &[Str("hello")], "hel"),
+            ("%-7s|", &[Str("hi")], "hi     |"),
+            ("%5s", &[Str("hi")], "   hi"),
+            ("%s and %s", &[Str("this"), Str("that")], "this and that"),
+            // floating point
+            ("%f", &[Double(1.5)], "1.500000"),
+            ("%.2f", &[Double(2.675)], "2.67"),
+            ("%.0f", &[Double(2.5)], "2"),
+            ("%#.0f", &[Double(3.0)], "3."),
+            ("%8.3f", &[Double(-1.5)], "  -1.500"),
+            ("%08.3f", &[Double(-1.5)], "-001.500"),
+            ("%e", &[Double(12345.678)], "1.234568e+04"),
+            ("%E", &[Double(0.00012)], "1.200000E-04"),
+            ("%+.1e", &[Double(0.0)], "+0.0e+00"),
+            ("%.0e", &[Double(12345.0)], "1e+04"),
+            ("%g", &[Double(0.0001)], "0.0001"),
+            ("%g", &[Double(100000.0)], "100000"),
+            ("%g", &[Double(1234567.0)], "1.23457e+06"),
+            ("%g", &[Double(0.00001)], "1e-05"),
+            ("%g", &[Double(0.5)], "0.5"),
+            ("%#g", &[Double(1.0)], "1.00000"),
+            ("%G", &[Double(1e-10)], "1E-10"),
+            ("%f", &[Double(f64::INFINITY)], "inf"),
+            ("%F", &[Double(f64::NEG_INFINITY)], "-INF"),
+            ("%f", &[Double(f64::NAN)], "nan"),
+            ("%5.1f|", &[Double(-0.04)], " -0.0|"),
+            // literals and unknown conversions
+            ("100%%", &[], "100%"),
+            ("%y", &[], "%y"),
+            ("trailing %", &[], "trailing "),
+        ];
+
+        for (fmt, args, expected) in table {
+            assert_eq!(*expected, format(fmt, args), "format string {fmt:?}");
+        }
+    }
+
+    #[test]
+    fn tracef_invalid_arg() {
+        let mem = "%d;%s;%f\0".as_bytes().to_vec();
+
+        assert_eq!(
+            "(invalid);(invalid);(invalid)",
+            tracef(0, mem.len() + 64, &mem)
+        );
+    }
+
+    #[test]
+    fn tracef_huge_fields() {
+        assert_eq!(format("%*d", &[Int(i32::MAX), Int(1)]).len(), 1024);
+        assert_eq!(format("%.4000000000f", &[Double(1.0)]).len(), 1026);
+    }
 }
@@ -1,30 +1,129 @@
 //! Utility functions for WASM-4.
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use alloc::{format, string::String, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
 use core::array;
+use sha2::{Digest, Sha256};
 
 use crate::core::wasm4::FRAMEBUFFER_SIZE;
 
 /// Returns the default WASM-4 palette.
 pub fn default_palette() -> [u8; 16] {
-    let mut buf = vec![];
-    buf.write_u32::<LittleEndian>(0xe0f8cf).unwrap();
-    buf.write_u32::<LittleEndian>(0x86c06c).unwrap();
-    buf.write_u32::<LittleEndian>(0x306850).unwrap();
-    buf.write_u32::<LittleEndian>(0x071821).unwrap();
+    let mut buf = [0; 16];
+    LittleEndian::write_u32_into(&[0xe0f8cf, 0x86c06c, 0x306850, 0x071821], &mut buf);
 
-    buf.try_into().expect("wrong palette size")
+    buf
 }
 
 /// Returns the default WASM-4 draw colors.
 pub fn default_draw_colors() -> [u8; 2] {
-    bytemuck::cast_slice(&[0x1203_u16])
-        .to_vec()
-        .try_into()
-        .expect("wrong draw colors size")
+    0x1203_u16.to_le_bytes()
 }
 
 /// Returns an empty WASM-4 framebuffer.
 pub fn default_framebuffer() -> [u8; FRAMEBUFFER_SIZE] {
     array::from_fn(|_| 0)
 }
+
+/// The SHA-256 hash of `bytes`, to tell carts apart in caches. Unlike a
+/// faster hash, no cart can be made to collide with another.
+pub fn content_hash(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+/// Read an unsigned LEB128 number, like the sizes in a WebAssembly module,
+/// advancing `offset` past it.
+pub fn read_leb128(bytes: &[u8], offset: &mut usize) -> Option<u32> {
+    let mut value = 0u32;
+
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*offset)?;
+        *offset += 1;
+
+        value |= u32::from(byte & 0x7f).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Append `value` to `out` as an unsigned LEB128 number.
+pub fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Encode `bytes` as lowercase hexadecimal.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decode hexadecimal, `None` if `hex` isn't.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encode `bytes` as standard, padded base64.
+pub fn to_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, byte)| {
+            bits | (u32::from(*byte) << (16 - idx * 8))
+        });
+
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - idx * 6)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Decode standard or URL safe base64, padded or not and ignoring whitespace.
+pub fn from_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bits = 0u32;
+    let mut len = 0;
+
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+
+        bits = (bits << 6) | u32::from(value);
+        len += 6;
+        if len >= 8 {
+            len -= 8;
+            out.push((bits >> len) as u8);
+        }
+    }
+
+    Some(out)
+}
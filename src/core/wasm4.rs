@@ -13,6 +13,8 @@ pub const SYSTEM_FLAGS_ADDR: usize = 0x1f;
 pub const NETPLAY_ADDR: usize = 0x20;
 pub const FRAMEBUFFER_ADDR: usize = 0xa0;
 
+pub const MEMORY_SIZE: usize = 0x10000;
+
 pub const BUTTON_1: u8 = 1;
 pub const BUTTON_2: u8 = 2;
 pub const BUTTON_LEFT: u8 = 16;
@@ -46,3 +48,76 @@ pub const TONE_MODE3: u32 = 8;
 pub const TONE_MODE4: u32 = 12;
 pub const TONE_PAN_LEFT: u32 = 16;
 pub const TONE_PAN_RIGHT: u32 = 32;
+
+/// The functions a cart can import from the `env` module.
+///
+/// See <https://wasm4.org/docs/reference/functions>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostCall {
+    Trace,
+    Tracef,
+    TraceUtf8,
+    TraceUtf16,
+    Blit,
+    BlitSub,
+    Line,
+    HLine,
+    VLine,
+    Oval,
+    Rect,
+    Text,
+    TextUtf8,
+    TextUtf16,
+    DiskR,
+    DiskW,
+    Tone,
+}
+
+impl HostCall {
+    /// All host calls, in the order used by [`HostCallCounts`].
+    pub const ALL: [HostCall; 17] = [
+        HostCall::Trace,
+        HostCall::Tracef,
+        HostCall::TraceUtf8,
+        HostCall::TraceUtf16,
+        HostCall::Blit,
+        HostCall::BlitSub,
+        HostCall::Line,
+        HostCall::HLine,
+        HostCall::VLine,
+        HostCall::Oval,
+        HostCall::Rect,
+        HostCall::Text,
+        HostCall::TextUtf8,
+        HostCall::TextUtf16,
+        HostCall::DiskR,
+        HostCall::DiskW,
+        HostCall::Tone,
+    ];
+
+    /// The name the function is imported as.
+    pub fn name(self) -> &'static str {
+        match self {
+            HostCall::Trace => "trace",
+            HostCall::Tracef => "tracef",
+            HostCall::TraceUtf8 => "traceUtf8",
+            HostCall::TraceUtf16 => "traceUtf16",
+            HostCall::Blit => "blit",
+            HostCall::BlitSub => "blitSub",
+            HostCall::Line => "line",
+            HostCall::HLine => "hline",
+            HostCall::VLine => "vline",
+            HostCall::Oval => "oval",
+            HostCall::Rect => "rect",
+            HostCall::Text => "text",
+            HostCall::TextUtf8 => "textUtf8",
+            HostCall::TextUtf16 => "textUtf16",
+            HostCall::DiskR => "diskr",
+            HostCall::DiskW => "diskw",
+            HostCall::Tone => "tone",
+        }
+    }
+}
+
+/// Number of calls per [`HostCall`], indexed like [`HostCall::ALL`].
+pub type HostCallCounts = [u32; 17];
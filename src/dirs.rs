@@ -0,0 +1,60 @@
+//! Per-user directories, for the saves of standalone games and for caches.
+
+use std::{env, fs, path::PathBuf};
+
+use anyhow::anyhow;
+
+/// The directory to keep `app`'s data in, like saves. It's created if it
+/// doesn't exist yet.
+///
+/// That's `$XDG_DATA_HOME/<app>` (or `~/.local/share/<app>`) on Linux,
+/// `~/Library/Application Support/<app>` on macOS and `%APPDATA%\<app>` on
+/// Windows.
+pub fn data_dir(app: &str) -> anyhow::Result<PathBuf> {
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| home().map(|home| home.join(".local").join("share")))
+    };
+
+    create(base, app)
+}
+
+/// The directory to keep `app`'s caches in, which can be deleted at any
+/// time. It's created if it doesn't exist yet.
+///
+/// That's `$XDG_CACHE_HOME/<app>` (or `~/.cache/<app>`) on Linux,
+/// `~/Library/Caches/<app>` on macOS and `%LOCALAPPDATA%\<app>` on Windows.
+pub fn cache_dir(app: &str) -> anyhow::Result<PathBuf> {
+    let base = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| home().map(|home| home.join(".cache")))
+    };
+
+    create(base, app)
+}
+
+fn create(base: Option<PathBuf>, app: &str) -> anyhow::Result<PathBuf> {
+    let dir = base
+        .ok_or_else(|| anyhow!("can't find the home directory"))?
+        .join(app);
+    fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+/// An absolute path from the environment variable `name`.
+fn env_dir(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+fn home() -> Option<PathBuf> {
+    env_dir(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+}
@@ -0,0 +1,166 @@
+//! Integration with [`embedded_graphics_core`].
+//!
+//! [`Framebuffer`] lets host code draw into a WASM-4 framebuffer with any
+//! embedded-graphics drawable, and [`present`] shows a cart's screen on any
+//! [`DrawTarget`] with [`Rgb565`] colors, like most SPI LCD drivers.
+
+use core::convert::Infallible;
+
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{Gray2, GrayColor, Rgb565, Rgb888},
+    primitives::Rectangle,
+    Pixel,
+};
+
+use crate::core::{
+    framebuffer,
+    wasm4::{FRAMEBUFFER_SIZE, SCREEN_SIZE},
+    Sink, Source,
+};
+
+pub use embedded_graphics_core;
+
+/// A [`DrawTarget`] over a 2bpp WASM-4 framebuffer.
+///
+/// Colors are palette indices, so [`Gray2::new(0)`](Gray2::new) draws with the
+/// first palette color and `Gray2::new(3)` with the last. Pixels outside of
+/// the screen are clipped.
+pub struct Framebuffer<'a, T: Source<u8> + Sink<u8>> {
+    fb: &'a mut T,
+}
+
+impl<'a, T: Source<u8> + Sink<u8>> Framebuffer<'a, T> {
+    pub fn new(fb: &'a mut T) -> Self {
+        Self { fb }
+    }
+}
+
+impl<'a, T: Source<u8> + Sink<u8>> OriginDimensions for Framebuffer<'a, T> {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_SIZE, SCREEN_SIZE)
+    }
+}
+
+impl<'a, T: Source<u8> + Sink<u8>> DrawTarget for Framebuffer<'a, T> {
+    type Color = Gray2;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            framebuffer::set_pixel_unclipped(self.fb, point.x, point.y, color.luma());
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        // every byte holds four pixels.
+        self.fb.fill(color.luma() * 0b01010101);
+
+        Ok(())
+    }
+}
+
+/// Draw a cart's framebuffer with its palette onto `display`, with the
+/// screen's top left corner at `top_left`.
+///
+/// `framebuffer` and `palette` are read with [`Backend::read_screen`](crate::Backend::read_screen).
+pub fn present<D>(
+    display: &mut D,
+    top_left: Point,
+    framebuffer: &[u8; FRAMEBUFFER_SIZE],
+    palette: &[u8; 16],
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let colors: [Rgb565; 4] = core::array::from_fn(|n| {
+        let color = &palette[n * 4..n * 4 + 4];
+        Rgb888::new(color[2], color[1], color[0]).into()
+    });
+
+    let pixels = framebuffer
+        .iter()
+        .flat_map(|byte| (0..4).map(move |n| (byte >> (n * 2)) & 0b11))
+        .map(|idx| colors[idx as usize]);
+
+    display.fill_contiguous(
+        &Rectangle::new(top_left, Size::new(SCREEN_SIZE, SCREEN_SIZE)),
+        pixels,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics_core::{
+        draw_target::DrawTarget,
+        geometry::{OriginDimensions, Point, Size},
+        pixelcolor::{Gray2, Rgb565, RgbColor},
+        Pixel,
+    };
+
+    use super::{present, Framebuffer};
+    use crate::core::{
+        utils,
+        wasm4::{FRAMEBUFFER_SIZE, SCREEN_SIZE},
+    };
+
+    /// An in-memory [`Rgb565`] display.
+    struct Display(Vec<Rgb565>);
+
+    impl OriginDimensions for Display {
+        fn size(&self) -> Size {
+            Size::new(SCREEN_SIZE, SCREEN_SIZE)
+        }
+    }
+
+    impl DrawTarget for Display {
+        type Color = Rgb565;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            for Pixel(point, color) in pixels {
+                self.0[point.y as usize * SCREEN_SIZE as usize + point.x as usize] = color;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn draw_and_present() {
+        let mut fb = utils::default_framebuffer();
+        let mut target = Framebuffer::new(&mut fb);
+
+        target.clear(Gray2::new(1)).unwrap();
+        target
+            .draw_iter([
+                Pixel(Point::new(1, 0), Gray2::new(3)),
+                Pixel(Point::new(-1, 0), Gray2::new(3)),
+                Pixel(Point::new(0, SCREEN_SIZE as i32), Gray2::new(3)),
+            ])
+            .unwrap();
+
+        assert_eq!(fb[0], 0b01_01_11_01);
+        assert!(fb[1..FRAMEBUFFER_SIZE].iter().all(|b| *b == 0b01_01_01_01));
+
+        let mut palette = [0; 16];
+        palette[4..8].copy_from_slice(&[0x00, 0x00, 0xff, 0x00]);
+        palette[12..16].copy_from_slice(&[0xff, 0x00, 0x00, 0x00]);
+
+        let mut display = Display(vec![Rgb565::BLACK; FRAMEBUFFER_SIZE * 4]);
+        present(&mut display, Point::zero(), &fb, &palette).unwrap();
+
+        assert_eq!(display.0[0], Rgb565::RED);
+        assert_eq!(display.0[1], Rgb565::BLUE);
+        assert!(display.0[2..].iter().all(|c| *c == Rgb565::RED));
+    }
+}
@@ -25,16 +25,52 @@ pub fn launch(
     let res = launch_web(backend, title, display_scale);
 
     #[cfg(not(target_arch = "wasm32"))]
-    let res = launch_desktop(backend, title, display_scale);
+    let res = {
+        let meta = crate::core::meta::CartMeta {
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+        launch_window(backend, &meta, display_scale)
+    };
 
     res
 }
 
-/// Launch a game window in a desktop window.
+/// Launch a game in a desktop window, with the title and icon in `meta`. The
+/// save is kept next to `path`, see [`CartMeta::save_file`](crate::core::meta::CartMeta::save_file).
 #[cfg(not(target_arch = "wasm32"))]
 pub fn launch_desktop(
     backend: impl Backend + 'static,
-    title: &str,
+    path: &std::path::Path,
+    meta: &crate::core::meta::CartMeta,
+    display_scale: u32,
+) -> anyhow::Result<()> {
+    let save = meta.read_save(path).ok();
+    let backend = SaveFile::new(backend, meta.save_file(path), save);
+
+    launch_window(backend, meta, display_scale)
+}
+
+/// Launch a game in a desktop window like [`launch_desktop`], keeping its
+/// save in `save_file`, for games that don't come from a cart file.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn launch_desktop_with_save_file(
+    backend: impl Backend + 'static,
+    save_file: &std::path::Path,
+    meta: &crate::core::meta::CartMeta,
+    display_scale: u32,
+) -> anyhow::Result<()> {
+    let save = std::fs::read(save_file).ok();
+    let backend = SaveFile::new(backend, save_file.to_path_buf(), save);
+
+    launch_window(backend, meta, display_scale)
+}
+
+/// Launch a game in a desktop window, with the title and icon in `meta`.
+#[cfg(not(target_arch = "wasm32"))]
+fn launch_window(
+    backend: impl Backend + 'static,
+    meta: &crate::core::meta::CartMeta,
     display_scale: u32,
 ) -> anyhow::Result<()> {
     let event_loop = EventLoop::new();
@@ -43,8 +79,15 @@ pub fn launch_desktop(
             wasm4::SCREEN_SIZE as f64 * display_scale as f64,
             wasm4::SCREEN_SIZE as f64 * display_scale as f64,
         );
+        let icon = meta.icon.as_ref().and_then(|icon| {
+            winit::window::Icon::from_rgba(icon.to_rgba(), icon.width, icon.height)
+                .map_err(|err| log::error!("failed to set the window icon: {err}"))
+                .ok()
+        });
+
         WindowBuilder::new()
-            .with_title(title)
+            .with_title(meta.title.as_deref().unwrap_or("wasmstation"))
+            .with_window_icon(icon)
             .with_inner_size(size)
             .with_min_inner_size(size)
             .build(&event_loop)?
@@ -133,6 +176,16 @@ pub fn launch_custom<T>(
                     control_flow.set_exit();
                 }
                 WindowEvent::KeyboardInput { input, .. } => {
+                    if input.virtual_keycode == Some(VirtualKeyCode::R)
+                        && input.state == ElementState::Pressed
+                    {
+                        match backend.reset() {
+                            Ok(()) => backend.call_start(),
+                            Err(err) => log::error!("failed to reset cart: {err}"),
+                        }
+                        return;
+                    }
+
                     let [gamepad1, gamepad2, gamepad3, _] =
                         bytemuck::cast_mut::<u32, [u8; 4]>(&mut gamepads);
 
@@ -228,6 +281,79 @@ pub fn launch_custom<T>(
     });
 }
 
+/// Keeps the saves of a backend in a file, written after every update.
+#[cfg(not(target_arch = "wasm32"))]
+struct SaveFile<B> {
+    backend: B,
+    path: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B: Backend> SaveFile<B> {
+    fn new(mut backend: B, path: std::path::PathBuf, save: Option<Vec<u8>>) -> Self {
+        if let Some(save) = save {
+            backend.set_save_cache(crate::core::Disk::new(&save));
+        }
+
+        Self { backend, path }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B: Backend> Backend for SaveFile<B> {
+    fn call_update(&mut self) {
+        self.backend.call_update();
+
+        if let Some(disk) = self.backend.write_save_cache() {
+            if let Err(err) = std::fs::write(&self.path, disk.as_bytes()) {
+                log::error!("failed to write {}: {err}", self.path.display());
+            }
+        }
+    }
+
+    fn call_start(&mut self) {
+        self.backend.call_start();
+    }
+
+    fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
+        self.backend.read_screen(framebuffer, palette);
+    }
+
+    fn read_system_flags(&self) -> u8 {
+        self.backend.read_system_flags()
+    }
+
+    fn set_gamepad(&mut self, gamepad: u32) {
+        self.backend.set_gamepad(gamepad);
+    }
+
+    fn set_mouse(&mut self, x: i16, y: i16, buttons: u8) {
+        self.backend.set_mouse(x, y, buttons);
+    }
+
+    /// Saves are written to the file after every update, so there is never
+    /// anything left to write.
+    fn write_save_cache(&mut self) -> Option<crate::core::Disk> {
+        None
+    }
+
+    fn set_save_cache(&mut self, disk: crate::core::Disk) {
+        self.backend.set_save_cache(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        self.backend.reset()
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        self.backend.read_memory(offset, buf);
+    }
+
+    fn take_host_calls(&mut self) -> wasm4::HostCallCounts {
+        self.backend.take_host_calls()
+    }
+}
+
 #[inline(always)]
 fn read_framebuffer(
     pixels: &mut [u8],
@@ -1,11 +1,17 @@
 #![doc = include_str!("../README.md")]
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 pub mod core;
 
 #[doc(inline)]
 pub use crate::core::{Api, Backend, Console, Sink, Source};
 
+#[cfg(feature = "std")]
+pub mod dirs;
+
 #[cfg(feature = "wasmer")]
 pub mod wasmer_backend;
 
@@ -20,8 +26,28 @@ pub mod wasmi_backend;
 #[doc(inline)]
 pub use crate::wasmi_backend::WasmiBackend;
 
+#[cfg(feature = "wasmtime")]
+pub mod wasmtime_backend;
+
+#[cfg(feature = "wasmtime")]
+#[doc(inline)]
+pub use crate::wasmtime_backend::WasmtimeBackend;
+
+#[cfg(feature = "wasm3")]
+pub mod wasm3_backend;
+
+#[cfg(feature = "wasm3")]
+#[doc(inline)]
+pub use crate::wasm3_backend::Wasm3Backend;
+
 #[cfg(feature = "sdl2-renderer")]
 pub mod sdl2_renderer;
 
 #[cfg(feature = "gpu-renderer")]
 pub mod gpu_renderer;
+
+#[cfg(feature = "term-renderer")]
+pub mod term_renderer;
+
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded_graphics;
@@ -0,0 +1,558 @@
+//! A launcher for a directory of carts, shown as a grid of thumbnails or by a
+//! system cart.
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use byteorder::{ByteOrder, LittleEndian};
+use sdl2::{event::Event, keyboard::Keycode, mouse::MouseButton, pixels::PixelFormatEnum};
+
+use super::{
+    boot, bounding_rect, cart_name, framebuffer_to_rgb24, play, Display, Exit, TARGET_MS_PER_FRAME,
+    TEXTURE_LENGTH,
+};
+use crate::core::{
+    framebuffer::{charset_byte, Canvas, Screen},
+    meta::CartMeta,
+    system::{System, Thumbnail},
+    utils,
+    wasm4::SCREEN_SIZE,
+    Backend, Console,
+};
+
+/// How long carts run before their screen is taken as a thumbnail, in frames.
+const THUMBNAIL_FRAMES: usize = 180;
+
+/// The size of the launcher's screen, in pixels.
+const SIZE: usize = 480;
+/// The size of a cart's cell in the grid.
+const CELL: usize = 160;
+const COLUMNS: usize = SIZE / CELL;
+const PAGE_LEN: usize = COLUMNS * COLUMNS;
+/// The size of a thumbnail, scaled down from the cart's screen.
+const THUMBNAIL: usize = 120;
+/// The longest name that fits in a cell.
+const NAME_LEN: usize = (CELL - 16) / 8;
+
+/// Remembers when each cart was last played, next to the carts.
+const PLAYED_FILE: &str = ".played";
+/// The settings of system carts, next to the carts.
+const SETTINGS_FILE: &str = ".settings";
+
+/// The launcher's palette, the default WASM-4 palette from dark to light.
+const PALETTE: [u32; 4] = [0x071821, 0x306850, 0x86c06c, 0xe0f8cf];
+
+/// Browse the `.wasm` carts in `dir` and play them in a SDL2 window.
+///
+/// Every cart is run without a window for a few seconds first, to show its
+/// screen as a thumbnail. Arrow keys (or the mouse) select a cart and `X` or
+/// enter starts it. Escape returns from a cart to the launcher.
+///
+/// With a `menu`, that cart is run as a system cart instead of the built-in
+/// grid, see [`system`](crate::core::system). It's left out of the carts.
+pub fn launch_library<B, F>(
+    dir: &Path,
+    display_scale: u32,
+    menu: Option<&Path>,
+    load: F,
+) -> anyhow::Result<()>
+where
+    B: Backend,
+    F: Fn(&[u8], &Console) -> anyhow::Result<B>,
+{
+    let library = Arc::new(Library::scan(dir, menu, &load)?);
+
+    let title = format!("wasmstation - {}", cart_name(dir));
+    let mut display = Display::new(&title, display_scale)?;
+    let mut launcher = Launcher {
+        display: &mut display,
+        library: &library,
+        console: Console::default(),
+        title: &title,
+        load: &load,
+    };
+
+    match menu {
+        Some(menu) => launcher.menu(menu),
+        None => launcher.grid(),
+    }
+}
+
+struct Launcher<'a, F> {
+    display: &'a mut Display,
+    library: &'a Arc<Library>,
+    /// Plays the carts, as opposed to the menu.
+    console: Console,
+    title: &'a str,
+    load: &'a F,
+}
+
+impl<'a, B, F> Launcher<'a, F>
+where
+    B: Backend,
+    F: Fn(&[u8], &Console) -> anyhow::Result<B>,
+{
+    /// Let a system cart choose the carts to play.
+    fn menu(&mut self, path: &Path) -> anyhow::Result<()> {
+        let console = Console::default().with_system(self.library.clone());
+        let bytes = fs::read(path)?;
+        let mut menu = (self.load)(&bytes, &console)?;
+
+        let meta = CartMeta::from_wasm(&bytes).unwrap_or_default();
+        let save_file = meta.save_file(path);
+        boot(&mut menu, meta.read_save(path).ok());
+
+        loop {
+            match play(
+                self.display,
+                &mut menu,
+                &save_file,
+                false,
+                Some(self.library),
+            )? {
+                Exit::Launch(idx) => {
+                    if self.play(idx)? == Exit::Quit {
+                        return Ok(());
+                    }
+                }
+                Exit::Quit | Exit::Back => return Ok(()),
+            }
+        }
+    }
+
+    /// Show the carts in a grid to choose from.
+    fn grid(&mut self) -> anyhow::Result<()> {
+        let texture_creator = self.display.canvas.texture_creator();
+        let mut texture = texture_creator.create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            SIZE as u32,
+            SIZE as u32,
+        )?;
+        let mut rgb = vec![0; SIZE * SIZE * 3];
+        let mut grid = Grid { selected: 0 };
+
+        loop {
+            let start = Instant::now();
+
+            let mut selected = None;
+            let carts = self.library.carts.len();
+            let window_size = self.display.canvas.window().size();
+            for event in self.display.event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => return Ok(()),
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => match keycode {
+                        Keycode::Left => grid.select(-1, carts),
+                        Keycode::Right => grid.select(1, carts),
+                        Keycode::Up => grid.select(-(COLUMNS as isize), carts),
+                        Keycode::Down => grid.select(COLUMNS as isize, carts),
+                        Keycode::X | Keycode::Return | Keycode::Space => {
+                            selected = Some(grid.selected)
+                        }
+                        _ => (),
+                    },
+                    Event::MouseButtonDown {
+                        mouse_btn: MouseButton::Left,
+                        x,
+                        y,
+                        ..
+                    } => selected = grid.cart_at(window_size, x, y, carts),
+                    _ => (),
+                }
+            }
+
+            if let Some(idx) = selected {
+                grid.selected = idx;
+
+                if self.play(idx)? == Exit::Quit {
+                    return Ok(());
+                }
+            }
+
+            grid.draw(self.library, &mut rgb);
+            self.display.present(&mut texture, &rgb)?;
+
+            thread::sleep(start + TARGET_MS_PER_FRAME - Instant::now());
+        }
+    }
+
+    /// Play the cart at `idx` until it's quit or escape is pressed.
+    fn play(&mut self, idx: usize) -> anyhow::Result<Exit> {
+        let cart = &self.library.carts[idx];
+        let path = &cart.path;
+        let mut backend = match fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| (self.load)(&bytes, &self.console))
+        {
+            Ok(backend) => backend,
+            Err(err) => {
+                log::error!("failed to load {}: {err}", path.display());
+                return Ok(Exit::Back);
+            }
+        };
+
+        self.library.played(idx);
+        self.display
+            .set_title(&format!("wasmstation - {}", cart.name));
+
+        let save_file = cart.meta.save_file(path);
+        boot(&mut backend, cart.meta.read_save(path).ok());
+        let exit = play(self.display, &mut backend, &save_file, true, None)?;
+
+        self.display.set_title(self.title);
+
+        Ok(exit)
+    }
+}
+
+/// A cart in the launcher.
+struct Cart {
+    path: PathBuf,
+    /// The cart's title, or a name made from its file name.
+    name: String,
+    meta: CartMeta,
+    /// The cart's screen after [`THUMBNAIL_FRAMES`].
+    thumbnail: Option<Thumbnail>,
+}
+
+/// The carts in a directory, shared with system carts.
+pub(super) struct Library {
+    dir: PathBuf,
+    carts: Vec<Cart>,
+    /// When each cart was last played, by file name, in seconds since the
+    /// unix epoch.
+    played: Mutex<HashMap<String, u64>>,
+    settings: Mutex<HashMap<String, Vec<u8>>>,
+    /// A cart that a system cart asked to launch.
+    launch: Mutex<Option<usize>>,
+}
+
+impl Library {
+    /// Find the carts in `dir`, except for `menu`, and make their thumbnails.
+    fn scan<B, F>(dir: &Path, menu: Option<&Path>, load: &F) -> anyhow::Result<Self>
+    where
+        B: Backend,
+        F: Fn(&[u8], &Console) -> anyhow::Result<B>,
+    {
+        let menu = menu.and_then(|menu| fs::canonicalize(menu).ok());
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension() == Some(OsStr::new("wasm")))
+            .filter(|path| menu.is_none() || fs::canonicalize(path).ok() != menu)
+            .collect();
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(anyhow!("no .wasm carts in {}", dir.display()));
+        }
+
+        // thumbnails are made without sound or trace output.
+        let console = Console::muted(Box::new(|_| ()));
+
+        let carts = paths
+            .into_iter()
+            .map(|path| {
+                let bytes = fs::read(&path);
+                let thumbnail = bytes
+                    .as_ref()
+                    .map_err(|err| anyhow!("{err}"))
+                    .and_then(|bytes| load(bytes, &console))
+                    .map(thumbnail);
+
+                if let Err(err) = &thumbnail {
+                    log::error!("failed to load {}: {err}", path.display());
+                }
+
+                let meta = bytes
+                    .ok()
+                    .and_then(|bytes| CartMeta::from_wasm(&bytes).ok())
+                    .unwrap_or_default();
+                Cart {
+                    name: meta.title.clone().unwrap_or_else(|| cart_name(&path)),
+                    meta,
+                    thumbnail: thumbnail.ok(),
+                    path,
+                }
+            })
+            .collect();
+
+        let played = read_map(&dir.join(PLAYED_FILE), |secs| secs.parse().ok());
+        let settings = read_map(&dir.join(SETTINGS_FILE), utils::from_hex);
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            carts,
+            played: Mutex::new(played),
+            settings: Mutex::new(settings),
+            launch: Mutex::new(None),
+        })
+    }
+
+    fn file_name(&self, idx: usize) -> Option<String> {
+        let name = self.carts.get(idx)?.path.file_name()?;
+
+        Some(name.to_string_lossy().to_string())
+    }
+
+    /// When the cart at `idx` was last played.
+    fn played_at(&self, idx: usize) -> Option<u64> {
+        lock(&self.played).get(&self.file_name(idx)?).copied()
+    }
+
+    /// Remember that the cart at `idx` was played now.
+    fn played(&self, idx: usize) {
+        let Some(name) = self.file_name(idx) else {
+            return;
+        };
+
+        let mut played = lock(&self.played);
+        played.insert(name, unix_time(SystemTime::now()));
+
+        if let Err(err) = write_map(&self.dir.join(PLAYED_FILE), &played, |secs| {
+            secs.to_string()
+        }) {
+            log::error!("failed to write {PLAYED_FILE}: {err}");
+        }
+    }
+
+    /// The cart a system cart asked to launch since the last call.
+    pub(super) fn take_launch(&self) -> Option<usize> {
+        lock(&self.launch).take()
+    }
+}
+
+impl System for Library {
+    fn cart_count(&self) -> usize {
+        self.carts.len()
+    }
+
+    fn cart_meta(&self, index: usize, key: &str) -> Option<String> {
+        let cart = self.carts.get(index)?;
+
+        match key {
+            "name" => Some(cart.name.clone()),
+            "title" => cart.meta.title.clone(),
+            "author" => cart.meta.author.clone(),
+            "description" => cart.meta.description.clone(),
+            "version" => cart.meta.version.clone(),
+            "file" => self.file_name(index),
+            "played" => self.played_at(index).map(|secs| secs.to_string()),
+            _ => None,
+        }
+    }
+
+    fn cart_thumbnail(&self, index: usize) -> Option<Thumbnail> {
+        self.carts.get(index)?.thumbnail.clone()
+    }
+
+    fn launch(&self, index: usize) {
+        if index < self.carts.len() {
+            *lock(&self.launch) = Some(index);
+        } else {
+            log::error!("launch: there is no cart {index}");
+        }
+    }
+
+    fn setting(&self, key: &str) -> Option<Vec<u8>> {
+        lock(&self.settings).get(key).cloned()
+    }
+
+    fn set_setting(&self, key: &str, value: &[u8]) {
+        let mut settings = lock(&self.settings);
+        settings.insert(key.to_string(), value.to_vec());
+
+        if let Err(err) = write_map(&self.dir.join(SETTINGS_FILE), &settings, |v| {
+            utils::to_hex(v)
+        }) {
+            log::error!("failed to write {SETTINGS_FILE}: {err}");
+        }
+    }
+}
+
+/// The built-in menu, a grid of carts.
+struct Grid {
+    selected: usize,
+}
+
+impl Grid {
+    /// Move the selection by `offset`, staying on the first or last of `carts`.
+    fn select(&mut self, offset: isize, carts: usize) {
+        let selected = self.selected as isize + offset;
+
+        self.selected = selected.clamp(0, carts as isize - 1) as usize;
+    }
+
+    /// The cart at window coordinates `x` and `y`, if there is one.
+    fn cart_at(&self, window_size: (u32, u32), x: i32, y: i32, carts: usize) -> Option<usize> {
+        let bounds = bounding_rect(&sdl2::rect::Rect::new(0, 0, window_size.0, window_size.1));
+        if !bounds.contains_point((x, y)) {
+            return None;
+        }
+
+        let scale = |v: i32, start: i32| (v - start) as usize * SIZE / bounds.width() as usize;
+        let (x, y) = (scale(x, bounds.x()), scale(y, bounds.y()));
+
+        let idx = self.page() * PAGE_LEN + y / CELL * COLUMNS + x / CELL;
+        (idx < carts).then_some(idx)
+    }
+
+    /// The page of the grid that's shown.
+    fn page(&self) -> usize {
+        self.selected / PAGE_LEN
+    }
+
+    /// Draw the current page of the grid as RGB24.
+    fn draw(&self, library: &Library, rgb: &mut [u8]) {
+        let mut fb = vec![0u8; SIZE * SIZE / 4];
+        let mut canvas = Canvas::new(&mut fb, SIZE as u32, SIZE as u32);
+
+        let first = self.page() * PAGE_LEN;
+        let carts = library.carts.iter().enumerate().skip(first).take(PAGE_LEN);
+
+        for (idx, cart) in carts.clone() {
+            let (x, y) = cell_position(idx - first);
+
+            if idx == self.selected {
+                canvas.rect(0x30, x + 2, y + 2, CELL as u32 - 4, CELL as u32 - 4);
+            }
+            if cart.thumbnail.is_none() {
+                canvas.text(b"no preview", x + 40, y + 64, 0x2);
+            }
+
+            let name: Vec<u8> = cart.name.chars().take(NAME_LEN).map(charset_byte).collect();
+            canvas.text(&name, x + 8, y + 136, 0x4);
+
+            let played = played_ago(library.played_at(idx));
+            canvas.text(played.as_bytes(), x + 8, y + 146, 0x3);
+        }
+
+        let mut palette = [0; 16];
+        LittleEndian::write_u32_into(&PALETTE, &mut palette);
+        framebuffer_to_rgb24(&fb, &palette, rgb);
+
+        let mut screen = vec![0; TEXTURE_LENGTH];
+        for (idx, cart) in carts {
+            if let Some(thumbnail) = &cart.thumbnail {
+                framebuffer_to_rgb24(&thumbnail.framebuffer, &thumbnail.palette, &mut screen);
+
+                let (x, y) = cell_position(idx - first);
+                draw_thumbnail(rgb, &screen, x as usize + 20, y as usize + 8);
+            }
+        }
+    }
+}
+
+/// The top left corner of the cell at `slot` on a page.
+fn cell_position(slot: usize) -> (i32, i32) {
+    (
+        (slot % COLUMNS * CELL) as i32,
+        (slot / COLUMNS * CELL) as i32,
+    )
+}
+
+/// Run a cart for [`THUMBNAIL_FRAMES`] and take its screen.
+fn thumbnail(mut backend: impl Backend) -> Thumbnail {
+    backend.call_start();
+    for _ in 0..THUMBNAIL_FRAMES {
+        backend.call_update();
+    }
+
+    let mut thumbnail = Thumbnail {
+        framebuffer: utils::default_framebuffer(),
+        palette: utils::default_palette(),
+    };
+    backend.read_screen(&mut thumbnail.framebuffer, &mut thumbnail.palette);
+
+    thumbnail
+}
+
+/// Copy a cart's RGB24 screen into the launcher's screen, scaled down to
+/// [`THUMBNAIL`].
+fn draw_thumbnail(rgb: &mut [u8], screen_rgb: &[u8], x: usize, y: usize) {
+    let screen = SCREEN_SIZE as usize;
+
+    for ty in 0..THUMBNAIL {
+        for tx in 0..THUMBNAIL {
+            let src = (ty * screen / THUMBNAIL * screen + tx * screen / THUMBNAIL) * 3;
+            let dst = ((y + ty) * SIZE + x + tx) * 3;
+
+            rgb[dst..dst + 3].copy_from_slice(&screen_rgb[src..src + 3]);
+        }
+    }
+}
+
+/// Describe how long ago a cart was played, like "3 days ago".
+fn played_ago(played: Option<u64>) -> String {
+    let Some(played) = played else {
+        return "never played".to_string();
+    };
+
+    let secs = unix_time(SystemTime::now()).saturating_sub(played);
+
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        86400..=172799 => "1 day ago".to_string(),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Lock a mutex, even if another thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Read a file with a key and a value on every line, separated by the last
+/// space. Lines with values that don't `parse` are skipped.
+fn read_map<T>(path: &Path, parse: impl Fn(&str) -> Option<T>) -> HashMap<String, T> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.rsplit_once(' ')?;
+
+            Some((key.to_string(), parse(value)?))
+        })
+        .collect()
+}
+
+fn write_map<T>(
+    path: &Path,
+    map: &HashMap<String, T>,
+    format: impl Fn(&T) -> String,
+) -> anyhow::Result<()> {
+    let mut contents = String::new();
+
+    for (key, value) in map {
+        contents.push_str(&format!("{key} {}\n", format(value)));
+    }
+
+    fs::write(path, contents)?;
+
+    Ok(())
+}
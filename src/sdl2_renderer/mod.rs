@@ -0,0 +1,437 @@
+//! A software renderer based on [SDL2](sdl2) renderer.
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use log::debug;
+use palette::Srgb;
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    mouse::MouseButton,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture},
+    surface::Surface,
+    video::Window,
+    EventPump, Sdl,
+};
+
+use crate::core::{
+    meta::{CartMeta, Icon},
+    utils,
+    wasm4::{
+        BUTTON_1, BUTTON_2, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_UP, FRAMEBUFFER_SIZE,
+        MOUSE_LEFT, MOUSE_MIDDLE, MOUSE_RIGHT, SCREEN_SIZE,
+    },
+    Backend, Disk,
+};
+
+const TARGET_FPS: f32 = 60.0;
+const TARGET_MS_PER_FRAME: Duration = Duration::from_millis((1000.0 / TARGET_FPS) as u64);
+
+const SCREEN_LENGTH: usize = (SCREEN_SIZE * SCREEN_SIZE) as usize;
+const TEXTURE_LENGTH: usize = SCREEN_LENGTH * 3;
+
+pub use sdl2;
+
+mod library;
+
+use library::Library;
+
+pub use library::launch_library;
+
+/// Launch a game in a SDL2 window.
+///
+/// The window is named after the title in `meta`, or the file at `path`, and
+/// the save is kept in the cart's [`CartMeta::save_file`].
+pub fn launch_desktop(
+    mut backend: impl Backend,
+    path: &Path,
+    meta: &CartMeta,
+    display_scale: u32,
+) -> anyhow::Result<()> {
+    let title = meta.title.clone().unwrap_or_else(|| cart_name(path));
+    let mut display = Display::for_cart(&title, meta, display_scale)?;
+
+    let save_file = meta.save_file(path);
+    boot(&mut backend, meta.read_save(path).ok());
+    play(&mut display, &mut backend, &save_file, false, None)?;
+
+    Ok(())
+}
+
+/// Launch a game in a SDL2 window, keeping its save in `save_file`, for games
+/// that don't come from a cart file.
+///
+/// The window is named after the title in `meta`.
+pub fn launch_desktop_with_save_file(
+    mut backend: impl Backend,
+    save_file: &Path,
+    meta: &CartMeta,
+    display_scale: u32,
+) -> anyhow::Result<()> {
+    let title = meta.title.as_deref().unwrap_or("wasmstation");
+    let mut display = Display::for_cart(title, meta, display_scale)?;
+
+    boot(&mut backend, fs::read(save_file).ok());
+    play(&mut display, &mut backend, save_file, false, None)?;
+
+    Ok(())
+}
+
+/// An SDL2 window, which can show one cart after another.
+struct Display {
+    canvas: Canvas<Window>,
+    event_pump: EventPump,
+    _sdl: Sdl,
+}
+
+impl Display {
+    fn new(title: &str, display_scale: u32) -> anyhow::Result<Self> {
+        let sdl = sdl2::init().map_err(|s| anyhow!("{s}"))?;
+        let mut window = sdl
+            .video()
+            .map_err(|x| anyhow!("{x}"))?
+            .window(
+                title,
+                SCREEN_SIZE * display_scale,
+                SCREEN_SIZE * display_scale,
+            )
+            .position_centered()
+            .resizable()
+            .build()?;
+        window.set_minimum_size(SCREEN_SIZE, SCREEN_SIZE)?;
+
+        let event_pump = sdl.event_pump().map_err(|x| anyhow!("{x}"))?;
+
+        let mut canvas = window.into_canvas().build()?;
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas.present();
+
+        Ok(Self {
+            canvas,
+            event_pump,
+            _sdl: sdl,
+        })
+    }
+
+    /// A window for a single cart, named `title` and with the icon in `meta`.
+    fn for_cart(title: &str, meta: &CartMeta, display_scale: u32) -> anyhow::Result<Self> {
+        let mut display = Self::new(&format!("wasmstation - {title}"), display_scale)?;
+        if let Some(icon) = &meta.icon {
+            display.set_icon(icon);
+        }
+
+        Ok(display)
+    }
+
+    fn set_title(&mut self, title: &str) {
+        if let Err(err) = self.canvas.window_mut().set_title(title) {
+            log::error!("failed to set the window title: {err}");
+        }
+    }
+
+    fn set_icon(&mut self, icon: &Icon) {
+        let mut rgba = icon.to_rgba();
+        let surface = Surface::from_data(
+            &mut rgba,
+            icon.width,
+            icon.height,
+            icon.width * 4,
+            PixelFormatEnum::RGBA32,
+        );
+
+        match surface {
+            Ok(surface) => self.canvas.window_mut().set_icon(surface),
+            Err(err) => log::error!("failed to set the window icon: {err}"),
+        }
+    }
+
+    /// Show `texture`, updated with the RGB24 pixels in `rgb`, scaled to fit
+    /// the window.
+    fn present(&mut self, texture: &mut Texture, rgb: &[u8]) -> anyhow::Result<()> {
+        let width = texture.query().width as usize;
+
+        self.canvas.clear();
+        texture.update(None, rgb, width * 3)?;
+        self.canvas
+            .copy(texture, None, bounding_rect(&self.canvas.viewport()))
+            .map_err(|s| anyhow!("{s}"))?;
+        self.canvas.present();
+
+        Ok(())
+    }
+}
+
+/// How a cart's game loop ended.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    /// The window was closed.
+    Quit,
+    /// Escape was pressed, to go back to the launcher.
+    Back,
+    /// A system cart launched the cart at this index of the [`Library`].
+    Launch(usize),
+}
+
+/// Load the cart's `save`, if it has one, and call its `start()`.
+fn boot(backend: &mut impl Backend, save: Option<Vec<u8>>) {
+    if let Some(data) = save {
+        backend.set_save_cache(Disk::new(&data));
+    }
+
+    backend.call_start();
+}
+
+/// Run a started cart in `display` until the window is closed, until escape
+/// is pressed if `can_go_back` is set, or until it launches a cart from
+/// `library` if it's a system cart.
+fn play(
+    display: &mut Display,
+    backend: &mut impl Backend,
+    save_file: &Path,
+    can_go_back: bool,
+    library: Option<&Library>,
+) -> anyhow::Result<Exit> {
+    let texture_creator = display.canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(
+        PixelFormatEnum::RGB24,
+        SCREEN_SIZE,
+        SCREEN_SIZE,
+    )?;
+
+    let mut mouse: (i16, i16) = (0, 0);
+    let mut mouse_buttons: u8 = 0;
+    let mut gamepads: u32 = 0;
+
+    let mut framebuffer: [u8; FRAMEBUFFER_SIZE] = utils::default_framebuffer();
+    let mut palette: [u8; 16] = utils::default_palette();
+    let mut rgb = vec![0; TEXTURE_LENGTH];
+
+    loop {
+        let start = Instant::now();
+
+        // update input
+        let window_size = display.canvas.window().size();
+        for event in display.event_pump.poll_iter() {
+            match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    repeat: false,
+                    ..
+                } => reset(backend),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if can_go_back => return Ok(Exit::Back),
+                _ => (),
+            }
+
+            if handle_input(
+                event,
+                &mut gamepads,
+                &mut mouse,
+                &mut mouse_buttons,
+                window_size,
+            ) {
+                return Ok(Exit::Quit);
+            }
+        }
+        backend.set_gamepad(gamepads);
+        backend.set_mouse(mouse.0, mouse.1, mouse_buttons);
+
+        // update state
+        backend.call_update();
+        if let Some(disk) = backend.write_save_cache() {
+            write_save(disk, save_file)?;
+        }
+        if let Some(idx) = library.and_then(Library::take_launch) {
+            return Ok(Exit::Launch(idx));
+        }
+
+        // update screen
+        backend.read_screen(&mut framebuffer, &mut palette);
+        framebuffer_to_rgb24(&framebuffer, &palette, &mut rgb);
+        display.present(&mut texture, &rgb)?;
+
+        thread::sleep(start + TARGET_MS_PER_FRAME - Instant::now());
+        debug!(
+            "game loop took {} ms",
+            Instant::now().saturating_duration_since(start).as_millis()
+        );
+    }
+}
+
+/// A readable name for the cart at `path`, like "tiny golf" for `tiny-golf.wasm`.
+fn cart_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default()
+        .split('.')
+        .next()
+        .unwrap_or("wasmstation")
+        .replace(['-', '_'], " ")
+}
+
+fn reset(backend: &mut impl Backend) {
+    match backend.reset() {
+        Ok(()) => backend.call_start(),
+        Err(err) => log::error!("failed to reset cart: {err}"),
+    }
+}
+
+fn write_save(disk: Disk, path: &Path) -> anyhow::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(disk.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Convert a 2bpp framebuffer of any size to RGB24, filling `rgb`.
+fn framebuffer_to_rgb24(framebuffer: &[u8], palette: &[u8; 16], rgb: &mut [u8]) {
+    let palette_srgb: [Srgb<u8>; 4] = [
+        Srgb::new(palette[2], palette[1], palette[0]),
+        Srgb::new(palette[6], palette[5], palette[4]),
+        Srgb::new(palette[10], palette[9], palette[8]),
+        Srgb::new(palette[14], palette[13], palette[12]),
+    ];
+
+    for (idx, pixel) in rgb.chunks_exact_mut(3).enumerate() {
+        let color = palette_srgb[((framebuffer[idx / 4] >> ((idx % 4) * 2)) & 0x3) as usize];
+
+        pixel.copy_from_slice(&[color.red, color.green, color.blue]);
+    }
+}
+
+fn bounding_rect(size: &Rect) -> Rect {
+    let game_size = size.width().min(size.height());
+
+    Rect::new(
+        ((size.width() - game_size) / 2) as i32,
+        ((size.height() - game_size) / 2) as i32,
+        game_size,
+        game_size,
+    )
+}
+
+enum DesktopInputEvent {
+    Key {
+        down: bool,
+        keycode: Option<Keycode>,
+    },
+    Mouse {
+        buttons: Option<MouseButton>,
+        down: Option<bool>,
+        location: (i32, i32),
+    },
+}
+
+fn handle_input(
+    event: Event,
+    gamepads: &mut u32,
+    mouse: &mut (i16, i16),
+    mouse_buttons: &mut u8,
+    window_size: (u32, u32),
+) -> bool {
+    let event = match event {
+        Event::Quit { .. } => return true,
+        Event::KeyDown { keycode, .. } => DesktopInputEvent::Key {
+            down: true,
+            keycode,
+        },
+        Event::KeyUp { keycode, .. } => DesktopInputEvent::Key {
+            down: false,
+            keycode,
+        },
+        Event::MouseButtonDown {
+            mouse_btn, x, y, ..
+        } => DesktopInputEvent::Mouse {
+            buttons: Some(mouse_btn),
+            down: Some(true),
+            location: (x, y),
+        },
+        Event::MouseButtonUp {
+            mouse_btn, x, y, ..
+        } => DesktopInputEvent::Mouse {
+            buttons: Some(mouse_btn),
+            down: Some(false),
+            location: (x, y),
+        },
+        Event::MouseMotion { x, y, .. } => DesktopInputEvent::Mouse {
+            buttons: None,
+            down: None,
+            location: (x, y),
+        },
+        _ => return false,
+    };
+
+    let [gamepad1, _, _, _] = bytemuck::cast_mut::<u32, [u8; 4]>(gamepads);
+
+    match event {
+        DesktopInputEvent::Key { down, keycode } => {
+            let mask: u8 = match keycode {
+                Some(Keycode::Left) => BUTTON_LEFT,
+                Some(Keycode::Right) => BUTTON_RIGHT,
+                Some(Keycode::Up) => BUTTON_UP,
+                Some(Keycode::Down) => BUTTON_DOWN,
+                Some(Keycode::X) => BUTTON_1,
+                Some(Keycode::Z) => BUTTON_2,
+                _ => 0x0,
+            };
+
+            match down {
+                true => *gamepad1 |= mask,
+                false => *gamepad1 ^= mask,
+            }
+        }
+        DesktopInputEvent::Mouse {
+            buttons,
+            down,
+            location,
+        } => {
+            if let Some(buttons) = buttons {
+                let mask: u8 = match buttons {
+                    MouseButton::Left => MOUSE_LEFT,
+                    MouseButton::Middle => MOUSE_MIDDLE,
+                    MouseButton::Right => MOUSE_RIGHT,
+                    _ => 0x0,
+                };
+
+                match down.unwrap_or(false) {
+                    true => *mouse_buttons |= mask,
+                    false => *mouse_buttons ^= mask,
+                };
+            }
+
+            let location: (u32, u32) = (location.0 as u32, location.1 as u32);
+
+            let game_size = window_size.0.min(window_size.1);
+            let border_x = (window_size.0 - game_size) / 2;
+            let border_y = (window_size.1 - game_size) / 2;
+
+            if location.0 >= border_x
+                && location.1 >= border_y
+                && location.0 <= (window_size.0 - border_x)
+                && location.1 <= (window_size.1 - border_y)
+            {
+                *mouse = (
+                    (((location.0 - border_x) as f32 / game_size as f32) * SCREEN_SIZE as f32)
+                        as i16,
+                    (((location.1 - border_y) as f32 / game_size as f32) * SCREEN_SIZE as f32)
+                        as i16,
+                );
+            }
+        }
+    }
+
+    false
+}
@@ -0,0 +1,416 @@
+//! A terminal renderer drawing with Unicode half blocks using [`crossterm`].
+//!
+//! Every character cell shows two pixels: the upper one as the foreground
+//! of `▀` and the lower one as the background, so the 160x160 screen needs
+//! a terminal of 160 columns and 80 rows. Only cells that changed since the
+//! previous frame are redrawn, which keeps the output small over SSH.
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Stdout, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    queue,
+    style::{self, Color},
+    terminal::{self, ClearType},
+};
+
+use crate::core::{
+    meta::CartMeta,
+    utils,
+    wasm4::{
+        BUTTON_1, BUTTON_2, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_UP, FRAMEBUFFER_SIZE,
+        SCREEN_SIZE,
+    },
+    Backend, Disk,
+};
+
+const TARGET_FPS: f32 = 60.0;
+const TARGET_MS_PER_FRAME: Duration = Duration::from_millis((1000.0 / TARGET_FPS) as u64);
+
+/// How long a key counts as held after a press, when the terminal
+/// doesn't report key releases.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+const ROWS: u16 = SCREEN_SIZE as u16 / 2;
+const COLUMNS: u16 = SCREEN_SIZE as u16;
+
+pub use crossterm;
+
+/// The colors used for drawing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// 24-bit colors, taken directly from the cart's palette.
+    TrueColor,
+    /// The closest colors of the xterm 256 color palette.
+    Ansi256,
+}
+
+impl ColorMode {
+    /// Use [`ColorMode::TrueColor`] if the `COLORTERM` environment variable
+    /// announces support for it, and [`ColorMode::Ansi256`] otherwise.
+    pub fn detect() -> Self {
+        match env::var("COLORTERM").as_deref() {
+            Ok("truecolor") | Ok("24bit") => Self::TrueColor,
+            _ => Self::Ansi256,
+        }
+    }
+
+    fn color(self, rgb: [u8; 3]) -> Color {
+        match self {
+            Self::TrueColor => Color::Rgb {
+                r: rgb[0],
+                g: rgb[1],
+                b: rgb[2],
+            },
+            Self::Ansi256 => Color::AnsiValue(ansi256(rgb)),
+        }
+    }
+}
+
+/// Launch a game in the current terminal.
+///
+/// Keyboard input goes to gamepad 1: arrow keys, `x` and `z`. `r` resets
+/// the cart, `q`, `Esc` and `Ctrl+C` quit. The save is kept in the cart's
+/// [`CartMeta::save_file`], and read from `<cart>.disk` if that doesn't exist
+/// yet, see [`CartMeta::read_save`].
+pub fn launch(
+    mut backend: impl Backend,
+    path: &Path,
+    meta: &CartMeta,
+    colors: ColorMode,
+) -> anyhow::Result<()> {
+    if let Ok(data) = meta.read_save(path) {
+        backend.set_save_cache(Disk::new(&data));
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let title = meta.title.as_deref().unwrap_or(&stem);
+
+    play(backend, &meta.save_file(path), title, colors)
+}
+
+/// Launch a game in the current terminal like [`launch`], keeping its save in
+/// `save_file`, for games that don't come from a cart file.
+pub fn launch_with_save_file(
+    mut backend: impl Backend,
+    save_file: &Path,
+    meta: &CartMeta,
+    colors: ColorMode,
+) -> anyhow::Result<()> {
+    if let Ok(data) = fs::read(save_file) {
+        backend.set_save_cache(Disk::new(&data));
+    }
+
+    let title = meta.title.as_deref().unwrap_or("wasmstation");
+    play(backend, save_file, title, colors)
+}
+
+/// Run a game, whose save is already loaded, until it's quit. The terminal's
+/// window is named after `title`.
+fn play(
+    mut backend: impl Backend,
+    save_file: &Path,
+    title: &str,
+    colors: ColorMode,
+) -> anyhow::Result<()> {
+    let mut term = Terminal::new(title)?;
+
+    backend.call_start();
+
+    let mut keys = Keys::default();
+    let mut screen = Screen::new(colors);
+
+    let mut framebuffer: [u8; FRAMEBUFFER_SIZE] = utils::default_framebuffer();
+    let mut palette: [u8; 16] = utils::default_palette();
+
+    'running: loop {
+        let start = Instant::now();
+
+        // update input
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) => match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        break 'running
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => break 'running,
+                    KeyCode::Char('r') if key.kind == KeyEventKind::Press => {
+                        match backend.reset() {
+                            Ok(()) => backend.call_start(),
+                            Err(err) => log::error!("failed to reset cart: {err}"),
+                        }
+                    }
+                    _ => keys.handle(key, term.reports_releases),
+                },
+                Event::Resize(..) => screen.invalidate(),
+                _ => (),
+            }
+        }
+        backend.set_gamepad(keys.gamepad(term.reports_releases) as u32);
+
+        // update state
+        backend.call_update();
+        if let Some(disk) = backend.write_save_cache() {
+            write_save(disk, save_file)?;
+        }
+
+        // update screen
+        backend.read_screen(&mut framebuffer, &mut palette);
+        screen.draw(&mut term.stdout, &framebuffer, &palette)?;
+
+        thread::sleep((start + TARGET_MS_PER_FRAME).saturating_duration_since(Instant::now()));
+    }
+
+    Ok(())
+}
+
+fn write_save(disk: Disk, path: &Path) -> anyhow::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(disk.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Puts the terminal in raw mode on an alternate screen,
+/// and restores it when dropped.
+struct Terminal {
+    stdout: Stdout,
+    reports_releases: bool,
+}
+
+impl Terminal {
+    fn new(title: &str) -> anyhow::Result<Self> {
+        terminal::enable_raw_mode()?;
+
+        let mut stdout = io::stdout();
+        queue!(
+            stdout,
+            terminal::SetTitle(format!("wasmstation - {title}")),
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(ClearType::All)
+        )?;
+
+        // windows always reports key releases, other platforms only
+        // with the kitty keyboard protocol.
+        let reports_releases =
+            cfg!(windows) || terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases && !cfg!(windows) {
+            queue!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        stdout.flush()?;
+
+        Ok(Self {
+            stdout,
+            reports_releases,
+        })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.reports_releases && !cfg!(windows) {
+            let _ = queue!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+
+        let _ = queue!(
+            self.stdout,
+            style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = self.stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The state of gamepad 1.
+#[derive(Default)]
+struct Keys {
+    held: u8,
+    pressed_at: [Option<Instant>; 8],
+}
+
+impl Keys {
+    fn handle(&mut self, key: KeyEvent, reports_releases: bool) {
+        let mask: u8 = match key.code {
+            KeyCode::Left => BUTTON_LEFT,
+            KeyCode::Right => BUTTON_RIGHT,
+            KeyCode::Up => BUTTON_UP,
+            KeyCode::Down => BUTTON_DOWN,
+            KeyCode::Char('x') | KeyCode::Char('X') => BUTTON_1,
+            KeyCode::Char('z') | KeyCode::Char('Z') => BUTTON_2,
+            _ => return,
+        };
+
+        match (key.kind, reports_releases) {
+            (KeyEventKind::Release, _) => self.held &= !mask,
+            (_, true) => self.held |= mask,
+            (_, false) => self.pressed_at[mask.trailing_zeros() as usize] = Some(Instant::now()),
+        }
+    }
+
+    fn gamepad(&self, reports_releases: bool) -> u8 {
+        if reports_releases {
+            return self.held;
+        }
+
+        self.pressed_at
+            .iter()
+            .enumerate()
+            .filter(|(_, pressed_at)| matches!(pressed_at, Some(t) if t.elapsed() < KEY_HOLD))
+            .fold(0, |gamepad, (bit, _)| gamepad | (1 << bit))
+    }
+}
+
+/// What is currently shown on the terminal.
+struct Screen {
+    colors: ColorMode,
+    cells: Vec<Option<(u8, u8)>>,
+    palette: [u8; 16],
+    origin: (u16, u16),
+    /// How many columns and rows of the screen fit on the terminal.
+    visible: (u16, u16),
+}
+
+impl Screen {
+    fn new(colors: ColorMode) -> Self {
+        Self {
+            colors,
+            cells: vec![None; (ROWS * COLUMNS) as usize],
+            palette: [0; 16],
+            origin: (0, 0),
+            visible: (COLUMNS, ROWS),
+        }
+    }
+
+    /// Redraw every cell on the next frame.
+    fn invalidate(&mut self) {
+        self.cells.fill(None);
+    }
+
+    fn draw(
+        &mut self,
+        out: &mut impl Write,
+        framebuffer: &[u8; FRAMEBUFFER_SIZE],
+        palette: &[u8; 16],
+    ) -> anyhow::Result<()> {
+        if *palette != self.palette {
+            self.palette = *palette;
+            self.invalidate();
+        }
+
+        if self.cells.iter().all(Option::is_none) {
+            // center the screen, cropping its right and bottom edges if the
+            // terminal is too small.
+            let (width, height) = terminal::size()?;
+            self.origin = (
+                width.saturating_sub(COLUMNS) / 2,
+                height.saturating_sub(ROWS) / 2,
+            );
+            self.visible = (width.min(COLUMNS), height.min(ROWS));
+            queue!(out, style::ResetColor, terminal::Clear(ClearType::All))?;
+        }
+
+        let rgb: [[u8; 3]; 4] = [
+            [palette[2], palette[1], palette[0]],
+            [palette[6], palette[5], palette[4]],
+            [palette[10], palette[9], palette[8]],
+            [palette[14], palette[13], palette[12]],
+        ];
+
+        let mut cursor: Option<(u16, u16)> = None;
+        let mut current: Option<(u8, u8)> = None;
+
+        for row in 0..self.visible.1 {
+            for column in 0..self.visible.0 {
+                let cell = (
+                    pixel(framebuffer, column as usize, row as usize * 2),
+                    pixel(framebuffer, column as usize, row as usize * 2 + 1),
+                );
+
+                let idx = (row * COLUMNS + column) as usize;
+                if self.cells[idx] == Some(cell) {
+                    continue;
+                }
+                self.cells[idx] = Some(cell);
+
+                if cursor != Some((column, row)) {
+                    queue!(
+                        out,
+                        cursor::MoveTo(self.origin.0 + column, self.origin.1 + row)
+                    )?;
+                }
+
+                if current != Some(cell) {
+                    queue!(
+                        out,
+                        style::SetForegroundColor(self.colors.color(rgb[cell.0 as usize])),
+                        style::SetBackgroundColor(self.colors.color(rgb[cell.1 as usize]))
+                    )?;
+                    current = Some(cell);
+                }
+
+                queue!(out, style::Print('▀'))?;
+                cursor = Some((column + 1, row));
+            }
+        }
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn pixel(framebuffer: &[u8; FRAMEBUFFER_SIZE], x: usize, y: usize) -> u8 {
+    let idx = y * SCREEN_SIZE as usize + x;
+
+    (framebuffer[idx / 4] >> ((idx % 4) * 2)) & 0x3
+}
+
+/// The closest color in the xterm 256 color palette, from either
+/// the 6x6x6 color cube or the grayscale ramp.
+fn ansi256(rgb: [u8; 3]) -> u8 {
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let cube_idx = |c: u8| -> usize {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| (c as i32 - **level as i32).abs())
+            .map(|(idx, _)| idx)
+            .unwrap()
+    };
+
+    let distance =
+        |a: [u8; 3]| -> i32 { (0..3).map(|i| (a[i] as i32 - rgb[i] as i32).pow(2)).sum() };
+
+    let (r, g, b) = (cube_idx(rgb[0]), cube_idx(rgb[1]), cube_idx(rgb[2]));
+    let cube = [CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]];
+
+    let avg = (rgb.iter().map(|c| *c as u32).sum::<u32>() / 3) as i32;
+    let gray_idx = ((avg - 8) / 10).clamp(0, 23);
+    let gray_level = (8 + gray_idx * 10) as u8;
+
+    if distance([gray_level; 3]) < distance(cube) {
+        232 + gray_idx as u8
+    } else {
+        16 + (36 * r + 6 * g + b) as u8
+    }
+}
@@ -0,0 +1,433 @@
+//! A [`Backend`] based on the [`wasm3`] WebAssembly interpreter.
+
+use std::rc::Rc;
+
+use wasm3::{error::Error, CallContext, Environment, Module, Runtime, WasmArgs, WasmType};
+
+use crate::core::{
+    features::WasmFeatures,
+    host, system, utils,
+    wasm4::{self, HostCallCounts},
+    Api, Backend, Console, Disk,
+};
+
+pub use wasm3;
+
+/// Size of the interpreter's value stack in bytes.
+const STACK_SIZE: u32 = 32 * 1024;
+
+/// The WebAssembly proposals wasm3 implements, none of them can be turned off.
+const FEATURES: WasmFeatures = WasmFeatures {
+    mutable_global: true,
+    sign_extension: true,
+    saturating_float_to_int: true,
+    multi_value: true,
+    bulk_memory: true,
+    ..WasmFeatures::MVP
+};
+
+/// A backend with a very small footprint for WebAssembly games.
+pub struct Wasm3Backend {
+    bytes: Vec<u8>,
+    runtime: Runtime,
+    api: Rc<Api>,
+}
+
+impl Wasm3Backend {
+    /// Create a [`Wasm3Backend`] from raw `.wasm` bytes.
+    pub fn from_bytes(wasm_bytes: &[u8], console: &Console) -> anyhow::Result<Self> {
+        console.features().check("wasm3", &FEATURES)?;
+
+        Self::new(wasm_bytes.to_vec(), Rc::new(console.create_api()))
+    }
+
+    /// Parse, load and link the module in a fresh [`Runtime`].
+    fn new(bytes: Vec<u8>, api: Rc<Api>) -> anyhow::Result<Self> {
+        let env = Environment::new()?;
+        let runtime = env.create_runtime(STACK_SIZE)?;
+
+        {
+            let mut module = runtime.load_module(Module::parse(&env, &bytes[..])?)?;
+
+            // see https://wasm4.org/docs/reference/functions
+            link(&mut module, &api, "trace", trace)?;
+            link(&mut module, &api, "tracef", tracef)?;
+            link(&mut module, &api, "traceUtf8", trace_utf8)?;
+            link(&mut module, &api, "traceUtf16", trace_utf16)?;
+            link(&mut module, &api, "blit", blit)?;
+            link(&mut module, &api, "blitSub", blit_sub)?;
+            link(&mut module, &api, "line", line)?;
+            link(&mut module, &api, "hline", hline)?;
+            link(&mut module, &api, "vline", vline)?;
+            link(&mut module, &api, "oval", oval)?;
+            link(&mut module, &api, "rect", rect)?;
+            link(&mut module, &api, "text", text)?;
+            link(&mut module, &api, "textUtf8", text_utf8)?;
+            link(&mut module, &api, "textUtf16", text_utf16)?;
+            link(&mut module, &api, "diskr", diskr)?;
+            link(&mut module, &api, "diskw", diskw)?;
+            link(&mut module, &api, "tone", tone)?;
+
+            if api.system().is_some() {
+                link(&mut module, &api, "cartCount", cart_count)?;
+                link(&mut module, &api, "cartMeta", cart_meta)?;
+                link(&mut module, &api, "cartThumbnail", cart_thumbnail)?;
+                link(&mut module, &api, "launch", launch)?;
+                link(&mut module, &api, "getSetting", get_setting)?;
+                link(&mut module, &api, "setSetting", set_setting)?;
+            }
+        }
+
+        // the cart's imported memory is allocated by the runtime when loading.
+        let mem = unsafe { &mut *runtime.memory_mut() };
+        if mem.len() < wasm4::MEMORY_SIZE {
+            anyhow::bail!("cart must import at least one page of memory");
+        }
+
+        mem[wasm4::PALETTE_ADDR..wasm4::PALETTE_ADDR + 16]
+            .copy_from_slice(&utils::default_palette());
+        mem[wasm4::DRAW_COLORS_ADDR..wasm4::DRAW_COLORS_ADDR + 2]
+            .copy_from_slice(&utils::default_draw_colors());
+        mem[wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE]
+            .copy_from_slice(&utils::default_framebuffer());
+
+        // wasm3 already applied the data segments while loading the module,
+        // they have to win over the defaults like in the other backends.
+        for (offset, data) in data_segments(&bytes)? {
+            match offset
+                .checked_add(data.len())
+                .and_then(|end| mem.get_mut(offset..end))
+            {
+                Some(mem) => mem.copy_from_slice(data),
+                None => anyhow::bail!("data segment at {offset:#x} is out of bounds"),
+            }
+        }
+
+        Ok(Self {
+            bytes,
+            runtime,
+            api,
+        })
+    }
+
+    fn memory(&self) -> &[u8] {
+        // the runtime is single threaded and only hands out its memory
+        // while no function is running.
+        unsafe { &*self.runtime.memory() }
+    }
+
+    fn memory_mut(&mut self) -> &mut [u8] {
+        unsafe { &mut *self.runtime.memory_mut() }
+    }
+
+    fn call(&self, name: &str) {
+        // a missing export is fine, carts don't need to define both.
+        if let Ok(func) = self.runtime.find_function::<(), ()>(name) {
+            if let Err(err) = func.call() {
+                log::error!("error calling '{name}': {err}");
+            }
+        }
+    }
+}
+
+impl Backend for Wasm3Backend {
+    fn call_update(&mut self) {
+        host::before_update(self.memory_mut());
+
+        self.call("update");
+    }
+
+    fn call_start(&mut self) {
+        self.call("start");
+    }
+
+    fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
+        let mem = self.memory();
+
+        framebuffer.copy_from_slice(
+            &mem[wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE],
+        );
+        palette.copy_from_slice(&mem[wasm4::PALETTE_ADDR..wasm4::PALETTE_ADDR + 16]);
+    }
+
+    fn read_system_flags(&self) -> u8 {
+        self.memory()[wasm4::SYSTEM_FLAGS_ADDR]
+    }
+
+    fn set_gamepad(&mut self, gamepad: u32) {
+        let mem = self.memory_mut();
+
+        mem[wasm4::GAMEPAD1_ADDR..wasm4::GAMEPAD1_ADDR + 4].copy_from_slice(&gamepad.to_le_bytes());
+    }
+
+    fn set_mouse(&mut self, x: i16, y: i16, buttons: u8) {
+        let mem = self.memory_mut();
+
+        mem[wasm4::MOUSE_X_ADDR..wasm4::MOUSE_X_ADDR + 2].copy_from_slice(&x.to_le_bytes());
+        mem[wasm4::MOUSE_Y_ADDR..wasm4::MOUSE_Y_ADDR + 2].copy_from_slice(&y.to_le_bytes());
+        mem[wasm4::MOUSE_BUTTONS_ADDR] = buttons;
+    }
+
+    fn write_save_cache(&mut self) -> Option<Disk> {
+        self.api.write_save()
+    }
+
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.api.save_cache.set(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        // wasm3 can't unload modules, so the cart is parsed again in a new runtime.
+        *self = Self::new(self.bytes.clone(), Rc::clone(&self.api))?;
+
+        Ok(())
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        let mem = offset
+            .checked_add(buf.len())
+            .and_then(|end| self.memory().get(offset..end));
+
+        match mem {
+            Some(mem) => buf.copy_from_slice(mem),
+            None => log::error!("error reading memory: {offset:#x} is out of bounds"),
+        }
+    }
+
+    fn take_host_calls(&mut self) -> HostCallCounts {
+        self.api.take_host_calls()
+    }
+}
+
+/// The active data segments of a module, as their offset in memory and bytes.
+fn data_segments(wasm: &[u8]) -> anyhow::Result<Vec<(usize, &[u8])>> {
+    const DATA_SECTION: u8 = 11;
+
+    let malformed = || anyhow::anyhow!("malformed data section");
+    let mut segments = Vec::new();
+    let mut offset = 8;
+
+    while offset < wasm.len() {
+        let id = wasm[offset];
+        offset += 1;
+        let size = utils::read_leb128(wasm, &mut offset).ok_or_else(malformed)? as usize;
+        let end = offset.checked_add(size).filter(|end| *end <= wasm.len());
+        let end = end.ok_or_else(malformed)?;
+
+        if id != DATA_SECTION {
+            offset = end;
+            continue;
+        }
+
+        let section = &wasm[offset..end];
+        let mut pos = 0;
+        let count = utils::read_leb128(section, &mut pos).ok_or_else(malformed)?;
+
+        for _ in 0..count {
+            let start = match utils::read_leb128(section, &mut pos).ok_or_else(malformed)? {
+                0 => Some(const_offset(section, &mut pos)?),
+                // passive segments are only copied by `memory.init`.
+                1 => None,
+                // there's only the one memory, so the index is skipped.
+                2 => {
+                    utils::read_leb128(section, &mut pos).ok_or_else(malformed)?;
+                    Some(const_offset(section, &mut pos)?)
+                }
+                _ => return Err(malformed()),
+            };
+
+            let len = utils::read_leb128(section, &mut pos).ok_or_else(malformed)? as usize;
+            let data = pos
+                .checked_add(len)
+                .and_then(|end| section.get(pos..end))
+                .ok_or_else(malformed)?;
+            pos += len;
+
+            if let Some(start) = start {
+                segments.push((start, data));
+            }
+        }
+
+        offset = end;
+    }
+
+    Ok(segments)
+}
+
+/// Read a data segment's offset, which WASM-4 carts always give as `i32.const`.
+fn const_offset(section: &[u8], pos: &mut usize) -> anyhow::Result<usize> {
+    const I32_CONST: u8 = 0x41;
+    const END: u8 = 0x0b;
+
+    if section.get(*pos) != Some(&I32_CONST) {
+        anyhow::bail!("unsupported data segment offset, only i32.const is");
+    }
+    *pos += 1;
+
+    // a signed LEB128 number, which is reinterpreted as an unsigned address.
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *section
+            .get(*pos)
+            .ok_or_else(|| anyhow::anyhow!("malformed data section"))?;
+        *pos += 1;
+        value |= u32::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+
+        if byte & 0x80 == 0 {
+            if shift < 25 && byte & 0x40 != 0 {
+                value |= u32::MAX << (shift + 7);
+            }
+
+            if section.get(*pos) != Some(&END) {
+                anyhow::bail!("unsupported data segment offset, only i32.const is");
+            }
+            *pos += 1;
+
+            return Ok(value as usize);
+        }
+    }
+
+    Err(anyhow::anyhow!("malformed data section"))
+}
+
+/// Link a WASM-4 function, skipping it if the cart doesn't import it.
+fn link<Args, Ret>(
+    module: &mut Module,
+    api: &Rc<Api>,
+    name: &str,
+    func: fn(&mut [u8], &Api, Args) -> Ret,
+) -> anyhow::Result<()>
+where
+    Args: WasmArgs + 'static,
+    Ret: WasmType + 'static,
+{
+    let api = Rc::clone(api);
+    let linked = module.link_closure("env", name, move |ctx: &CallContext, args: Args| {
+        // imports are only called by the runtime, which isn't borrowing its memory.
+        let mem = unsafe { &mut *ctx.memory_mut() };
+
+        func(mem, &api, args)
+    });
+
+    match linked {
+        Ok(()) | Err(Error::FunctionNotFound) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn trace(mem: &mut [u8], api: &Api, ptr: u32) {
+    host::trace(mem, api, ptr);
+}
+
+fn tracef(mem: &mut [u8], api: &Api, (fmt, args): (u32, u32)) {
+    host::tracef(mem, api, fmt, args);
+}
+
+fn trace_utf8(mem: &mut [u8], api: &Api, (ptr, len): (u32, u32)) {
+    host::trace_utf8(mem, api, ptr, len);
+}
+
+fn trace_utf16(mem: &mut [u8], api: &Api, (ptr, len): (u32, u32)) {
+    host::trace_utf16(mem, api, ptr, len);
+}
+
+fn blit(
+    mem: &mut [u8],
+    api: &Api,
+    (sprite, x, y, width, height, flags): (u32, i32, i32, u32, u32, u32),
+) {
+    host::blit(mem, api, sprite, x, y, width, height, flags);
+}
+
+#[allow(clippy::type_complexity)]
+fn blit_sub(
+    mem: &mut [u8],
+    api: &Api,
+    (sprite, x, y, width, height, src_x, src_y, stride, flags): (
+        u32,
+        i32,
+        i32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+    ),
+) {
+    host::blit_sub(
+        mem, api, sprite, x, y, width, height, src_x, src_y, stride, flags,
+    );
+}
+
+fn line(mem: &mut [u8], api: &Api, (x1, y1, x2, y2): (i32, i32, i32, i32)) {
+    host::line(mem, api, x1, y1, x2, y2);
+}
+
+fn hline(mem: &mut [u8], api: &Api, (x, y, len): (i32, i32, u32)) {
+    host::hline(mem, api, x, y, len);
+}
+
+fn vline(mem: &mut [u8], api: &Api, (x, y, len): (i32, i32, u32)) {
+    host::vline(mem, api, x, y, len);
+}
+
+fn oval(mem: &mut [u8], api: &Api, (x, y, width, height): (i32, i32, u32, u32)) {
+    host::oval(mem, api, x, y, width, height);
+}
+
+fn rect(mem: &mut [u8], api: &Api, (x, y, width, height): (i32, i32, u32, u32)) {
+    host::rect(mem, api, x, y, width, height);
+}
+
+fn text(mem: &mut [u8], api: &Api, (ptr, x, y): (u32, i32, i32)) {
+    host::text(mem, api, ptr, x, y);
+}
+
+fn text_utf8(mem: &mut [u8], api: &Api, (ptr, len, x, y): (u32, u32, i32, i32)) {
+    host::text_utf8(mem, api, ptr, len, x, y);
+}
+
+fn text_utf16(mem: &mut [u8], api: &Api, (ptr, len, x, y): (u32, u32, i32, i32)) {
+    host::text_utf16(mem, api, ptr, len, x, y);
+}
+
+fn diskr(mem: &mut [u8], api: &Api, (dest, len): (u32, u32)) -> u32 {
+    host::diskr(mem, api, dest, len)
+}
+
+fn diskw(mem: &mut [u8], api: &Api, (src, len): (u32, u32)) -> u32 {
+    host::diskw(mem, api, src, len)
+}
+
+fn tone(_mem: &mut [u8], api: &Api, (frequency, duration, volume, flags): (u32, u32, u32, u32)) {
+    host::tone(api, frequency, duration, volume, flags);
+}
+
+fn cart_count(_mem: &mut [u8], api: &Api, (): ()) -> u32 {
+    system::cart_count(api)
+}
+
+fn cart_meta(
+    mem: &mut [u8],
+    api: &Api,
+    (index, key, key_len, dest, len): (u32, u32, u32, u32, u32),
+) -> u32 {
+    system::cart_meta(mem, api, index, key, key_len, dest, len)
+}
+
+fn cart_thumbnail(mem: &mut [u8], api: &Api, (index, dest): (u32, u32)) -> u32 {
+    system::cart_thumbnail(mem, api, index, dest)
+}
+
+fn launch(_mem: &mut [u8], api: &Api, index: u32) {
+    system::launch(api, index);
+}
+
+fn get_setting(mem: &mut [u8], api: &Api, (key, key_len, dest, len): (u32, u32, u32, u32)) -> u32 {
+    system::get_setting(mem, api, key, key_len, dest, len)
+}
+
+fn set_setting(mem: &mut [u8], api: &Api, (key, key_len, value, len): (u32, u32, u32, u32)) {
+    system::set_setting(mem, api, key, key_len, value, len);
+}
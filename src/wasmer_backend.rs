@@ -1,22 +1,56 @@
 //! A [`Backend`] based on the [`wasmer`] WebAssembly engine.
 
+#[cfg(target_arch = "wasm32")]
+use core::ops::Range;
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+use anyhow::anyhow;
 use log::error;
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+use log::warn;
 use wasmer::{
-    imports, Engine, Function, FunctionEnv, FunctionEnvMut, Instance, Memory, MemoryType,
-    MemoryView, Module, Store, TypedFunction, ValueType, WasmPtr, WasmSlice,
+    imports, Engine, ExportError, Function, FunctionEnv, FunctionEnvMut, Instance, Memory,
+    MemoryType, MemoryView, Module, Store,
 };
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+use wasmer::{CompilerConfig, Cranelift, CraneliftOptLevel, EngineBuilder};
 
 use crate::core::{
-    framebuffer::{self, pixel_width_of_flags},
-    trace, utils,
-    wasm4::{self, DRAW_COLORS_ADDR, FRAMEBUFFER_ADDR, FRAMEBUFFER_SIZE},
-    Api, Backend, Console, Sink, Source,
+    host::{self, GuestMemory},
+    system, utils,
+    wasm4::{self, HostCallCounts},
+    Api, Backend, Console, Disk, Sink, Source,
+};
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+use crate::{
+    core::{
+        artifact::{self, Artifact, Header},
+        features::WasmFeatures,
+    },
+    dirs,
 };
 
 pub use wasmer;
 
+/// The WebAssembly proposals wasmer implements. Mutable globals, sign
+/// extension and saturating float to int can't be turned off.
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+const FEATURES: WasmFeatures = WasmFeatures {
+    tail_call: false,
+    extended_const: false,
+    ..WasmFeatures::ALL
+};
+
 /// A fast yet large backend for WebAssembly games.
 pub struct WasmerBackend {
+    module: Module,
     fn_env: FunctionEnv<WasmerRuntimeEnv>,
     store: Store,
     instance: Instance,
@@ -24,33 +58,81 @@ pub struct WasmerBackend {
 
 impl WasmerBackend {
     /// Create a [`WasmerBackend`] from raw `.wasm` bytes.
+    ///
+    /// In WebAssembly, the browser decides which proposals carts may use,
+    /// instead of the console's [`WasmFeatures`].
     pub fn from_bytes(wasm_bytes: &[u8], console: &Console) -> anyhow::Result<Self> {
-        let store = Store::new(Engine::default());
-        let module = Module::new(&store, wasm_bytes)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        return WasmerBuilder::default().build(wasm_bytes, console);
 
-        Self::new(store, module, console)        
+        #[cfg(target_arch = "wasm32")]
+        {
+            let store = Store::new(Engine::default());
+            let module = Module::new(&store, wasm_bytes)?;
+
+            Self::new(store, module, console.create_api())
+        }
     }
 
     #[cfg(any(doc, not(target_arch = "wasm32")))]
-    /// Start a [`WasmerBackend`] without compiling at runtime from [`Module`](wasmer::Module) bytes.
-    /// 
+    /// Start a [`WasmerBackend`] without compiling at runtime, from an
+    /// artifact made by [`WasmerBackend::precompile`].
+    ///
+    /// The artifact is rejected if it was made for another version of wasmer
+    /// or wasmstation, for another target, with other [`WasmerBuilder`]
+    /// settings or with other [`WasmFeatures`] than the console's. If it
+    /// includes the original cart, that is compiled instead.
+    ///
     /// Note: This method is not available in WebAssembly.
-    pub fn precompiled(module_bytes: &[u8], console: &Console) -> anyhow::Result<Self> {
-        let store = Store::new(Engine::headless());
-        let module = unsafe { Module::deserialize(&store, module_bytes)? };
+    pub fn precompiled(artifact: &[u8], console: &Console) -> anyhow::Result<Self> {
+        WasmerBuilder::default().precompiled(artifact, console)
+    }
 
-        Self::new(store, module, console)
+    #[cfg(any(doc, not(target_arch = "wasm32")))]
+    /// Like [`WasmerBackend::precompiled`], but also rejects artifacts that
+    /// weren't compiled from the cart with `cart_hash`, see
+    /// [`content_hash`](utils::content_hash).
+    ///
+    /// Note: This method is not available in WebAssembly.
+    pub fn precompiled_for(
+        artifact: &[u8],
+        cart_hash: &[u8; 32],
+        console: &Console,
+    ) -> anyhow::Result<Self> {
+        WasmerBuilder::default().precompiled_for(artifact, cart_hash, console)
     }
 
-    /// Create a [`WasmerBackend`] from precompiled [`Module`](wasmer::Module) bytes.
-    fn new(mut store: Store, module: Module, console: &Console) -> anyhow::Result<Self> {
+    #[cfg(any(doc, not(target_arch = "wasm32")))]
+    /// Compile raw `.wasm` bytes to an artifact for [`WasmerBackend::precompiled`],
+    /// for this version of wasmer and this target. It's only loaded by consoles
+    /// with the same `features`.
+    ///
+    /// With `include_cart`, the original cart is kept in the artifact, to be
+    /// compiled when the module can't be used.
+    pub fn precompile(
+        wasm_bytes: &[u8],
+        features: &WasmFeatures,
+        include_cart: bool,
+    ) -> anyhow::Result<Vec<u8>> {
+        WasmerBuilder::default().precompile(wasm_bytes, features, include_cart)
+    }
 
+    #[cfg(any(doc, not(target_arch = "wasm32")))]
+    /// Pick the compiler and its settings, instead of wasmer's defaults.
+    ///
+    /// Note: This method is not available in WebAssembly.
+    pub fn builder() -> WasmerBuilder {
+        WasmerBuilder::default()
+    }
+
+    /// Create a [`WasmerBackend`] by instantiating a compiled [`Module`](wasmer::Module).
+    fn new(mut store: Store, module: Module, api: Api) -> anyhow::Result<Self> {
         // init memory and env
-        let wasm_env = WasmerRuntimeEnv::new(&mut store, console.create_api())?;
+        let wasm_env = WasmerRuntimeEnv::new(&mut store, api)?;
         let fn_env = FunctionEnv::new(&mut store, wasm_env);
 
         // see https://wasm4.org/docs/reference/functions
-        let imports = imports! {
+        let mut imports = imports! {
             "env" => {
                 "memory" => fn_env.as_mut(&mut store).memory.clone(),
                 "trace" => Function::new_typed_with_env(&mut store, &fn_env, trace),
@@ -73,45 +155,446 @@ impl WasmerBackend {
             }
         };
 
+        if fn_env.as_ref(&store).api.system().is_some() {
+            let system = [
+                (
+                    "cartCount",
+                    Function::new_typed_with_env(&mut store, &fn_env, cart_count),
+                ),
+                (
+                    "cartMeta",
+                    Function::new_typed_with_env(&mut store, &fn_env, cart_meta),
+                ),
+                (
+                    "cartThumbnail",
+                    Function::new_typed_with_env(&mut store, &fn_env, cart_thumbnail),
+                ),
+                (
+                    "launch",
+                    Function::new_typed_with_env(&mut store, &fn_env, launch),
+                ),
+                (
+                    "getSetting",
+                    Function::new_typed_with_env(&mut store, &fn_env, get_setting),
+                ),
+                (
+                    "setSetting",
+                    Function::new_typed_with_env(&mut store, &fn_env, set_setting),
+                ),
+            ];
+
+            for (name, func) in system {
+                imports.define("env", name, func);
+            }
+        }
+
         let instance = Instance::new(&mut store, &module, &imports)?;
 
         Ok(Self {
+            module,
             fn_env,
             store,
             instance,
         })
     }
+
+    /// Call one of the cart's callbacks, logging it if the cart traps.
+    fn call(&mut self, name: &str) {
+        // a missing export is fine, carts don't need to define both.
+        match self
+            .instance
+            .exports
+            .get_typed_function::<(), ()>(&self.store, name)
+        {
+            Ok(func) => {
+                if let Err(err) = func.call(&mut self.store) {
+                    error!("error calling '{name}': {err}");
+                }
+            }
+            Err(ExportError::Missing(_)) => (),
+            Err(err) => error!("error calling '{name}': {err}"),
+        }
+    }
 }
 
-impl Backend for WasmerBackend {
-    fn call_update(&mut self) {
-        let view = self.fn_env.as_ref(&self.store).memory.view(&self.store);
+/// The compilers wasmer can use, see [`WasmerBuilder::compiler`].
+///
+/// Only Cranelift is always available, Singlepass and LLVM need the
+/// `wasmer-singlepass` and `wasmer-llvm` features.
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Compiler {
+    /// Quick to compile, with fast code.
+    #[default]
+    Cranelift,
+    /// Compiles in linear time, for quick startup and hot reloading.
+    Singlepass,
+    /// Slow to compile, with the fastest code.
+    Llvm,
+}
 
-        // clear the framebuffer (important)
-        if 0 == wasm4::SYSTEM_PRESERVE_FRAMEBUFFER
-            & view.read_u8(wasm4::SYSTEM_FLAGS_ADDR as u64).unwrap()
-        {
-            let slice = WasmPtr::<u8>::new(wasm4::FRAMEBUFFER_ADDR as u32)
-                .slice(&view, FRAMEBUFFER_SIZE as u32)
-                .unwrap();
-            framebuffer::clear(&mut WasmSliceSinkSource { slice });
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+impl Compiler {
+    fn name(self) -> &'static str {
+        match self {
+            Compiler::Cranelift => "cranelift",
+            Compiler::Singlepass => "singlepass",
+            Compiler::Llvm => "llvm",
         }
+    }
+}
 
-        if let Ok(update) = self.instance.exports.get_function("update") {
-            let typed: TypedFunction<(), ()> = update
-                .typed(&self.store)
-                .expect("update function incorrect type");
-            typed.call(&mut self.store).expect("call update function");
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+impl FromStr for Compiler {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cranelift" => Ok(Compiler::Cranelift),
+            "singlepass" => Ok(Compiler::Singlepass),
+            "llvm" => Ok(Compiler::Llvm),
+            _ => Err(anyhow!(
+                "unknown compiler '{s}', expected cranelift, singlepass or llvm"
+            )),
         }
     }
+}
 
-    fn call_start(&mut self) {
-        if let Ok(start) = self.instance.exports.get_function("start") {
-            let typed: TypedFunction<(), ()> = start
-                .typed(&self.store)
-                .expect("start function incorrect type");
-            typed.call(&mut self.store).expect("call start function");
+/// How much the compiler optimizes, see [`WasmerBuilder::opt_level`].
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    None,
+    #[default]
+    Speed,
+    SpeedAndSize,
+}
+
+/// Builds a [`WasmerBackend`] with a chosen compiler and settings, made with
+/// [`WasmerBackend::builder`].
+///
+/// ```no_run
+/// # use wasmstation::{wasmer_backend::{Compiler, WasmerBackend}, Console};
+/// # let cart = &[];
+/// let backend = WasmerBackend::builder()
+///     .compiler(Compiler::Singlepass)
+///     .canonicalize_nans(true)
+///     .build(cart, &Console::default())?;
+/// # anyhow::Ok(())
+/// ```
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WasmerBuilder {
+    compiler: Compiler,
+    opt_level: OptLevel,
+    canonicalize_nans: bool,
+}
+
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+impl WasmerBuilder {
+    /// The compiler turning carts into native code, Cranelift by default.
+    pub fn compiler(mut self, compiler: Compiler) -> Self {
+        self.compiler = compiler;
+        self
+    }
+
+    /// How much Cranelift and LLVM optimize, Singlepass doesn't.
+    pub fn opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Replace every NaN made by float instructions with the same canonical
+    /// NaN, so that carts behave the same on every CPU. Off by default, as it
+    /// makes float-heavy carts slower.
+    pub fn canonicalize_nans(mut self, canonicalize_nans: bool) -> Self {
+        self.canonicalize_nans = canonicalize_nans;
+        self
+    }
+
+    /// Create a [`WasmerBackend`] from raw `.wasm` bytes.
+    pub fn build(&self, wasm_bytes: &[u8], console: &Console) -> anyhow::Result<WasmerBackend> {
+        let store = Store::new(self.engine(console.features())?);
+        let module = Module::new(&store, wasm_bytes)?;
+
+        WasmerBackend::new(store, module, console.create_api())
+    }
+
+    /// Compile raw `.wasm` bytes to an artifact, like [`WasmerBackend::precompile`].
+    pub fn precompile(
+        &self,
+        wasm_bytes: &[u8],
+        features: &WasmFeatures,
+        include_cart: bool,
+    ) -> anyhow::Result<Vec<u8>> {
+        let store = Store::new(self.engine(features)?);
+        let module = Module::new(&store, wasm_bytes)?;
+
+        self.to_artifact(&module, wasm_bytes, features, include_cart)
+    }
+
+    /// Start a [`WasmerBackend`] from an artifact, like
+    /// [`WasmerBackend::precompiled`], if it was made with these settings.
+    pub fn precompiled(&self, artifact: &[u8], console: &Console) -> anyhow::Result<WasmerBackend> {
+        self.load_artifact(artifact, None, console)
+    }
+
+    /// Start a [`WasmerBackend`] from an artifact of the cart with
+    /// `cart_hash`, like [`WasmerBackend::precompiled_for`], if it was made
+    /// with these settings.
+    pub fn precompiled_for(
+        &self,
+        artifact: &[u8],
+        cart_hash: &[u8; 32],
+        console: &Console,
+    ) -> anyhow::Result<WasmerBackend> {
+        self.load_artifact(artifact, Some(cart_hash), console)
+    }
+
+    /// The wasmer [`Engine`] compiling carts that use `features` with these
+    /// settings.
+    pub fn engine(&self, features: &WasmFeatures) -> anyhow::Result<Engine> {
+        features.check("wasmer", &FEATURES)?;
+
+        let mut compiler: Box<dyn CompilerConfig> = match self.compiler {
+            Compiler::Cranelift => {
+                let mut cranelift = Cranelift::new();
+                cranelift.opt_level(match self.opt_level {
+                    OptLevel::None => CraneliftOptLevel::None,
+                    OptLevel::Speed => CraneliftOptLevel::Speed,
+                    OptLevel::SpeedAndSize => CraneliftOptLevel::SpeedAndSize,
+                });
+                Box::new(cranelift)
+            }
+            #[cfg(feature = "wasmer-singlepass")]
+            Compiler::Singlepass => Box::new(wasmer::Singlepass::new()),
+            #[cfg(feature = "wasmer-llvm")]
+            Compiler::Llvm => {
+                use wasmer::LLVMOptLevel;
+
+                let mut llvm = wasmer::LLVM::new();
+                llvm.opt_level(match self.opt_level {
+                    OptLevel::None => LLVMOptLevel::None,
+                    OptLevel::Speed => LLVMOptLevel::Aggressive,
+                    OptLevel::SpeedAndSize => LLVMOptLevel::Default,
+                });
+                Box::new(llvm)
+            }
+            #[allow(unreachable_patterns)]
+            compiler => {
+                return Err(anyhow!(
+                    "the {0} compiler isn't available, enable wasmstation's `wasmer-{0}` feature",
+                    compiler.name()
+                ))
+            }
+        };
+        compiler.canonicalize_nans(self.canonicalize_nans);
+
+        let mut wasm_features = wasmer::Features::new();
+        wasm_features
+            .bulk_memory(features.bulk_memory)
+            .reference_types(features.reference_types)
+            .multi_value(features.multi_value)
+            .simd(features.simd);
+
+        Ok(EngineBuilder::new(compiler)
+            .set_features(Some(wasm_features))
+            .engine())
+    }
+
+    /// A name for these settings, telling apart the modules they compile.
+    fn settings(&self) -> String {
+        let opt_level = match self.opt_level {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed-and-size",
+        };
+        let nans = if self.canonicalize_nans { "-nans" } else { "" };
+
+        format!("{}-{opt_level}{nans}", self.compiler.name())
+    }
+
+    fn load_artifact(
+        &self,
+        artifact: &[u8],
+        cart_hash: Option<&[u8; 32]>,
+        console: &Console,
+    ) -> anyhow::Result<WasmerBackend> {
+        console.features().check("wasmer", &FEATURES)?;
+
+        let artifact = Artifact::parse(artifact)?;
+        if let Some(cart_hash) = cart_hash {
+            artifact.check_cart(cart_hash)?;
+        }
+
+        let (target, settings) = (wasmer::Triple::host().to_string(), self.settings());
+        if let Err(err) = artifact.check(&header(&target, &settings, console.features())) {
+            return match artifact.wasm {
+                Some(wasm) => {
+                    warn!("{err}, compiling the included cart instead");
+                    self.build(wasm, console)
+                }
+                None => Err(err),
+            };
+        }
+
+        let store = Store::new(Engine::headless());
+        // the module was made by this version of wasmer, for this target,
+        // with these settings.
+        let module = unsafe { Module::deserialize(&store, artifact.module)? };
+
+        WasmerBackend::new(store, module, console.create_api())
+    }
+
+    /// Wrap `module`, compiled from `wasm_bytes` with these settings and
+    /// `features`, in an artifact.
+    fn to_artifact(
+        &self,
+        module: &Module,
+        wasm_bytes: &[u8],
+        features: &WasmFeatures,
+        include_cart: bool,
+    ) -> anyhow::Result<Vec<u8>> {
+        let (target, settings) = (wasmer::Triple::host().to_string(), self.settings());
+        let module = module.serialize()?;
+
+        Ok(Artifact {
+            header: header(&target, &settings, features),
+            cart_hash: utils::content_hash(wasm_bytes),
+            module: &module,
+            wasm: include_cart.then_some(wasm_bytes),
+        }
+        .to_bytes())
+    }
+}
+
+/// Compiled modules on disk, so that carts are only compiled once per wasmer
+/// version and target.
+///
+/// Modules are keyed by the [`content_hash`](utils::content_hash) of the
+/// cart, in a directory for each version, target triple, [`WasmerBuilder`]
+/// settings and [`WasmFeatures`].
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+pub struct ModuleCache {
+    dir: PathBuf,
+    builder: WasmerBuilder,
+}
+
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+impl ModuleCache {
+    /// A cache in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            builder: WasmerBuilder::default(),
+        }
+    }
+
+    /// Compile carts missing from the cache with `builder`.
+    pub fn with_builder(mut self, builder: WasmerBuilder) -> Self {
+        self.builder = builder;
+        self
+    }
+
+    /// The cache in the user's cache directory, see [`dirs::cache_dir`].
+    pub fn user() -> anyhow::Result<Self> {
+        Ok(Self::new(dirs::cache_dir("wasmstation")?.join("modules")))
+    }
+
+    /// Create a [`WasmerBackend`] from raw `.wasm` bytes, with the cached
+    /// module if there is one. Otherwise the cart is compiled and cached.
+    pub fn load(&self, wasm_bytes: &[u8], console: &Console) -> anyhow::Result<WasmerBackend> {
+        let cart_hash = utils::content_hash(wasm_bytes);
+        let path = self.path(&cart_hash, console.features());
+
+        if let Ok(module_bytes) = fs::read(&path) {
+            match self
+                .builder
+                .precompiled_for(&module_bytes, &cart_hash, console)
+            {
+                Ok(backend) => return Ok(backend),
+                Err(err) => error!("ignoring cached module {}: {err}", path.display()),
+            }
+        }
+
+        let store = Store::new(self.builder.engine(console.features())?);
+        let module = Module::new(&store, wasm_bytes)?;
+
+        if let Err(err) = self.write_module(&module, wasm_bytes, console.features(), &path) {
+            error!("failed to cache module at {}: {err}", path.display());
+        }
+
+        WasmerBackend::new(store, module, console.create_api())
+    }
+
+    /// Remove every cached module.
+    pub fn clear(&self) -> anyhow::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, cart_hash: &[u8; 32], features: &WasmFeatures) -> PathBuf {
+        self.dir
+            .join(format!("wasmer-{}", wasmer::VERSION))
+            .join(wasmer::Triple::host().to_string())
+            .join(format!(
+                "{}-{:03x}",
+                self.builder.settings(),
+                features.bits()
+            ))
+            .join(format!("{}.module", utils::to_hex(cart_hash)))
+    }
+
+    /// Write `module` to `path` as an artifact, through a temporary file so
+    /// that it's never read half written.
+    fn write_module(
+        &self,
+        module: &Module,
+        wasm_bytes: &[u8],
+        features: &WasmFeatures,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
         }
+
+        let artifact = self
+            .builder
+            .to_artifact(module, wasm_bytes, features, false)?;
+        let tmp = path.with_extension(format!("{}.tmp", process::id()));
+        fs::write(&tmp, artifact)?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+}
+
+/// The [`Header`] of artifacts for this version of wasmer, on `target`, made
+/// with the [`WasmerBuilder`] `settings` and `features`.
+#[cfg(any(doc, not(target_arch = "wasm32")))]
+fn header<'a>(target: &'a str, settings: &'a str, features: &WasmFeatures) -> Header<'a> {
+    Header {
+        wasmstation: artifact::VERSION,
+        engine: "wasmer",
+        engine_version: wasmer::VERSION,
+        target,
+        compiler: settings,
+        features: *features,
+    }
+}
+
+impl Backend for WasmerBackend {
+    fn call_update(&mut self) {
+        let view = self.fn_env.as_ref(&self.store).memory.view(&self.store);
+        host::before_update(&mut ViewMemory { view });
+
+        self.call("update");
+    }
+
+    fn call_start(&mut self) {
+        self.call("start");
     }
 
     fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
@@ -152,12 +635,34 @@ impl Backend for WasmerBackend {
         .expect("write to MOUSE_BUTTONS_ADDR");
     }
 
-    fn write_save_cache(&mut self) -> Option<[u8; 1024]> {
+    fn write_save_cache(&mut self) -> Option<Disk> {
         self.fn_env.as_mut(&mut self.store).write_save()
     }
 
-    fn set_save_cache(&mut self, data: [u8; 1024]) {
-        self.fn_env.as_mut(&mut self.store).api.save_cache.set(data);
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.fn_env.as_mut(&mut self.store).api.save_cache.set(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        // a fresh store drops the old instance, the module is reused as-is.
+        let store = Store::new(self.store.engine().clone());
+        let api = self.fn_env.as_ref(&self.store).api.clone();
+
+        *self = Self::new(store, self.module.clone(), api)?;
+
+        Ok(())
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        let view = self.fn_env.as_ref(&self.store).memory.view(&self.store);
+
+        if let Err(err) = view.read(offset as u64, buf) {
+            error!("error reading memory: {err}");
+        }
+    }
+
+    fn take_host_calls(&mut self) -> HostCallCounts {
+        self.fn_env.as_ref(&self.store).api.take_host_calls()
     }
 }
 
@@ -186,220 +691,124 @@ impl WasmerRuntimeEnv {
         Ok(Self { memory, api })
     }
 
-    pub fn write_save(&self) -> Option<[u8; 1024]> {
+    pub fn write_save(&self) -> Option<Disk> {
         self.api.write_save()
     }
 }
 
-struct Context<'a> {
+/// A cart's memory, accessed through a [`MemoryView`].
+struct ViewMemory<'a> {
     view: MemoryView<'a>,
 }
 
-impl<'a> Context<'a> {
-    fn from_env(env: &'a FunctionEnvMut<'a, WasmerRuntimeEnv>) -> Context<'a> {
-        let view = env.data().memory.view(env);
-
-        Self { view }
-    }
-
-    fn view(&'a self) -> &'a MemoryView<'a> {
-        &self.view
+impl<'a> Source<u8> for ViewMemory<'a> {
+    fn item_at(&self, offset: usize) -> Option<u8> {
+        self.items_at(offset).map(|[item]| item)
     }
 
-    fn fb(&self) -> WasmSliceSinkSource<u8> {
-        let slice = WasmPtr::<u8>::new(FRAMEBUFFER_ADDR as u32)
-            .slice(&self.view, FRAMEBUFFER_SIZE as u32)
-            .unwrap();
+    fn items_at<const L: usize>(&self, offset: usize) -> Option<[u8; L]> {
+        let mut items = [0; L];
 
-        WasmSliceSinkSource { slice }
+        self.read_into(offset, &mut items).then_some(items)
     }
 
-    fn draw_colors(&self) -> u16 {
-        WasmPtr::<u16>::new(DRAW_COLORS_ADDR as u32)
-            .read(&self.view)
-            .unwrap()
+    #[cfg(target_arch = "wasm32")]
+    fn read_into(&self, offset: usize, buf: &mut [u8]) -> bool {
+        self.view.read(offset as u64, buf).is_ok()
     }
-}
 
-struct MemoryViewSource<'a> {
-    view: &'a MemoryView<'a>,
+    #[cfg(not(target_arch = "wasm32"))]
+    fn as_slice(&self) -> Option<&[u8]> {
+        // carts' memory never grows, so it isn't moved while the view borrows
+        // the store.
+        Some(unsafe { self.view.data_unchecked() })
+    }
 }
 
-impl<'a> Source<u8> for MemoryViewSource<'a> {
-    fn item_at(&self, offset: usize) -> Option<u8> {
-        match self.view.read_u8(offset as u64) {
-            Ok(b) => Some(b),
-            Err(_) => None,
-        }
+impl<'a> Sink<u8> for ViewMemory<'a> {
+    fn set_item_at(&mut self, offset: usize, item: u8) {
+        self.write_from(offset, &[item]);
     }
 
-    fn items_at<const L: usize>(&self, offset: usize) -> Option<[u8; L]> {
-        let mut items = [0; L];
+    fn fill(&mut self, item: u8) {
+        self.fill_range(0..self.size(), item);
+    }
 
-        for (i, item) in items.iter_mut().enumerate() {
-            match self.view.read_u8((offset + i) as u64) {
-                Ok(b) => *item = b,
-                Err(_) => return None,
-            }
+    #[cfg(target_arch = "wasm32")]
+    fn write_from(&mut self, offset: usize, items: &[u8]) {
+        if let Err(err) = self.view.write(offset as u64, items) {
+            error!("error writing to memory at {offset:#x}: {err}");
         }
-
-        Some(items)
     }
-}
 
-struct WasmSliceSinkSource<'a, T>
-where
-    T: ValueType + Copy,
-{
-    slice: WasmSlice<'a, T>,
-}
+    /// In the browser, the memory can't be borrowed, so it's written a chunk
+    /// at a time.
+    #[cfg(target_arch = "wasm32")]
+    fn fill_range(&mut self, range: Range<usize>, item: u8) {
+        let chunk = [item; 256];
 
-impl<'a, T> Source<T> for WasmSliceSinkSource<'a, T>
-where
-    T: ValueType + Copy,
-{
-    fn item_at(&self, offset: usize) -> Option<T> {
-        match self.slice.index(offset as u64).read() {
-            Ok(b) => Some(b),
-            Err(_) => None,
+        for start in range.clone().step_by(chunk.len()) {
+            let len = chunk.len().min(range.end - start);
+            self.write_from(start, &chunk[..len]);
         }
     }
 
-    fn items_at<const L: usize>(&self, offset: usize) -> Option<[T; L]> {
-        (0..L)
-            .filter_map(|i| {
-                self.slice
-                    .index((offset + i) as u64)
-                    .read()
-                    .map(Some)
-                    .unwrap_or(None)
-            })
-            .collect::<Vec<T>>()
-            // TODO: remove hot allocation of Vec<T>
-            // WasmSliceSinkSource::items_at isn't being used right now so this isn't high priority.
-            .try_into()
-            .map(Some)
-            .unwrap_or(None)
-    }
-}
-
-impl<'a, T> Sink<T> for WasmSliceSinkSource<'a, T>
-where
-    T: ValueType + Copy,
-{
-    fn set_item_at(&mut self, offset: usize, item: T) {
-        self.slice
-            .index(offset as u64)
-            .write(item)
-            .expect("writing to wasm memory failed");
-    }
-
-    fn fill(&mut self, item: T) {
-        for n in 0..FRAMEBUFFER_SIZE as u64 {
-            self.slice.write(n, item).unwrap();
-        }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        // see `as_slice`, and host functions only make one view at a time.
+        Some(unsafe { self.view.data_unchecked_mut() })
     }
 }
 
-fn trace(env: FunctionEnvMut<WasmerRuntimeEnv>, ptr: WasmPtr<u8>) {
-    let ctx = Context::from_env(&env);
-
-    // WASM4's trace is "supposed" to just be ASCII but UTF-8
-    // is a superset of ASCII so this should be fine.
-    let msg = match ptr.read_utf8_string_with_nul(ctx.view()) {
-        Ok(str) => str,
-        Err(err) => {
-            error!(
-                "Failed to read null-terminated string at {:X?}: {err}",
-                ptr.offset()
-            );
-            return;
-        }
-    };
-
-    env.data().api.print(&msg);
-}
-
-fn tracef(env: FunctionEnvMut<WasmerRuntimeEnv>, fmt: WasmPtr<u8>, args: WasmPtr<u8>) {
-    let ctx = Context::from_env(&env);
-
-    env.data().api.print(&trace::tracef(
-        fmt.offset() as usize,
-        args.offset() as usize,
-        &MemoryViewSource { view: ctx.view() },
-    ));
+impl<'a> GuestMemory for ViewMemory<'a> {
+    fn size(&self) -> usize {
+        self.view.data_size() as usize
+    }
 }
 
-fn trace_utf8(env: FunctionEnvMut<WasmerRuntimeEnv>, ptr: WasmPtr<u8>, len: u32) {
-    let ctx = Context::from_env(&env);
+type Env<'a> = FunctionEnvMut<'a, WasmerRuntimeEnv>;
 
-    let msg = match ptr.read_utf8_string(ctx.view(), len) {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            error!(
-                "Error getting bytes from cart in traceUtf8 at {:X?}: {}",
-                ptr.offset(),
-                err
-            );
-            return;
-        }
-    };
+/// Borrow the game's memory and the [`Api`] at the same time.
+fn split<'a>(env: &'a Env<'_>) -> (ViewMemory<'a>, &'a Api) {
+    let data = env.data();
 
-    env.data().api.print(&msg);
+    (
+        ViewMemory {
+            view: data.memory.view(env),
+        },
+        &data.api,
+    )
 }
 
-fn trace_utf16(env: FunctionEnvMut<WasmerRuntimeEnv>, ptr: WasmPtr<u8>, len: u32) {
-    let ctx = Context::from_env(&env);
+fn trace(env: Env, ptr: u32) {
+    let (mem, api) = split(&env);
+    host::trace(&mem, api, ptr);
+}
 
-    let slice = match ptr.slice(ctx.view(), len) {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            error!(
-                "Error getting bytes from cart in traceUtf16 at {:X?}: {}",
-                ptr.offset(),
-                err
-            );
-            return;
-        }
-    };
+fn tracef(env: Env, fmt: u32, args: u32) {
+    let (mem, api) = split(&env);
+    host::tracef(&mem, api, fmt, args);
+}
 
-    let bytes = match slice.read_to_vec() {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            error!("Error reading WasmSlice to Vec: {err}");
-            return;
-        }
-    };
-
-    // lossy conversion is better here, it's not likely
-    // that the cart will give us an incompatible character.
-    let msg = match String::from_utf16(bytemuck::cast_slice(&bytes)) {
-        Ok(msg) => msg,
-        Err(err) => {
-            error!("Error reading UTF-16 string from bytes: {err}");
-            return;
-        }
-    };
+fn trace_utf8(env: Env, ptr: u32, len: u32) {
+    let (mem, api) = split(&env);
+    host::trace_utf8(&mem, api, ptr, len);
+}
 
-    env.data().api.print(&msg);
+fn trace_utf16(env: Env, ptr: u32, len: u32) {
+    let (mem, api) = split(&env);
+    host::trace_utf16(&mem, api, ptr, len);
 }
 
-fn blit(
-    env: FunctionEnvMut<WasmerRuntimeEnv>,
-    ptr: WasmPtr<u8>,
-    x: i32,
-    y: i32,
-    width: u32,
-    height: u32,
-    flags: u32,
-) {
-    blit_sub(env, ptr, x, y, width, height, 0, 0, width, flags)
+fn blit(env: Env, sprite: u32, x: i32, y: i32, width: u32, height: u32, flags: u32) {
+    let (mut mem, api) = split(&env);
+    host::blit(&mut mem, api, sprite, x, y, width, height, flags);
 }
 
 #[allow(clippy::too_many_arguments)]
 fn blit_sub(
-    env: FunctionEnvMut<WasmerRuntimeEnv>,
-    sprite: WasmPtr<u8>,
+    env: Env,
+    sprite: u32,
     x: i32,
     y: i32,
     width: u32,
@@ -409,127 +818,90 @@ fn blit_sub(
     stride: u32,
     flags: u32,
 ) {
-    let ctx = Context::from_env(&env);
-    let num_bits = stride * (height + src_y) * pixel_width_of_flags(flags);
-    let len = (num_bits + 7) / 8;
-    let sprite_slice = sprite.slice(ctx.view(), len).unwrap();
-
-    let src = WasmSliceSinkSource {
-        slice: sprite_slice,
-    };
-
-    framebuffer::blit_sub(
-        &mut ctx.fb(),
-        &src,
-        x,
-        y,
-        width,
-        height,
-        src_x,
-        src_y,
-        stride,
-        flags,
-        ctx.draw_colors(),
+    let (mut mem, api) = split(&env);
+    host::blit_sub(
+        &mut mem, api, sprite, x, y, width, height, src_x, src_y, stride, flags,
     );
 }
 
-fn line(env: FunctionEnvMut<WasmerRuntimeEnv>, x1: i32, y1: i32, x2: i32, y2: i32) {
-    let ctx = Context::from_env(&env);
-    framebuffer::line(&mut ctx.fb(), ctx.draw_colors(), x1, y1, x2, y2);
+fn line(env: Env, x1: i32, y1: i32, x2: i32, y2: i32) {
+    let (mut mem, api) = split(&env);
+    host::line(&mut mem, api, x1, y1, x2, y2);
 }
 
-fn hline(env: FunctionEnvMut<WasmerRuntimeEnv>, x: i32, y: i32, len: u32) {
-    let ctx = Context::from_env(&env);
-    framebuffer::hline(&mut ctx.fb(), ctx.draw_colors(), x, y, len);
+fn hline(env: Env, x: i32, y: i32, len: u32) {
+    let (mut mem, api) = split(&env);
+    host::hline(&mut mem, api, x, y, len);
 }
 
-fn vline(env: FunctionEnvMut<WasmerRuntimeEnv>, x: i32, y: i32, len: u32) {
-    let ctx = Context::from_env(&env);
-    framebuffer::vline(&mut ctx.fb(), ctx.draw_colors(), x, y, len);
+fn vline(env: Env, x: i32, y: i32, len: u32) {
+    let (mut mem, api) = split(&env);
+    host::vline(&mut mem, api, x, y, len);
 }
 
-fn oval(env: FunctionEnvMut<WasmerRuntimeEnv>, x: i32, y: i32, width: u32, height: u32) {
-    let ctx = Context::from_env(&env);
-    framebuffer::oval(&mut ctx.fb(), ctx.draw_colors(), x, y, width, height);
-}
-fn rect(env: FunctionEnvMut<WasmerRuntimeEnv>, x: i32, y: i32, width: u32, height: u32) {
-    let ctx = Context::from_env(&env);
-    framebuffer::rect(&mut ctx.fb(), ctx.draw_colors(), x, y, width, height);
+fn oval(env: Env, x: i32, y: i32, width: u32, height: u32) {
+    let (mut mem, api) = split(&env);
+    host::oval(&mut mem, api, x, y, width, height);
 }
 
-fn text(env: FunctionEnvMut<WasmerRuntimeEnv>, ptr: WasmPtr<u8>, x: i32, y: i32) {
-    let ctx = Context::from_env(&env);
-    let w4_string = ptr.read_until(ctx.view(), |b| *b == 0).unwrap();
-
-    framebuffer::text(&mut ctx.fb(), &w4_string, x, y, ctx.draw_colors())
+fn rect(env: Env, x: i32, y: i32, width: u32, height: u32) {
+    let (mut mem, api) = split(&env);
+    host::rect(&mut mem, api, x, y, width, height);
 }
 
-fn text_utf8(env: FunctionEnvMut<WasmerRuntimeEnv>, ptr: WasmPtr<u8>, length: u32, x: i32, y: i32) {
-    let ctx = Context::from_env(&env);
-    let slice = ptr.slice(ctx.view(), length).unwrap();
-    let w4_string = slice.read_to_vec().unwrap();
+fn text(env: Env, ptr: u32, x: i32, y: i32) {
+    let (mut mem, api) = split(&env);
+    host::text(&mut mem, api, ptr, x, y);
+}
 
-    framebuffer::text(&mut ctx.fb(), &w4_string, x, y, ctx.draw_colors())
+fn text_utf8(env: Env, ptr: u32, len: u32, x: i32, y: i32) {
+    let (mut mem, api) = split(&env);
+    host::text_utf8(&mut mem, api, ptr, len, x, y);
 }
 
-fn text_utf16(
-    env: FunctionEnvMut<WasmerRuntimeEnv>,
-    ptr: WasmPtr<u8>,
-    length: u32,
-    x: i32,
-    y: i32,
-) {
-    let ctx = Context::from_env(&env);
-    let slice = ptr.slice(ctx.view(), length).unwrap();
-    let w4_string = slice.read_to_vec().unwrap();
-
-    framebuffer::text(
-        &mut ctx.fb(),
-        bytemuck::cast_slice::<u8, u16>(&w4_string),
-        x,
-        y,
-        ctx.draw_colors(),
-    )
+fn text_utf16(env: Env, ptr: u32, len: u32, x: i32, y: i32) {
+    let (mut mem, api) = split(&env);
+    host::text_utf16(&mut mem, api, ptr, len, x, y);
 }
 
-fn diskr(env: FunctionEnvMut<WasmerRuntimeEnv>, dest: WasmPtr<u8>, size: u32) -> u32 {
-    let ctx = Context::from_env(&env);
-    let bytes_read = u32::min(size, 1024);
+fn diskr(env: Env, dest: u32, len: u32) -> u32 {
+    let (mut mem, api) = split(&env);
+    host::diskr(&mut mem, api, dest, len)
+}
 
-    let mut src = env.data().api.save_cache.get().to_vec();
-    src.resize(bytes_read as usize, 0);
+fn diskw(env: Env, src: u32, len: u32) -> u32 {
+    let (mem, api) = split(&env);
+    host::diskw(&mem, api, src, len)
+}
 
-    dest.slice(ctx.view(), bytes_read)
-        .expect("get memory slice")
-        .write_slice(&src)
-        .expect("write slice to memory");
+fn tone(env: Env, frequency: u32, duration: u32, volume: u32, flags: u32) {
+    host::tone(&env.data().api, frequency, duration, volume, flags);
+}
 
-    bytes_read
+fn cart_count(env: Env) -> u32 {
+    system::cart_count(&env.data().api)
 }
 
-fn diskw(env: FunctionEnvMut<WasmerRuntimeEnv>, src: WasmPtr<u8>, size: u32) -> u32 {
-    let ctx = Context::from_env(&env);
-    let bytes_written = u32::min(size, 1024);
+fn cart_meta(env: Env, index: u32, key: u32, key_len: u32, dest: u32, len: u32) -> u32 {
+    let (mut mem, api) = split(&env);
+    system::cart_meta(&mut mem, api, index, key, key_len, dest, len)
+}
 
-    let mut buf = src
-        .slice(ctx.view(), bytes_written)
-        .expect("get memory slice")
-        .read_to_vec()
-        .expect("get memory slice to vec");
-    buf.resize(1024, 0);
+fn cart_thumbnail(env: Env, index: u32, dest: u32) -> u32 {
+    let (mut mem, api) = split(&env);
+    system::cart_thumbnail(&mut mem, api, index, dest)
+}
 
-    env.data().api.save_cache.set(buf.try_into().unwrap());
-    env.data().api.needs_write.set(true);
+fn launch(env: Env, index: u32) {
+    system::launch(&env.data().api, index);
+}
 
-    bytes_written
+fn get_setting(env: Env, key: u32, key_len: u32, dest: u32, len: u32) -> u32 {
+    let (mut mem, api) = split(&env);
+    system::get_setting(&mut mem, api, key, key_len, dest, len)
 }
 
-fn tone(
-    env: FunctionEnvMut<WasmerRuntimeEnv>,
-    frequency: u32,
-    duration: u32,
-    volume: u32,
-    flags: u32,
-) {
-    env.data().api.tone(frequency, duration, volume, flags)
+fn set_setting(env: Env, key: u32, key_len: u32, value: u32, len: u32) {
+    let (mem, api) = split(&env);
+    system::set_setting(&mem, api, key, key_len, value, len);
 }
@@ -1,30 +1,69 @@
 //! A [`Backend`] based on the [`wasmi`] WebAssembly engine.
 
-extern crate alloc;
-
-use core::{array, str};
-
-use crate::core::{framebuffer, utils, wasm4, Api, Backend, Console, Sink, Source};
-use alloc::{string::String, vec::Vec};
-use wasmi::{
-    AsContext, AsContextMut, Caller, Engine, Func, Linker, Memory, MemoryType, Module, Store,
+use crate::core::{
+    features::WasmFeatures,
+    host, system, utils,
+    wasm4::{self, HostCallCounts},
+    Api, Backend, Console, Disk,
 };
+use wasmi::{Caller, Config, Engine, Func, Linker, Memory, MemoryType, Module, Store};
 
 pub use wasmi;
 
 /// A `no_std` backend for WebAssembly games.
 pub struct WasmiBackend {
     store: Store<WasmiBackendState>,
+    module: Module,
     start: Option<Func>,
     update: Option<Func>,
 }
 
+/// The WebAssembly proposals wasmi implements.
+const FEATURES: WasmFeatures = WasmFeatures {
+    simd: false,
+    ..WasmFeatures::ALL
+};
+
 impl WasmiBackend {
-    pub fn from_bytes(bytes: &[u8], console: &Console) -> Result<Self, wasmi::Error> {
-        let engine = Engine::default();
-        let module = Module::new(&engine, bytes).map_err(wasmi::Error::from)?;
+    pub fn from_bytes(bytes: &[u8], console: &Console) -> anyhow::Result<Self> {
+        let features = console.features();
+        // without std, neither error implements `Error`, so they're kept as messages.
+        features
+            .check("wasmi", &FEATURES)
+            .map_err(anyhow::Error::msg)?;
+
+        let mut config = Config::default();
+        config
+            .wasm_mutable_global(features.mutable_global)
+            .wasm_sign_extension(features.sign_extension)
+            .wasm_saturating_float_to_int(features.saturating_float_to_int)
+            .wasm_multi_value(features.multi_value)
+            .wasm_bulk_memory(features.bulk_memory)
+            .wasm_reference_types(features.reference_types)
+            .wasm_tail_call(features.tail_call)
+            .wasm_extended_const(features.extended_const);
+
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, bytes).map_err(anyhow::Error::msg)?;
+
+        let mut backend = Self {
+            store: Store::new(&engine, WasmiBackendState::default()),
+            module,
+            start: None,
+            update: None,
+        };
+        backend
+            .instantiate(console.create_api())
+            .map_err(anyhow::Error::msg)?;
+
+        Ok(backend)
+    }
 
-        let mut store: Store<WasmiBackendState> = Store::new(&engine, WasmiBackendState::default());
+    /// Instantiate the module in a fresh [`Store`] with freshly initialized memory.
+    fn instantiate(&mut self, api: Api) -> Result<(), wasmi::Error> {
+        let module = &self.module;
+        let mut store: Store<WasmiBackendState> =
+            Store::new(module.engine(), WasmiBackendState::default());
         let memory = Memory::new(&mut store, MemoryType::new(1, Some(1)).unwrap())
             .map_err(wasmi::Error::from)?;
 
@@ -43,10 +82,10 @@ impl WasmiBackend {
         // hacky, but strangely I don't think the API has an elegant way to do this
         *store.data_mut() = WasmiBackendState {
             memory: Some(memory),
-            api: Some(console.create_api()),
+            api: Some(api),
         };
 
-        let mut linker = <Linker<WasmiBackendState>>::new(&engine);
+        let mut linker = <Linker<WasmiBackendState>>::new(module.engine());
         linker
             .define("env", "memory", store.data().memory.unwrap())
             .map_err(wasmi::Error::from)?;
@@ -77,35 +116,41 @@ impl WasmiBackend {
                 .map_err(wasmi::Error::from)?;
         }
 
+        if store.data().api().system().is_some() {
+            let system: [(&str, Func); 6] = [
+                ("cartCount", Func::wrap(&mut store, cart_count)),
+                ("cartMeta", Func::wrap(&mut store, cart_meta)),
+                ("cartThumbnail", Func::wrap(&mut store, cart_thumbnail)),
+                ("launch", Func::wrap(&mut store, launch)),
+                ("getSetting", Func::wrap(&mut store, get_setting)),
+                ("setSetting", Func::wrap(&mut store, set_setting)),
+            ];
+
+            for (name, func) in system {
+                linker
+                    .define("env", name, func)
+                    .map_err(wasmi::Error::from)?;
+            }
+        }
+
         let instance = linker
-            .instantiate(&mut store, &module)
+            .instantiate(&mut store, module)
             .map_err(wasmi::Error::from)?
             .start(&mut store)
             .map_err(wasmi::Error::from)?;
 
-        let start: Option<Func> = instance.get_func(&store, "start");
-        let update: Option<Func> = instance.get_func(&store, "update");
+        self.start = instance.get_func(&store, "start");
+        self.update = instance.get_func(&store, "update");
+        self.store = store;
 
-        Ok(Self {
-            store,
-            start,
-            update,
-        })
+        Ok(())
     }
 }
 
 impl Backend for WasmiBackend {
     fn call_update(&mut self) {
         let mem = self.store.data().memory();
-
-        let mut flags: [u8; 1] = [0];
-        if let Err(err) = mem.read(&self.store, wasm4::SYSTEM_FLAGS_ADDR, &mut flags) {
-            log::error!("error reading system flags: {err}");
-        }
-
-        if wasm4::SYSTEM_PRESERVE_FRAMEBUFFER & flags[0] == 0 {
-            framebuffer::clear(&mut framebuffer(&mut self.store, mem));
-        }
+        host::before_update(mem.data_mut(&mut self.store));
 
         if let Some(update) = &self.update {
             if let Err(err) = update.call(&mut self.store, &[], &mut []) {
@@ -193,84 +238,86 @@ impl Backend for WasmiBackend {
         }
     }
 
-    fn write_save_cache(&mut self) -> Option<[u8; 1024]> {
+    fn write_save_cache(&mut self) -> Option<Disk> {
         self.store.data().api().write_save()
     }
 
-    fn set_save_cache(&mut self, data: [u8; 1024]) {
-        self.store.data().api().save_cache.set(data);
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.store.data().api().save_cache.set(disk);
     }
-}
 
-fn trace(caller: Caller<'_, WasmiBackendState>, mut ptr: u32) {
-    let mut msg = String::new();
-    let mut buf: [u8; 1] = [1];
+    fn reset(&mut self) -> anyhow::Result<()> {
+        // wasmi's errors only implement `std::error::Error` with `std`.
+        self.instantiate(self.store.data().api().clone())
+            .map_err(|err| anyhow::anyhow!("{err}"))
+    }
 
-    while buf[0] != 0 {
-        if let Err(err) = caller.data().memory().read(&caller, ptr as usize, &mut buf) {
-            log::error!("error reading bytes in trace: {err}");
-            return;
-        };
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        if let Err(err) = self.store.data().memory().read(&self.store, offset, buf) {
+            log::error!("error reading memory: {err}");
+        }
+    }
 
-        ptr += 1;
-        msg.push(buf[0] as char);
+    fn take_host_calls(&mut self) -> HostCallCounts {
+        self.store.data().api().take_host_calls()
     }
+}
 
-    caller.data().api().print(&msg);
+#[derive(Default)]
+struct WasmiBackendState {
+    pub memory: Option<Memory>,
+    pub api: Option<Api>,
 }
 
-fn tracef(mut caller: Caller<'_, WasmiBackendState>, fmt: u32, args: u32) {
-    let msg = crate::core::tracef(
-        fmt as usize,
-        args as usize,
-        &WasmiSlice {
-            offset: 0,
-            len: 64000,
-            mem: caller.data().memory(),
-            ctx: &mut caller,
-        },
-    );
+impl WasmiBackendState {
+    // eh... haha :)
+    pub fn memory(&self) -> Memory {
+        self.memory.unwrap()
+    }
 
-    caller.data().api().print(&msg);
+    pub fn api(&self) -> &Api {
+        self.api.as_ref().unwrap()
+    }
 }
 
-fn trace_utf8(caller: Caller<'_, WasmiBackendState>, ptr: u32, len: u32) {
-    let mut buf = alloc::vec![0; len as usize];
-    if let Err(err) = caller.data().memory().read(&caller, ptr as usize, &mut buf) {
-        log::error!("error reading traceUtf8 bytes: {err}");
-    }
+type Ctx<'a> = Caller<'a, WasmiBackendState>;
 
-    caller.data().api().print(&String::from_utf8_lossy(&buf));
+/// Borrow the game's memory and the [`Api`] at the same time.
+fn split<'a>(caller: &'a mut Ctx<'_>) -> (&'a mut [u8], &'a Api) {
+    let memory = caller.data().memory();
+    let (mem, state) = memory.data_and_store_mut(caller);
+
+    (mem, state.api())
 }
 
-fn trace_utf16(caller: Caller<'_, WasmiBackendState>, ptr: u32, len: u32) {
-    let mut buf = alloc::vec![0; len as usize];
-    if let Err(err) = caller.data().memory().read(&caller, ptr as usize, &mut buf) {
-        log::error!("error reading traceUtf16 bytes: {err}");
-    }
+fn trace(mut caller: Ctx, ptr: u32) {
+    let (mem, api) = split(&mut caller);
+    host::trace(mem, api, ptr);
+}
 
-    caller
-        .data()
-        .api()
-        .print(&String::from_utf16_lossy(bytemuck::cast_slice(&buf)));
+fn tracef(mut caller: Ctx, fmt: u32, args: u32) {
+    let (mem, api) = split(&mut caller);
+    host::tracef(mem, api, fmt, args);
 }
 
-#[allow(clippy::too_many_arguments)]
-fn blit(
-    caller: Caller<'_, WasmiBackendState>,
-    ptr: u32,
-    x: i32,
-    y: i32,
-    width: u32,
-    height: u32,
-    flags: u32,
-) {
-    blit_sub(caller, ptr, x, y, width, height, 0, 0, width, flags)
+fn trace_utf8(mut caller: Ctx, ptr: u32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::trace_utf8(mem, api, ptr, len);
+}
+
+fn trace_utf16(mut caller: Ctx, ptr: u32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::trace_utf16(mem, api, ptr, len);
+}
+
+fn blit(mut caller: Ctx, sprite: u32, x: i32, y: i32, width: u32, height: u32, flags: u32) {
+    let (mem, api) = split(&mut caller);
+    host::blit(mem, api, sprite, x, y, width, height, flags);
 }
 
 #[allow(clippy::too_many_arguments)]
 fn blit_sub(
-    mut caller: Caller<'_, WasmiBackendState>,
+    mut caller: Ctx,
     sprite: u32,
     x: i32,
     y: i32,
@@ -281,271 +328,90 @@ fn blit_sub(
     stride: u32,
     flags: u32,
 ) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    let num_bits = stride * (height + src_y) * framebuffer::pixel_width_of_flags(flags);
-
-    let mut sprite_buf = alloc::vec![0; ((num_bits + 7) / 8) as usize];
-    if let Err(err) = caller
-        .data()
-        .memory()
-        .read(&caller, sprite as usize, &mut sprite_buf)
-    {
-        log::error!("error reading sprite bytes: {err}");
-    }
-
-    framebuffer::blit_sub(
-        &mut framebuffer(&mut caller, mem),
-        &sprite_buf,
-        x,
-        y,
-        width,
-        height,
-        src_x,
-        src_y,
-        stride,
-        flags,
-        dc,
+    let (mem, api) = split(&mut caller);
+    host::blit_sub(
+        mem, api, sprite, x, y, width, height, src_x, src_y, stride, flags,
     );
 }
 
-fn line(mut caller: Caller<'_, WasmiBackendState>, x1: i32, y1: i32, x2: i32, y2: i32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    framebuffer::line(&mut framebuffer(&mut caller, mem), dc, x1, y1, x2, y2);
+fn line(mut caller: Ctx, x1: i32, y1: i32, x2: i32, y2: i32) {
+    let (mem, api) = split(&mut caller);
+    host::line(mem, api, x1, y1, x2, y2);
 }
 
-fn hline(mut caller: Caller<'_, WasmiBackendState>, x: i32, y: i32, len: u32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    framebuffer::hline(&mut framebuffer(&mut caller, mem), dc, x, y, len);
+fn hline(mut caller: Ctx, x: i32, y: i32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::hline(mem, api, x, y, len);
 }
 
-fn vline(mut caller: Caller<'_, WasmiBackendState>, x: i32, y: i32, len: u32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    framebuffer::vline(&mut framebuffer(&mut caller, mem), dc, x, y, len);
+fn vline(mut caller: Ctx, x: i32, y: i32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::vline(mem, api, x, y, len);
 }
 
-fn oval(mut caller: Caller<'_, WasmiBackendState>, x: i32, y: i32, width: u32, height: u32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    framebuffer::oval(&mut framebuffer(&mut caller, mem), dc, x, y, width, height);
+fn oval(mut caller: Ctx, x: i32, y: i32, width: u32, height: u32) {
+    let (mem, api) = split(&mut caller);
+    host::oval(mem, api, x, y, width, height);
 }
 
-fn rect(mut caller: Caller<'_, WasmiBackendState>, x: i32, y: i32, width: u32, height: u32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    framebuffer::rect(&mut framebuffer(&mut caller, mem), dc, x, y, width, height);
+fn rect(mut caller: Ctx, x: i32, y: i32, width: u32, height: u32) {
+    let (mem, api) = split(&mut caller);
+    host::rect(mem, api, x, y, width, height);
 }
 
-fn text(mut caller: Caller<'_, WasmiBackendState>, mut ptr: u32, x: i32, y: i32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    let mut text: Vec<u8> = Vec::new();
-    let mut buf: [u8; 1] = [1];
-
-    while buf[0] != 0 {
-        if let Err(err) = caller.data().memory().read(&caller, ptr as usize, &mut buf) {
-            log::error!("error reading bytes in text: {err}");
-            return;
-        };
-
-        ptr += 1;
-        text.push(buf[0]);
-    }
-
-    framebuffer::text(&mut framebuffer(&mut caller, mem), &text, x, y, dc);
+fn text(mut caller: Ctx, ptr: u32, x: i32, y: i32) {
+    let (mem, api) = split(&mut caller);
+    host::text(mem, api, ptr, x, y);
 }
 
-fn text_utf8(mut caller: Caller<'_, WasmiBackendState>, ptr: u32, len: u32, x: i32, y: i32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    let mut text = alloc::vec![0; len as usize];
-    if let Err(err) = caller
-        .data()
-        .memory()
-        .read(&caller, ptr as usize, &mut text)
-    {
-        log::error!("error reading traceUtf8 bytes: {err}");
-    }
-
-    framebuffer::text(&mut framebuffer(&mut caller, mem), &text, x, y, dc);
+fn text_utf8(mut caller: Ctx, ptr: u32, len: u32, x: i32, y: i32) {
+    let (mem, api) = split(&mut caller);
+    host::text_utf8(mem, api, ptr, len, x, y);
 }
 
-fn text_utf16(mut caller: Caller<'_, WasmiBackendState>, ptr: u32, len: u32, x: i32, y: i32) {
-    let mem = caller.data().memory();
-    let dc = draw_colors(&caller, mem);
-
-    let mut text = alloc::vec![0; len as usize];
-    if let Err(err) = caller
-        .data()
-        .memory()
-        .read(&caller, ptr as usize, &mut text)
-    {
-        log::error!("error reading traceUtf8 bytes: {err}");
-    }
-
-    framebuffer::text(
-        &mut framebuffer(&mut caller, mem),
-        bytemuck::cast_slice::<u8, u16>(&text),
-        x,
-        y,
-        dc,
-    );
+fn text_utf16(mut caller: Ctx, ptr: u32, len: u32, x: i32, y: i32) {
+    let (mem, api) = split(&mut caller);
+    host::text_utf16(mem, api, ptr, len, x, y);
 }
 
-fn diskr(mut caller: Caller<'_, WasmiBackendState>, dest: u32, len: u32) -> u32 {
-    let data = caller.data().api().save_cache.get();
-
-    if let Err(err) = caller
-        .data()
-        .memory()
-        .write(&mut caller, dest as usize, &data)
-    {
-        log::error!("error writing disk cache to memory: {err}");
-    };
-
-    u32::min(len, 1024)
+fn diskr(mut caller: Ctx, dest: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    host::diskr(mem, api, dest, len)
 }
 
-fn diskw(mut caller: Caller<'_, WasmiBackendState>, src: u32, len: u32) -> u32 {
-    let mut buf = alloc::vec![0; len as usize];
-    if let Err(err) = caller
-        .data()
-        .memory()
-        .read(&mut caller, src as usize, &mut buf)
-    {
-        log::error!("error reading disk cache from memory: {err}");
-    };
-
-    buf.resize(1024, 0);
-
-    caller.data().api().needs_write.set(true);
-    caller
-        .data()
-        .api()
-        .save_cache
-        .set(buf.try_into().expect("resize disk write contents"));
-
-    u32::min(len, 1024)
+fn diskw(mut caller: Ctx, src: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    host::diskw(mem, api, src, len)
 }
 
-fn tone(caller: Caller<'_, WasmiBackendState>, freq: u32, dura: u32, vol: u32, flags: u32) {
-    caller.data().api().tone(freq, dura, vol, flags);
+fn tone(caller: Ctx, frequency: u32, duration: u32, volume: u32, flags: u32) {
+    host::tone(caller.data().api(), frequency, duration, volume, flags);
 }
 
-struct WasmiSlice<C> {
-    offset: usize,
-    len: usize,
-    mem: Memory,
-    ctx: C,
+fn cart_count(caller: Ctx) -> u32 {
+    system::cart_count(caller.data().api())
 }
 
-impl<C> Source<u8> for WasmiSlice<C>
-where
-    C: AsContext,
-{
-    fn item_at(&self, offset: usize) -> Option<u8> {
-        if self.len < offset {
-            return None;
-        }
-
-        let mut buf: [u8; 1] = [0];
-
-        if self
-            .mem
-            .read(&self.ctx, self.offset + offset, &mut buf)
-            .is_err()
-        {
-            return None;
-        }
-
-        Some(buf[0])
-    }
-
-    fn items_at<const L: usize>(&self, offset: usize) -> Option<[u8; L]> {
-        if self.len < (offset + L) {
-            return None;
-        }
-
-        let mut buf: [u8; L] = array::from_fn(|_| 0);
-
-        if self
-            .mem
-            .read(&self.ctx, self.offset + offset, &mut buf)
-            .is_err()
-        {
-            return None;
-        }
-
-        Some(buf)
-    }
+fn cart_meta(mut caller: Ctx, index: u32, key: u32, key_len: u32, dest: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    system::cart_meta(mem, api, index, key, key_len, dest, len)
 }
 
-impl<C> Sink<u8> for WasmiSlice<C>
-where
-    C: AsContextMut,
-{
-    fn set_item_at(&mut self, offset: usize, item: u8) {
-        if self.len < offset {
-            return;
-        }
-
-        if let Err(err) = self.mem.write(&mut self.ctx, self.offset + offset, &[item]) {
-            log::error!("Couldn't set item at in WasmiSlice: {err}");
-        }
-    }
-
-    fn fill(&mut self, item: u8) {
-        for n in self.offset..(self.offset + self.len) {
-            if let Err(err) = self.mem.write(&mut self.ctx, n, &[item]) {
-                log::error!("Couldn't fill WasmiSlice: {err}");
-            }
-        }
-    }
+fn cart_thumbnail(mut caller: Ctx, index: u32, dest: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    system::cart_thumbnail(mem, api, index, dest)
 }
 
-#[derive(Default)]
-struct WasmiBackendState {
-    pub memory: Option<Memory>,
-    pub api: Option<Api>,
+fn launch(caller: Ctx, index: u32) {
+    system::launch(caller.data().api(), index);
 }
 
-impl WasmiBackendState {
-    // eh... haha :)
-    pub fn memory(&self) -> Memory {
-        self.memory.unwrap()
-    }
-
-    pub fn api(&self) -> &Api {
-        self.api.as_ref().unwrap()
-    }
+fn get_setting(mut caller: Ctx, key: u32, key_len: u32, dest: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    system::get_setting(mem, api, key, key_len, dest, len)
 }
 
-fn framebuffer<C: AsContextMut>(ctx: C, mem: Memory) -> WasmiSlice<C> {
-    WasmiSlice {
-        offset: wasm4::FRAMEBUFFER_ADDR,
-        len: wasm4::FRAMEBUFFER_SIZE,
-        mem,
-        ctx,
-    }
-}
-
-fn draw_colors<C: AsContext>(ctx: C, mem: Memory) -> u16 {
-    let mut buf: [u8; 2] = [0, 0];
-
-    if let Err(err) = mem.read(ctx, wasm4::DRAW_COLORS_ADDR, &mut buf) {
-        log::error!("error reading draw colors: {err}");
-    }
-
-    bytemuck::cast(buf)
+fn set_setting(mut caller: Ctx, key: u32, key_len: u32, value: u32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    system::set_setting(mem, api, key, key_len, value, len);
 }
@@ -0,0 +1,349 @@
+//! A [`Backend`] based on the [`wasmtime`] WebAssembly engine.
+
+use wasmtime::{Caller, Config, Engine, Linker, Memory, MemoryType, Module, Store, TypedFunc};
+
+use crate::core::{
+    features::WasmFeatures,
+    host, system, utils,
+    wasm4::{self, HostCallCounts},
+    Api, Backend, Console, Disk,
+};
+
+pub use wasmtime;
+
+/// A backend for WebAssembly games using the Cranelift based [`wasmtime`] runtime.
+pub struct WasmtimeBackend {
+    module: Module,
+    store: Store<WasmtimeBackendState>,
+    start: Option<TypedFunc<(), ()>>,
+    update: Option<TypedFunc<(), ()>>,
+}
+
+/// The WebAssembly proposals wasmtime implements. Mutable globals, sign
+/// extension and saturating float to int can't be turned off.
+const FEATURES: WasmFeatures = WasmFeatures {
+    tail_call: false,
+    extended_const: false,
+    ..WasmFeatures::ALL
+};
+
+impl WasmtimeBackend {
+    /// Create a [`WasmtimeBackend`] from raw `.wasm` bytes.
+    pub fn from_bytes(wasm_bytes: &[u8], console: &Console) -> anyhow::Result<Self> {
+        let engine = Self::engine(console.features())?;
+        let module = Module::new(&engine, wasm_bytes)?;
+
+        Self::new(module, console.create_api())
+    }
+
+    /// Start a [`WasmtimeBackend`] without compiling at runtime from a precompiled
+    /// `.cwasm` artifact, as created by [`Engine::precompile_module`] with the
+    /// [`WasmtimeBackend::engine`] for the console's features.
+    ///
+    /// The artifact must come from the same version of wasmtime with a compatible
+    /// configuration, [`Module::deserialize`] only checks the parts that can be checked.
+    pub fn precompiled(module_bytes: &[u8], console: &Console) -> anyhow::Result<Self> {
+        let engine = Self::engine(console.features())?;
+        let module = unsafe { Module::deserialize(&engine, module_bytes)? };
+
+        Self::new(module, console.create_api())
+    }
+
+    /// The [`Engine`] compiling carts that use `features`.
+    pub fn engine(features: &WasmFeatures) -> anyhow::Result<Engine> {
+        features.check("wasmtime", &FEATURES)?;
+
+        let mut config = Config::new();
+        config
+            .wasm_multi_value(features.multi_value)
+            .wasm_bulk_memory(features.bulk_memory)
+            .wasm_reference_types(features.reference_types)
+            .wasm_simd(features.simd);
+
+        Engine::new(&config)
+    }
+
+    /// Create a [`WasmtimeBackend`] by instantiating a compiled [`Module`].
+    fn new(module: Module, api: Api) -> anyhow::Result<Self> {
+        let mut store = Store::new(module.engine(), WasmtimeBackendState { memory: None, api });
+
+        // this is all the memory that the game is allowed to use.
+        let memory = Memory::new(&mut store, MemoryType::new(1, Some(1)))?;
+        memory.write(&mut store, wasm4::PALETTE_ADDR, &utils::default_palette())?;
+        memory.write(
+            &mut store,
+            wasm4::DRAW_COLORS_ADDR,
+            &utils::default_draw_colors(),
+        )?;
+        memory.write(
+            &mut store,
+            wasm4::FRAMEBUFFER_ADDR,
+            &utils::default_framebuffer(),
+        )?;
+        store.data_mut().memory = Some(memory);
+
+        // see https://wasm4.org/docs/reference/functions
+        let mut linker = <Linker<WasmtimeBackendState>>::new(module.engine());
+        linker.define(&store, "env", "memory", memory)?;
+        linker
+            .func_wrap("env", "trace", trace)?
+            .func_wrap("env", "tracef", tracef)?
+            .func_wrap("env", "traceUtf8", trace_utf8)?
+            .func_wrap("env", "traceUtf16", trace_utf16)?
+            .func_wrap("env", "blit", blit)?
+            .func_wrap("env", "blitSub", blit_sub)?
+            .func_wrap("env", "line", line)?
+            .func_wrap("env", "hline", hline)?
+            .func_wrap("env", "vline", vline)?
+            .func_wrap("env", "oval", oval)?
+            .func_wrap("env", "rect", rect)?
+            .func_wrap("env", "text", text)?
+            .func_wrap("env", "textUtf8", text_utf8)?
+            .func_wrap("env", "textUtf16", text_utf16)?
+            .func_wrap("env", "diskr", diskr)?
+            .func_wrap("env", "diskw", diskw)?
+            .func_wrap("env", "tone", tone)?;
+
+        if store.data().api.system().is_some() {
+            linker
+                .func_wrap("env", "cartCount", cart_count)?
+                .func_wrap("env", "cartMeta", cart_meta)?
+                .func_wrap("env", "cartThumbnail", cart_thumbnail)?
+                .func_wrap("env", "launch", launch)?
+                .func_wrap("env", "getSetting", get_setting)?
+                .func_wrap("env", "setSetting", set_setting)?;
+        }
+
+        let instance = linker.instantiate(&mut store, &module)?;
+
+        Ok(Self {
+            start: instance.get_typed_func(&mut store, "start").ok(),
+            update: instance.get_typed_func(&mut store, "update").ok(),
+            module,
+            store,
+        })
+    }
+
+    fn memory(&self) -> Memory {
+        self.store.data().memory()
+    }
+}
+
+impl Backend for WasmtimeBackend {
+    fn call_update(&mut self) {
+        host::before_update(self.memory().data_mut(&mut self.store));
+
+        if let Some(update) = &self.update {
+            if let Err(err) = update.call(&mut self.store, ()) {
+                log::error!("error calling 'update': {err:?}");
+            }
+        }
+    }
+
+    fn call_start(&mut self) {
+        if let Some(start) = &self.start {
+            if let Err(err) = start.call(&mut self.store, ()) {
+                log::error!("error calling 'start': {err:?}");
+            }
+        }
+    }
+
+    fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
+        let mem = self.memory().data(&self.store);
+
+        framebuffer.copy_from_slice(
+            &mem[wasm4::FRAMEBUFFER_ADDR..wasm4::FRAMEBUFFER_ADDR + wasm4::FRAMEBUFFER_SIZE],
+        );
+        palette.copy_from_slice(&mem[wasm4::PALETTE_ADDR..wasm4::PALETTE_ADDR + 16]);
+    }
+
+    fn read_system_flags(&self) -> u8 {
+        self.memory().data(&self.store)[wasm4::SYSTEM_FLAGS_ADDR]
+    }
+
+    fn set_gamepad(&mut self, gamepad: u32) {
+        let mem = self.memory().data_mut(&mut self.store);
+
+        mem[wasm4::GAMEPAD1_ADDR..wasm4::GAMEPAD1_ADDR + 4].copy_from_slice(&gamepad.to_le_bytes());
+    }
+
+    fn set_mouse(&mut self, x: i16, y: i16, buttons: u8) {
+        let mem = self.memory().data_mut(&mut self.store);
+
+        mem[wasm4::MOUSE_X_ADDR..wasm4::MOUSE_X_ADDR + 2].copy_from_slice(&x.to_le_bytes());
+        mem[wasm4::MOUSE_Y_ADDR..wasm4::MOUSE_Y_ADDR + 2].copy_from_slice(&y.to_le_bytes());
+        mem[wasm4::MOUSE_BUTTONS_ADDR] = buttons;
+    }
+
+    fn write_save_cache(&mut self) -> Option<Disk> {
+        self.store.data().api.write_save()
+    }
+
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.store.data().api.save_cache.set(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        // a fresh store drops the old instance, the module is reused as-is.
+        *self = Self::new(self.module.clone(), self.store.data().api.clone())?;
+
+        Ok(())
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        if let Err(err) = self.memory().read(&self.store, offset, buf) {
+            log::error!("error reading memory: {err}");
+        }
+    }
+
+    fn take_host_calls(&mut self) -> HostCallCounts {
+        self.store.data().api.take_host_calls()
+    }
+}
+
+struct WasmtimeBackendState {
+    memory: Option<Memory>,
+    api: Api,
+}
+
+impl WasmtimeBackendState {
+    fn memory(&self) -> Memory {
+        self.memory.expect("memory is created before instantiating")
+    }
+}
+
+type Ctx<'a> = Caller<'a, WasmtimeBackendState>;
+
+/// Borrow the game's memory and the [`Api`] at the same time.
+fn split<'a>(caller: &'a mut Ctx<'_>) -> (&'a mut [u8], &'a Api) {
+    let memory = caller.data().memory();
+    let (mem, state) = memory.data_and_store_mut(caller);
+
+    (mem, &state.api)
+}
+
+fn trace(mut caller: Ctx, ptr: u32) {
+    let (mem, api) = split(&mut caller);
+    host::trace(mem, api, ptr);
+}
+
+fn tracef(mut caller: Ctx, fmt: u32, args: u32) {
+    let (mem, api) = split(&mut caller);
+    host::tracef(mem, api, fmt, args);
+}
+
+fn trace_utf8(mut caller: Ctx, ptr: u32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::trace_utf8(mem, api, ptr, len);
+}
+
+fn trace_utf16(mut caller: Ctx, ptr: u32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::trace_utf16(mem, api, ptr, len);
+}
+
+fn blit(mut caller: Ctx, sprite: u32, x: i32, y: i32, width: u32, height: u32, flags: u32) {
+    let (mem, api) = split(&mut caller);
+    host::blit(mem, api, sprite, x, y, width, height, flags);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn blit_sub(
+    mut caller: Ctx,
+    sprite: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    src_x: u32,
+    src_y: u32,
+    stride: u32,
+    flags: u32,
+) {
+    let (mem, api) = split(&mut caller);
+    host::blit_sub(
+        mem, api, sprite, x, y, width, height, src_x, src_y, stride, flags,
+    );
+}
+
+fn line(mut caller: Ctx, x1: i32, y1: i32, x2: i32, y2: i32) {
+    let (mem, api) = split(&mut caller);
+    host::line(mem, api, x1, y1, x2, y2);
+}
+
+fn hline(mut caller: Ctx, x: i32, y: i32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::hline(mem, api, x, y, len);
+}
+
+fn vline(mut caller: Ctx, x: i32, y: i32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    host::vline(mem, api, x, y, len);
+}
+
+fn oval(mut caller: Ctx, x: i32, y: i32, width: u32, height: u32) {
+    let (mem, api) = split(&mut caller);
+    host::oval(mem, api, x, y, width, height);
+}
+
+fn rect(mut caller: Ctx, x: i32, y: i32, width: u32, height: u32) {
+    let (mem, api) = split(&mut caller);
+    host::rect(mem, api, x, y, width, height);
+}
+
+fn text(mut caller: Ctx, ptr: u32, x: i32, y: i32) {
+    let (mem, api) = split(&mut caller);
+    host::text(mem, api, ptr, x, y);
+}
+
+fn text_utf8(mut caller: Ctx, ptr: u32, len: u32, x: i32, y: i32) {
+    let (mem, api) = split(&mut caller);
+    host::text_utf8(mem, api, ptr, len, x, y);
+}
+
+fn text_utf16(mut caller: Ctx, ptr: u32, len: u32, x: i32, y: i32) {
+    let (mem, api) = split(&mut caller);
+    host::text_utf16(mem, api, ptr, len, x, y);
+}
+
+fn diskr(mut caller: Ctx, dest: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    host::diskr(mem, api, dest, len)
+}
+
+fn diskw(mut caller: Ctx, src: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    host::diskw(mem, api, src, len)
+}
+
+fn tone(caller: Ctx, frequency: u32, duration: u32, volume: u32, flags: u32) {
+    host::tone(&caller.data().api, frequency, duration, volume, flags);
+}
+
+fn cart_count(caller: Ctx) -> u32 {
+    system::cart_count(&caller.data().api)
+}
+
+fn cart_meta(mut caller: Ctx, index: u32, key: u32, key_len: u32, dest: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    system::cart_meta(mem, api, index, key, key_len, dest, len)
+}
+
+fn cart_thumbnail(mut caller: Ctx, index: u32, dest: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    system::cart_thumbnail(mem, api, index, dest)
+}
+
+fn launch(caller: Ctx, index: u32) {
+    system::launch(&caller.data().api, index);
+}
+
+fn get_setting(mut caller: Ctx, key: u32, key_len: u32, dest: u32, len: u32) -> u32 {
+    let (mem, api) = split(&mut caller);
+    system::get_setting(mem, api, key, key_len, dest, len)
+}
+
+fn set_setting(mut caller: Ctx, key: u32, key_len: u32, value: u32, len: u32) {
+    let (mem, api) = split(&mut caller);
+    system::set_setting(mem, api, key, key_len, value, len);
+}
@@ -1,13 +1,19 @@
 use std::{env, fs};
-use wasmstation::wasmer_backend::wasmer::{Module, Store};
+use wasmstation::{core::meta::CartMeta, {engine}};
+
+const CART: &[u8] = include_bytes!("{crate_name}.wasm");
 
 fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    fs::write(
+        format!("{out_dir}/cart.module"),
+        {precompile},
+    )
+    .unwrap();
     fs::write(
-        format!("{}/wasm.module", env::var("OUT_DIR").unwrap()),
-        Module::new(&Store::default(), include_bytes!("{cart_name}.wasm"))
-            .unwrap()
-            .serialize()
-            .unwrap(),
+        format!("{out_dir}/cart.meta"),
+        CartMeta::from_wasm(CART).unwrap().to_string(),
     )
     .unwrap();
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+//! Precompiled wasmer modules: the module cache, filled on the first load and
+//! used after that, and artifacts checked before they're loaded.
+#![cfg(feature = "wasmer")]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use wasmstation::{
+    core::{artifact::Artifact, features::WasmFeatures, utils},
+    wasmer_backend::{ModuleCache, OptLevel, WasmerBackend},
+    Backend, Console,
+};
+
+/// Stores 42 at `0x4000` in `start`.
+const CART: &str = r#"(module
+    (import "env" "memory" (memory 1 1))
+    (func (export "start") (i32.store (i32.const 0x4000) (i32.const 42))))"#;
+
+/// The cached modules in `dir`.
+fn modules(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .flat_map(|entry| match entry.path() {
+            path if path.is_dir() => modules(&path),
+            path if path.extension().is_some_and(|ext| ext == "module") => vec![path],
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+fn start(cache: &ModuleCache, cart: &[u8]) -> u32 {
+    let mut backend = cache.load(cart, &Console::new(Box::new(|_| ()))).unwrap();
+    backend.call_start();
+
+    let mut result = [0; 4];
+    backend.read_memory(0x4000, &mut result);
+
+    u32::from_le_bytes(result)
+}
+
+#[test]
+fn carts_are_compiled_once() {
+    let dir = env::temp_dir().join(format!("wasmstation-cache-{}", process::id()));
+    let cache = ModuleCache::new(&dir);
+    let cart = wat::parse_str(CART).unwrap();
+
+    assert_eq!(start(&cache, &cart), 42);
+    let cached = modules(&dir);
+    assert_eq!(cached.len(), 1);
+
+    assert_eq!(start(&cache, &cart), 42);
+    assert_eq!(modules(&dir), cached);
+
+    // a module that can't be loaded is replaced.
+    fs::write(&cached[0], b"not a module").unwrap();
+    assert_eq!(start(&cache, &cart), 42);
+    assert_ne!(fs::read(&cached[0]).unwrap(), b"not a module");
+
+    cache.clear().unwrap();
+    assert!(modules(&dir).is_empty());
+    cache.clear().unwrap();
+}
+
+#[test]
+fn mismatched_artifacts_fall_back_to_the_cart() {
+    let cart = wat::parse_str(CART).unwrap();
+    let console = Console::new(Box::new(|_| ()));
+
+    for include_cart in [false, true] {
+        let bytes =
+            WasmerBackend::precompile(&cart, &WasmFeatures::default(), include_cart).unwrap();
+        assert!(WasmerBackend::precompiled(&bytes, &console).is_ok());
+
+        let mut artifact = Artifact::parse(&bytes).unwrap();
+        artifact.header.engine_version = "0.0.0";
+        let result = WasmerBackend::precompiled(&artifact.to_bytes(), &console);
+
+        match result {
+            Ok(_) => assert!(include_cart),
+            Err(err) => {
+                assert!(!include_cart);
+                assert!(err.to_string().contains("wasmer 0.0.0"), "{err}");
+            }
+        }
+    }
+
+    assert!(WasmerBackend::precompiled(&cart, &console).is_err());
+}
+
+#[test]
+fn artifacts_of_other_carts_are_rejected() {
+    let dir = env::temp_dir().join(format!("wasmstation-other-{}", process::id()));
+    let cache = ModuleCache::new(&dir);
+    let console = Console::new(Box::new(|_| ()));
+    let cart = wat::parse_str(CART).unwrap();
+    let other = wat::parse_str(CART.replace("42", "7")).unwrap();
+
+    let bytes = WasmerBackend::precompile(&other, &WasmFeatures::default(), true).unwrap();
+    let hash = utils::content_hash(&cart);
+    assert!(WasmerBackend::precompiled_for(&bytes, &hash, &console).is_err());
+
+    // a cached module of another cart is replaced, even if it loads.
+    assert_eq!(start(&cache, &cart), 42);
+    let cached = modules(&dir);
+    fs::write(&cached[0], &bytes).unwrap();
+    assert_eq!(start(&cache, &cart), 42);
+    assert_ne!(fs::read(&cached[0]).unwrap(), bytes);
+
+    cache.clear().unwrap();
+}
+
+#[test]
+fn settings_are_cached_apart() {
+    let dir = env::temp_dir().join(format!("wasmstation-settings-{}", process::id()));
+    let cart = wat::parse_str(CART).unwrap();
+    let builder = WasmerBackend::builder()
+        .opt_level(OptLevel::None)
+        .canonicalize_nans(true);
+
+    assert_eq!(start(&ModuleCache::new(&dir), &cart), 42);
+    assert_eq!(
+        start(&ModuleCache::new(&dir).with_builder(builder), &cart),
+        42
+    );
+    assert_eq!(modules(&dir).len(), 2);
+
+    ModuleCache::new(&dir).clear().unwrap();
+}
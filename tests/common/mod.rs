@@ -0,0 +1,62 @@
+//! The backends every suite runs its carts on.
+
+#[cfg(feature = "wasm3")]
+use wasmstation::Wasm3Backend;
+#[cfg(feature = "wasmer")]
+use wasmstation::WasmerBackend;
+#[cfg(feature = "wasmi")]
+use wasmstation::WasmiBackend;
+#[cfg(feature = "wasmtime")]
+use wasmstation::WasmtimeBackend;
+use wasmstation::{Backend, Console};
+
+type Load = fn(&[u8], &Console) -> anyhow::Result<Box<dyn Backend>>;
+
+/// Every enabled backend, each behind its feature.
+const BACKENDS: &[(&str, Load)] = &[
+    #[cfg(feature = "wasmi")]
+    ("wasmi", |cart, console| {
+        Ok(Box::new(WasmiBackend::from_bytes(cart, console)?))
+    }),
+    #[cfg(feature = "wasmer")]
+    ("wasmer", |cart, console| {
+        Ok(Box::new(WasmerBackend::from_bytes(cart, console)?))
+    }),
+    #[cfg(feature = "wasmtime")]
+    ("wasmtime", |cart, console| {
+        Ok(Box::new(WasmtimeBackend::from_bytes(cart, console)?))
+    }),
+    #[cfg(feature = "wasm3")]
+    ("wasm3", |cart, console| {
+        Ok(Box::new(Wasm3Backend::from_bytes(cart, console)?))
+    }),
+];
+
+/// Load `cart` on every enabled backend, with their names.
+///
+/// Fails the test if no backend is enabled, as it would check nothing.
+pub fn try_backends(
+    cart: &[u8],
+    console: &Console,
+) -> Vec<(&'static str, anyhow::Result<Box<dyn Backend>>)> {
+    let backends: Vec<_> = BACKENDS
+        .iter()
+        .map(|(name, load)| (*name, load(cart, console)))
+        .collect();
+    assert!(
+        !backends.is_empty(),
+        "no backend to test, enable some with e.g. `--features wasmi,wasmtime`"
+    );
+
+    backends
+}
+
+/// Like [`try_backends`], for carts every backend has to load.
+// suites checking which carts fail to load don't use it.
+#[allow(dead_code)]
+pub fn backends(cart: &[u8], console: &Console) -> Vec<(&'static str, Box<dyn Backend>)> {
+    try_backends(cart, console)
+        .into_iter()
+        .map(|(name, backend)| (name, backend.unwrap_or_else(|err| panic!("{name}: {err}"))))
+        .collect()
+}
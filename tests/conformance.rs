@@ -0,0 +1,427 @@
+//! Backend conformance: small carts exercising every import, the registers
+//! and the callbacks, which have to leave every enabled backend in the same
+//! state, and where it can be computed, the same state as [`host`] does on a
+//! plain memory.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::backends;
+use wasmstation::{
+    core::{
+        host, utils,
+        wasm4::{self, HostCallCounts},
+        Disk,
+    },
+    Api, Backend, Console,
+};
+
+/// Every WASM-4 import, under the names carts use.
+const IMPORTS: &str = r#"
+    (import "env" "memory" (memory 1 1))
+    (import "env" "trace" (func $trace (param i32)))
+    (import "env" "tracef" (func $tracef (param i32 i32)))
+    (import "env" "traceUtf8" (func $traceUtf8 (param i32 i32)))
+    (import "env" "traceUtf16" (func $traceUtf16 (param i32 i32)))
+    (import "env" "blit" (func $blit (param i32 i32 i32 i32 i32 i32)))
+    (import "env" "blitSub" (func $blitSub (param i32 i32 i32 i32 i32 i32 i32 i32 i32)))
+    (import "env" "line" (func $line (param i32 i32 i32 i32)))
+    (import "env" "hline" (func $hline (param i32 i32 i32)))
+    (import "env" "vline" (func $vline (param i32 i32 i32)))
+    (import "env" "oval" (func $oval (param i32 i32 i32 i32)))
+    (import "env" "rect" (func $rect (param i32 i32 i32 i32)))
+    (import "env" "text" (func $text (param i32 i32 i32)))
+    (import "env" "textUtf8" (func $textUtf8 (param i32 i32 i32 i32)))
+    (import "env" "textUtf16" (func $textUtf16 (param i32 i32 i32 i32)))
+    (import "env" "diskr" (func $diskr (param i32 i32) (result i32)))
+    (import "env" "diskw" (func $diskw (param i32 i32) (result i32)))
+    (import "env" "tone" (func $tone (param i32 i32 i32 i32)))"#;
+
+/// Build a cart importing everything, with `data` segments and `funcs`.
+fn cart(data: &[(usize, &[u8])], funcs: &str) -> Vec<u8> {
+    let data: String = data
+        .iter()
+        .map(|(offset, bytes)| {
+            let bytes: String = bytes.iter().map(|byte| format!("\\{byte:02x}")).collect();
+            format!("(data (i32.const {offset}) \"{bytes}\")\n")
+        })
+        .collect();
+
+    wat::parse_str(format!("(module {IMPORTS}\n{data}\n{funcs})")).unwrap()
+}
+
+/// What the carts printed, shared by every backend and drained after each.
+type Log = Arc<Mutex<Vec<String>>>;
+
+fn console() -> (Console, Log) {
+    let log = Log::default();
+    let print = log.clone();
+
+    let console = Console::muted(Box::new(move |msg| {
+        print.lock().unwrap().push(msg.to_string())
+    }));
+
+    (console, log)
+}
+
+/// Everything a cart can change that the host can see.
+#[derive(Debug, PartialEq)]
+struct State {
+    memory: Vec<u8>,
+    host_calls: HostCallCounts,
+    trace: Vec<String>,
+    disk: Option<Disk>,
+}
+
+impl State {
+    fn of(backend: &mut dyn Backend, log: &Log) -> Self {
+        let mut memory = vec![0; wasm4::MEMORY_SIZE];
+        backend.read_memory(0, &mut memory);
+
+        Self {
+            memory,
+            host_calls: backend.take_host_calls(),
+            trace: log.lock().unwrap().drain(..).collect(),
+            disk: backend.write_save_cache(),
+        }
+    }
+
+    /// Panic with `name` and the first difference, rather than two dumps of
+    /// the whole memory.
+    fn assert_eq(&self, expected: &State, name: &str) {
+        if let Some(offset) = (0..self.memory.len()).find(|&i| self.memory[i] != expected.memory[i])
+        {
+            panic!(
+                "{name}: memory differs at {offset:#x}, {:#04x} instead of {:#04x}",
+                self.memory[offset], expected.memory[offset]
+            );
+        }
+        assert_eq!(self.host_calls, expected.host_calls, "{name}: host calls");
+        assert_eq!(self.trace, expected.trace, "{name}: trace");
+        assert_eq!(self.disk, expected.disk, "{name}: disk");
+    }
+}
+
+/// Run `cart` on every backend with `run`, and check that they all end up in
+/// the same state, which is returned.
+fn conform(cart: &[u8], mut run: impl FnMut(&mut dyn Backend)) -> State {
+    let (console, log) = console();
+    let mut first: Option<(&str, State)> = None;
+
+    for (name, mut backend) in backends(cart, &console) {
+        run(&mut *backend);
+        let state = State::of(&mut *backend, &log);
+
+        match &first {
+            Some((first_name, expected)) => {
+                state.assert_eq(expected, &format!("{name} (compared to {first_name})"))
+            }
+            None => first = Some((name, state)),
+        }
+    }
+
+    // `backends` fails the test when no backend is enabled.
+    first.unwrap().1
+}
+
+/// The same calls as a cart makes, through [`host`] on a plain memory.
+struct Reference {
+    memory: Vec<u8>,
+    api: Api,
+    log: Log,
+}
+
+impl Reference {
+    /// The memory of a freshly instantiated cart with `data`.
+    fn new(data: &[(usize, &[u8])]) -> Self {
+        let (console, log) = console();
+        let mut memory = vec![0; wasm4::MEMORY_SIZE];
+
+        memory[wasm4::PALETTE_ADDR..][..16].copy_from_slice(&utils::default_palette());
+        memory[wasm4::DRAW_COLORS_ADDR..][..2].copy_from_slice(&utils::default_draw_colors());
+        memory[wasm4::FRAMEBUFFER_ADDR..][..wasm4::FRAMEBUFFER_SIZE]
+            .copy_from_slice(&utils::default_framebuffer());
+        for (offset, bytes) in data {
+            memory[*offset..][..bytes.len()].copy_from_slice(bytes);
+        }
+
+        Self {
+            memory,
+            api: console.create_api(),
+            log,
+        }
+    }
+
+    fn state(self) -> State {
+        State {
+            memory: self.memory,
+            host_calls: self.api.take_host_calls(),
+            trace: self.log.lock().unwrap().drain(..).collect(),
+            disk: self.api.write_save(),
+        }
+    }
+
+    fn set_draw_colors(&mut self, draw_colors: u16) {
+        self.memory[wasm4::DRAW_COLORS_ADDR..][..2].copy_from_slice(&draw_colors.to_le_bytes());
+    }
+}
+
+/// Reads the framebuffer's pixel at `x`, `y`.
+fn pixel(memory: &[u8], x: usize, y: usize) -> u8 {
+    let index = y * wasm4::SCREEN_SIZE as usize + x;
+    let byte = memory[wasm4::FRAMEBUFFER_ADDR + index / 4];
+
+    (byte >> ((index % 4) * 2)) & 0b11
+}
+
+fn read<const L: usize>(memory: &[u8], offset: usize) -> [u8; L] {
+    memory[offset..][..L].try_into().unwrap()
+}
+
+#[test]
+fn fresh_carts_start_with_the_default_registers() {
+    let data: &[(usize, &[u8])] = &[(0x2000, b"cart data")];
+
+    let state = conform(&cart(data, ""), |_| ());
+    state.assert_eq(&Reference::new(data).state(), "reference");
+    assert_eq!(
+        read::<2>(&state.memory, wasm4::DRAW_COLORS_ADDR),
+        [0x03, 0x12]
+    );
+}
+
+#[test]
+fn data_segments_override_the_default_registers() {
+    // a custom palette, draw colors and the start of the framebuffer.
+    let palette = b"\x00\x11\x22\x00\x33\x44\x55\x00\x66\x77\x88\x00\x99\xaa\xbb\x00";
+    let data: &[(usize, &[u8])] = &[
+        (wasm4::PALETTE_ADDR, palette),
+        (wasm4::DRAW_COLORS_ADDR, b"\x21\x43"),
+        (wasm4::FRAMEBUFFER_ADDR, b"\xe4\xe4\xe4\xe4"),
+    ];
+    let cart = cart(data, "");
+
+    let state = conform(&cart, |backend| backend.call_start());
+    state.assert_eq(&Reference::new(data).state(), "reference");
+    assert_eq!(read::<16>(&state.memory, wasm4::PALETTE_ADDR), *palette);
+
+    let state = conform(&cart, |backend| {
+        backend.reset().unwrap();
+        backend.call_start();
+    });
+    state.assert_eq(&Reference::new(data).state(), "reference after reset");
+}
+
+#[test]
+fn drawing_imports_draw_the_same_pixels() {
+    let data: &[(usize, &[u8])] = &[
+        (0x2000, b"\xff\x81\xbd\xa5\xa5\xbd\x81\xff"),
+        (0x2010, b"\x1b\xe4\x1b\xe4\x55\xaa\x55\xaa"),
+        (0x2020, b"Hi!\0"),
+        (0x2030, "\u{e9}t\u{e9}".as_bytes()),
+        (0x2040, b"W\x004\x00"),
+    ];
+    let funcs = r#"(func (export "start")
+        (call $blit (i32.const 0x2000) (i32.const 1) (i32.const 1) (i32.const 8) (i32.const 8) (i32.const 0))
+        (call $blit (i32.const 0x2000) (i32.const 156) (i32.const -3) (i32.const 8) (i32.const 8) (i32.const 14))
+        (i32.store16 (i32.const 0x14) (i32.const 0x4321))
+        (call $blitSub (i32.const 0x2010) (i32.const 20) (i32.const 2) (i32.const 4) (i32.const 2)
+            (i32.const 2) (i32.const 1) (i32.const 16) (i32.const 1))
+        (i32.store16 (i32.const 0x14) (i32.const 0x0042))
+        (call $line (i32.const 0) (i32.const 159) (i32.const 159) (i32.const 0))
+        (i32.store16 (i32.const 0x14) (i32.const 0x0034))
+        (call $hline (i32.const -10) (i32.const 40) (i32.const 200))
+        (call $vline (i32.const 40) (i32.const -10) (i32.const 200))
+        (call $oval (i32.const 50) (i32.const 50) (i32.const 41) (i32.const 25))
+        (call $rect (i32.const 100) (i32.const 60) (i32.const 70) (i32.const 30))
+        (i32.store16 (i32.const 0x14) (i32.const 0x0021))
+        (call $text (i32.const 0x2020) (i32.const 10) (i32.const 120))
+        (call $textUtf8 (i32.const 0x2030) (i32.const 5) (i32.const 10) (i32.const 130))
+        (call $textUtf16 (i32.const 0x2040) (i32.const 4) (i32.const 10) (i32.const 140))
+        (call $tone (i32.const 262) (i32.const 60) (i32.const 50) (i32.const 0)))"#;
+
+    let state = conform(&cart(data, funcs), |backend| backend.call_start());
+
+    let mut reference = Reference::new(data);
+    let (mem, api) = (&mut reference.memory, &reference.api);
+    host::blit(mem, api, 0x2000, 1, 1, 8, 8, 0);
+    host::blit(mem, api, 0x2000, 156, -3, 8, 8, 14);
+    reference.set_draw_colors(0x4321);
+    let (mem, api) = (&mut reference.memory, &reference.api);
+    host::blit_sub(mem, api, 0x2010, 20, 2, 4, 2, 2, 1, 16, 1);
+    reference.set_draw_colors(0x0042);
+    let (mem, api) = (&mut reference.memory, &reference.api);
+    host::line(mem, api, 0, 159, 159, 0);
+    reference.set_draw_colors(0x0034);
+    let (mem, api) = (&mut reference.memory, &reference.api);
+    host::hline(mem, api, -10, 40, 200);
+    host::vline(mem, api, 40, -10, 200);
+    host::oval(mem, api, 50, 50, 41, 25);
+    host::rect(mem, api, 100, 60, 70, 30);
+    reference.set_draw_colors(0x0021);
+    let (mem, api) = (&mut reference.memory, &reference.api);
+    host::text(mem, api, 0x2020, 10, 120);
+    host::text_utf8(mem, api, 0x2030, 5, 10, 130);
+    host::text_utf16(mem, api, 0x2040, 4, 10, 140);
+    host::tone(api, 262, 60, 50, 0);
+
+    state.assert_eq(&reference.state(), "reference");
+    assert_eq!(state.host_calls.iter().sum::<u32>(), 12);
+    // the corners of the rect, with its outline and fill colors.
+    assert_eq!(pixel(&state.memory, 100, 60), 2);
+    assert_eq!(pixel(&state.memory, 101, 61), 3);
+}
+
+#[test]
+fn trace_imports_print_the_same_text() {
+    let mut args = Vec::new();
+    args.extend_from_slice(&(-42i32).to_le_bytes());
+    args.extend_from_slice(&255u32.to_le_bytes());
+    args.extend_from_slice(&u32::from(b'A').to_le_bytes());
+    args.extend_from_slice(&0x2000u32.to_le_bytes());
+    args.extend_from_slice(&1.5f64.to_le_bytes());
+
+    let data: &[(usize, &[u8])] = &[
+        (0x2000, b"plain\0"),
+        (0x2010, b"%d %x %c %s %f\0"),
+        (0x2030, &args),
+        (0x2050, "\u{e9}t\u{e9}".as_bytes()),
+        (0x2060, b"W\x004\x00"),
+    ];
+    let funcs = r#"(func (export "start")
+        (call $trace (i32.const 0x2000))
+        (call $tracef (i32.const 0x2010) (i32.const 0x2030))
+        (call $traceUtf8 (i32.const 0x2050) (i32.const 5))
+        (call $traceUtf16 (i32.const 0x2060) (i32.const 4)))"#;
+
+    let state = conform(&cart(data, funcs), |backend| backend.call_start());
+
+    let reference = Reference::new(data);
+    let (mem, api) = (&reference.memory, &reference.api);
+    host::trace(mem, api, 0x2000);
+    host::tracef(mem, api, 0x2010, 0x2030);
+    host::trace_utf8(mem, api, 0x2050, 5);
+    host::trace_utf16(mem, api, 0x2060, 4);
+
+    state.assert_eq(&reference.state(), "reference");
+    assert_eq!(state.trace[0], "plain");
+    assert_eq!(state.trace[2..], ["\u{e9}t\u{e9}", "W4"]);
+}
+
+#[test]
+fn registers_are_shared_with_the_host() {
+    // copies the input registers and system flags to 0x4000, then sets the
+    // system flags and palette.
+    let funcs = r#"(func (export "update")
+        (memory.copy (i32.const 0x4000) (i32.const 0x16) (i32.const 10))
+        (i32.store8 (i32.const 0x1f) (i32.const 3))
+        (i32.store (i32.const 0x04) (i32.const 0x123456)))"#;
+
+    let mut flags = Vec::new();
+    let state = conform(&cart(&[], funcs), |backend| {
+        backend.set_gamepad(0x80402010);
+        backend.set_mouse(-5, 170, 7);
+        backend.call_update();
+        flags.push(backend.read_system_flags());
+
+        let (mut framebuffer, mut palette) = ([0; wasm4::FRAMEBUFFER_SIZE], [0; 16]);
+        backend.read_screen(&mut framebuffer, &mut palette);
+        assert_eq!(palette[..4], [0x56, 0x34, 0x12, 0]);
+    });
+
+    assert!(flags.iter().all(|&flags| flags == 3), "{flags:?}");
+    assert_eq!(
+        read::<10>(&state.memory, 0x4000),
+        [0x10, 0x20, 0x40, 0x80, 0xfb, 0xff, 0xaa, 0x00, 7, 0]
+    );
+}
+
+/// Draws a pixel at `x` = the number of updates so far, and preserves the
+/// framebuffer if start says so.
+fn pixel_per_update(start: &str) -> Vec<u8> {
+    cart(
+        &[],
+        &format!(
+            r#"(func (export "start") {start})
+            (func (export "update")
+                (i32.store16 (i32.const 0x14) (i32.const 0x0044))
+                (call $rect (i32.load (i32.const 0x4000)) (i32.const 0) (i32.const 1) (i32.const 1))
+                (i32.store (i32.const 0x4000) (i32.add (i32.load (i32.const 0x4000)) (i32.const 1))))"#
+        ),
+    )
+}
+
+fn updates(backend: &mut dyn Backend) {
+    backend.call_start();
+    for _ in 0..3 {
+        backend.call_update();
+    }
+}
+
+#[test]
+fn the_framebuffer_is_cleared_before_each_update() {
+    let state = conform(&pixel_per_update(""), updates);
+    let row: Vec<u8> = (0..4).map(|x| pixel(&state.memory, x, 0)).collect();
+    assert_eq!(row, [0, 0, 3, 0]);
+}
+
+#[test]
+fn system_preserve_framebuffer_keeps_it() {
+    let preserve = "(i32.store8 (i32.const 0x1f) (i32.const 1))";
+
+    let state = conform(&pixel_per_update(preserve), updates);
+    let row: Vec<u8> = (0..4).map(|x| pixel(&state.memory, x, 0)).collect();
+    assert_eq!(row, [3, 3, 3, 0]);
+}
+
+#[test]
+fn start_runs_before_update_and_after_data() {
+    // appends S or U to a log at 0x4000, its length is at 0x3ffc.
+    let funcs = r#"(func $log (param i32)
+            (i32.store8 (i32.add (i32.const 0x4000) (i32.load (i32.const 0x3ffc))) (local.get 0))
+            (i32.store (i32.const 0x3ffc) (i32.add (i32.load (i32.const 0x3ffc)) (i32.const 1))))
+        (func (export "start")
+            (call $log (i32.load8_u (i32.const 0x2000)))
+            (call $log (i32.const 0x53))
+            (call $rect (i32.const 0) (i32.const 0) (i32.const 160) (i32.const 160)))
+        (func (export "update") (call $log (i32.const 0x55)))"#;
+    let cart = cart(&[(0x2000, b"d")], funcs);
+
+    let state = conform(&cart, |backend| backend.call_start());
+    assert_eq!(read::<2>(&state.memory, 0x4000), *b"dS");
+    // start draws to the screen, update clears it.
+    assert_eq!(pixel(&state.memory, 80, 80), 2);
+
+    let state = conform(&cart, |backend| {
+        backend.call_start();
+        backend.call_update();
+        backend.call_update();
+    });
+    assert_eq!(read::<4>(&state.memory, 0x4000), *b"dSUU");
+    assert_eq!(pixel(&state.memory, 80, 80), 0);
+}
+
+#[test]
+fn reset_reinstantiates_but_keeps_the_disk() {
+    let funcs = r#"(func (export "start")
+        (i32.store (i32.const 0x4000) (call $diskr (i32.const 0x4004) (i32.const 8))))
+        (func (export "update")
+            (i32.store (i32.const 0x4000) (call $diskw (i32.const 0x2000) (i32.const 5)))
+            (i32.store (i32.const 0x2000) (i32.const 0)))"#;
+    let cart = cart(&[(0x2000, b"saved")], funcs);
+
+    let state = conform(&cart, |backend| {
+        backend.call_update();
+        assert_eq!(backend.write_save_cache(), Some(Disk::new(b"saved")));
+
+        backend.reset().unwrap();
+        backend.call_start();
+    });
+
+    // the data segment is back, and the disk was read in start.
+    assert_eq!(read::<5>(&state.memory, 0x2000), *b"saved");
+    assert_eq!(read::<4>(&state.memory, 0x4000), 5u32.to_le_bytes());
+    assert_eq!(read::<5>(&state.memory, 0x4004), *b"saved");
+    assert_eq!(state.disk, None);
+}
@@ -0,0 +1,121 @@
+//! `diskr` and `diskw`, driven by small carts on every enabled backend.
+
+mod common;
+
+use common::backends;
+use wasmstation::{core::Disk, Backend, Console};
+
+/// Build a cart calling `start` and `update` with the given bodies.
+///
+/// `diskr` and `diskw` store their results at `0x4000`, "hello world" is at
+/// `0x2000` and `0x3000` is filled with `0xff`.
+fn cart(start: &str, update: &str) -> Vec<u8> {
+    wat::parse_str(format!(
+        r#"(module
+            (import "env" "memory" (memory 1 1))
+            (import "env" "diskr" (func $diskr (param i32 i32) (result i32)))
+            (import "env" "diskw" (func $diskw (param i32 i32) (result i32)))
+            (data (i32.const 0x2000) "hello world")
+            (data (i32.const 0x3000) "\ff\ff\ff\ff\ff\ff\ff\ff")
+            (func (export "start") {start})
+            (func (export "update") {update}))"#
+    ))
+    .unwrap()
+}
+
+fn read<const L: usize>(backend: &dyn Backend, offset: usize) -> [u8; L] {
+    let mut buf = [0; L];
+    backend.read_memory(offset, &mut buf);
+
+    buf
+}
+
+fn result(backend: &dyn Backend) -> u32 {
+    u32::from_le_bytes(read(backend, 0x4000))
+}
+
+const READ_8: &str =
+    "(i32.store (i32.const 0x4000) (call $diskr (i32.const 0x3000) (i32.const 8)))";
+
+#[test]
+fn diskr_reads_at_most_the_disk_length() {
+    let console = Console::new(Box::new(|_| ()));
+    for (name, mut backend) in backends(&cart(READ_8, ""), &console) {
+        backend.set_save_cache(Disk::new(b"abc"));
+        backend.call_start();
+
+        assert_eq!(result(&*backend), 3, "{name}");
+        assert_eq!(
+            read::<8>(&*backend, 0x3000),
+            *b"abc\xff\xff\xff\xff\xff",
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn diskr_of_an_empty_disk_reads_nothing() {
+    let console = Console::new(Box::new(|_| ()));
+    for (name, mut backend) in backends(&cart(READ_8, ""), &console) {
+        backend.call_start();
+
+        assert_eq!(result(&*backend), 0, "{name}");
+        assert_eq!(read::<8>(&*backend, 0x3000), [0xff; 8], "{name}");
+    }
+}
+
+#[test]
+fn diskw_replaces_the_disk() {
+    let write_2 = "(i32.store (i32.const 0x4000) (call $diskw (i32.const 0x2000) (i32.const 2)))";
+
+    let console = Console::new(Box::new(|_| ()));
+    for (name, mut backend) in backends(&cart(READ_8, write_2), &console) {
+        backend.set_save_cache(Disk::new(b"hello world"));
+        assert_eq!(backend.write_save_cache(), None, "{name}");
+
+        backend.call_update();
+        assert_eq!(result(&*backend), 2, "{name}");
+        assert_eq!(backend.write_save_cache(), Some(Disk::new(b"he")), "{name}");
+        assert_eq!(backend.write_save_cache(), None, "{name}");
+
+        // the shorter disk is what the cart reads back.
+        backend.call_start();
+        assert_eq!(result(&*backend), 2, "{name}");
+        assert_eq!(read::<3>(&*backend, 0x3000), *b"he\xff", "{name}");
+    }
+}
+
+#[test]
+fn diskw_writes_at_most_the_disk_capacity() {
+    let write_all =
+        "(i32.store (i32.const 0x4000) (call $diskw (i32.const 0x2000) (i32.const 5000)))";
+
+    let console = Console::new(Box::new(|_| ()));
+    for (name, mut backend) in backends(&cart("", write_all), &console) {
+        backend.call_update();
+
+        assert_eq!(result(&*backend), Disk::CAPACITY as u32, "{name}");
+        let disk = backend.write_save_cache().unwrap();
+        assert_eq!(disk.len(), Disk::CAPACITY, "{name}");
+        assert_eq!(&disk.as_bytes()[..11], b"hello world", "{name}");
+    }
+}
+
+#[test]
+fn out_of_bounds_disk_access_does_nothing() {
+    let update = "(i32.store (i32.const 0x4000) (call $diskw (i32.const 0xfffe) (i32.const 4)))";
+    let start = "(i32.store (i32.const 0x4000) (call $diskr (i32.const 0xfffe) (i32.const 4)))";
+
+    let console = Console::new(Box::new(|_| ()));
+    for (name, mut backend) in backends(&cart(start, update), &console) {
+        backend.set_save_cache(Disk::new(b"abcd"));
+
+        backend.call_update();
+        assert_eq!(result(&*backend), 0, "{name}");
+        assert_eq!(backend.write_save_cache(), None, "{name}");
+
+        backend.call_start();
+        assert_eq!(result(&*backend), 0, "{name}");
+        assert_eq!(read::<2>(&*backend, 0xfffe), [0, 0], "{name}");
+    }
+}
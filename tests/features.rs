@@ -0,0 +1,93 @@
+//! The WebAssembly proposals carts may use, the same on every enabled backend.
+
+mod common;
+
+use common::try_backends;
+use wasmstation::{core::features::WasmFeatures, Backend, Console};
+
+/// Uses sign extension, bulk memory and multi-value, like carts from recent
+/// compilers do. Stores `[0xff; 4]`, then `-1` and `2` at `0x4000`.
+const CART: &str = r#"(module
+    (import "env" "memory" (memory 1 1))
+    (func $pair (result i32 i32) (i32.const 0xff) (i32.const 2))
+    (func (export "start") (local $a i32) (local $b i32)
+        (memory.fill (i32.const 0x4000) (i32.const 0xff) (i32.const 4))
+        (call $pair)
+        (local.set $b)
+        (local.set $a)
+        (i32.store (i32.const 0x4004) (i32.extend8_s (local.get $a)))
+        (i32.store (i32.const 0x4008) (local.get $b))))"#;
+
+/// Uses SIMD, which WASM-4 carts can't by default.
+const SIMD_CART: &str = r#"(module
+    (import "env" "memory" (memory 1 1))
+    (func (export "start")
+        (v128.store (i32.const 0x4000) (v128.const i32x4 1 2 3 4))))"#;
+
+fn load(
+    cart: &str,
+    features: WasmFeatures,
+) -> Vec<(&'static str, anyhow::Result<Box<dyn Backend>>)> {
+    let cart = wat::parse_str(cart).unwrap();
+    let console = Console::new(Box::new(|_| ())).with_features(features);
+
+    try_backends(&cart, &console)
+}
+
+#[test]
+fn default_features_load_everywhere() {
+    for (name, backend) in load(CART, WasmFeatures::default()) {
+        let mut backend = backend.unwrap_or_else(|err| panic!("{name}: {err}"));
+        backend.call_start();
+
+        let mut result = [0; 12];
+        backend.read_memory(0x4000, &mut result);
+        assert_eq!(
+            result,
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0],
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn simd_is_off_by_default() {
+    for (name, backend) in load(SIMD_CART, WasmFeatures::default()) {
+        assert!(backend.is_err(), "{name}");
+    }
+}
+
+#[test]
+fn unsupported_features_fail_to_load() {
+    let simd = WasmFeatures {
+        simd: true,
+        ..Default::default()
+    };
+
+    for (name, backend) in load(SIMD_CART, simd) {
+        match name {
+            "wasmi" | "wasm3" => {
+                let err = backend.err().unwrap().to_string();
+                assert!(
+                    err.contains("doesn't support the WebAssembly simd"),
+                    "{name}: {err}"
+                );
+            }
+            _ => assert!(backend.is_ok(), "{name}"),
+        }
+    }
+}
+
+#[test]
+fn reference_types_without_bulk_memory_fail_everywhere() {
+    let features = WasmFeatures {
+        bulk_memory: false,
+        reference_types: true,
+        ..Default::default()
+    };
+
+    for (name, backend) in load(CART, features) {
+        let err = backend.err().unwrap().to_string();
+        assert!(err.contains("needs bulk-memory"), "{name}: {err}");
+    }
+}
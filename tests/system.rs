@@ -0,0 +1,145 @@
+//! The system imports, driven by a small launcher cart on every enabled backend.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+mod common;
+
+use common::{backends, try_backends};
+use wasmstation::{
+    core::{
+        system::{System, Thumbnail, THUMBNAIL_SIZE},
+        wasm4::FRAMEBUFFER_SIZE,
+    },
+    Backend, Console,
+};
+
+/// A launcher that counts the carts, reads the name and thumbnail of the
+/// second one, sets and reads back the "volume" setting and launches it.
+///
+/// The results are stored at `0x4000`, the name at `0x3000`, the setting at
+/// `0x3010` and the thumbnail at `0x5000`.
+const LAUNCHER: &str = r#"(module
+    (import "env" "memory" (memory 1 1))
+    (import "env" "cartCount" (func $cartCount (result i32)))
+    (import "env" "cartMeta" (func $cartMeta (param i32 i32 i32 i32 i32) (result i32)))
+    (import "env" "cartThumbnail" (func $cartThumbnail (param i32 i32) (result i32)))
+    (import "env" "launch" (func $launch (param i32)))
+    (import "env" "getSetting" (func $getSetting (param i32 i32 i32 i32) (result i32)))
+    (import "env" "setSetting" (func $setSetting (param i32 i32 i32 i32)))
+    (data (i32.const 0x2000) "name")
+    (data (i32.const 0x2010) "volume")
+    (data (i32.const 0x2020) "\07")
+    (func (export "start")
+        (i32.store (i32.const 0x4000) (call $cartCount))
+        (i32.store (i32.const 0x4004)
+            (call $cartMeta (i32.const 1) (i32.const 0x2000) (i32.const 4) (i32.const 0x3000) (i32.const 4)))
+        (i32.store (i32.const 0x4008) (call $cartThumbnail (i32.const 1) (i32.const 0x5000)))
+        (call $setSetting (i32.const 0x2010) (i32.const 6) (i32.const 0x2020) (i32.const 1))
+        (i32.store (i32.const 0x400c)
+            (call $getSetting (i32.const 0x2010) (i32.const 6) (i32.const 0x3010) (i32.const 8)))
+        (call $launch (i32.const 1))))"#;
+
+#[derive(Default)]
+struct Fake {
+    launched: Mutex<Option<usize>>,
+    settings: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl System for Fake {
+    fn cart_count(&self) -> usize {
+        2
+    }
+
+    fn cart_meta(&self, index: usize, key: &str) -> Option<String> {
+        match (index, key) {
+            (0, "name") => Some("first".to_string()),
+            (1, "name") => Some("second cart".to_string()),
+            _ => None,
+        }
+    }
+
+    fn cart_thumbnail(&self, index: usize) -> Option<Thumbnail> {
+        (index == 1).then_some(Thumbnail {
+            framebuffer: [0xaa; FRAMEBUFFER_SIZE],
+            palette: [0x11; 16],
+        })
+    }
+
+    fn launch(&self, index: usize) {
+        *self.launched.lock().unwrap() = Some(index);
+    }
+
+    fn setting(&self, key: &str) -> Option<Vec<u8>> {
+        self.settings.lock().unwrap().get(key).cloned()
+    }
+
+    fn set_setting(&self, key: &str, value: &[u8]) {
+        self.settings
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_vec());
+    }
+}
+
+fn read<const L: usize>(backend: &dyn Backend, offset: usize) -> [u8; L] {
+    let mut buf = [0; L];
+    backend.read_memory(offset, &mut buf);
+
+    buf
+}
+
+fn results(backend: &dyn Backend) -> [u32; 4] {
+    let bytes: [u8; 16] = read(backend, 0x4000);
+
+    [0, 1, 2, 3].map(|n| u32::from_le_bytes(bytes[n * 4..n * 4 + 4].try_into().unwrap()))
+}
+
+#[test]
+fn system_carts_can_use_the_system() {
+    let cart = wat::parse_str(LAUNCHER).unwrap();
+
+    let system = Arc::new(Fake::default());
+    let console = Console::new(Box::new(|_| ())).with_system(system.clone());
+
+    for (name, mut backend) in backends(&cart, &console) {
+        *system.launched.lock().unwrap() = None;
+        system.settings.lock().unwrap().clear();
+
+        backend.call_start();
+
+        assert_eq!(results(&*backend), [2, 11, 1, 1], "{name}");
+        assert_eq!(read::<5>(&*backend, 0x3000), *b"seco\0", "{name}");
+        assert_eq!(read::<2>(&*backend, 0x3010), [7, 0], "{name}");
+
+        let mut thumbnail = [0; THUMBNAIL_SIZE];
+        backend.read_memory(0x5000, &mut thumbnail);
+        assert_eq!(
+            thumbnail[..FRAMEBUFFER_SIZE],
+            [0xaa; FRAMEBUFFER_SIZE],
+            "{name}"
+        );
+        assert_eq!(thumbnail[FRAMEBUFFER_SIZE..], [0x11; 16], "{name}");
+
+        assert_eq!(*system.launched.lock().unwrap(), Some(1), "{name}");
+        assert_eq!(system.setting("volume"), Some(vec![7]), "{name}");
+    }
+}
+
+#[test]
+fn regular_carts_only_see_the_standard_imports() {
+    let cart = wat::parse_str(LAUNCHER).unwrap();
+
+    for (name, backend) in try_backends(&cart, &Console::new(Box::new(|_| ()))) {
+        match backend {
+            // wasm3 resolves imports lazily, so the cart traps once it calls one.
+            Ok(mut backend) => {
+                backend.call_start();
+                assert_eq!(results(&*backend), [0; 4], "{name}");
+            }
+            Err(err) => assert!(err.to_string().contains("cartCount"), "{name}: {err}"),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Build the web runtime into web/pkg, where the CLI picks it up for `wasmstation bundle`.
+set -e
+cd "$(dirname "$0")"
+
+cargo build --release --target wasm32-unknown-unknown
+wasm-bindgen --target no-modules --no-typescript --out-dir pkg \
+    ../target/wasm32-unknown-unknown/release/wasmstation_web.wasm
@@ -0,0 +1,115 @@
+//! The web runtime inlined into the pages made by `wasmstation bundle`.
+#![cfg(target_arch = "wasm32")]
+
+use wasm_bindgen::prelude::*;
+use wasmstation::{
+    core::{utils, wasm4, Disk},
+    gpu_renderer, Backend, Console, WasmiBackend,
+};
+use web_sys::Storage;
+
+/// Play `cart` on the canvas with the id `canvas_id`, keeping its saves in
+/// `localStorage` under `save_key`.
+#[wasm_bindgen]
+pub fn play(canvas_id: &str, cart: &[u8], save_key: &str) -> Result<(), JsError> {
+    run(canvas_id, cart, save_key).map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// [`play`], for Rust projects like the ones made by `wasmstation create --web`.
+pub fn run(canvas_id: &str, cart: &[u8], save_key: &str) -> anyhow::Result<()> {
+    console_error_panic_hook::set_once();
+    // fails if a logger is already set, which is fine.
+    let _ = console_log::init_with_level(log::Level::Info);
+
+    let backend = WasmiBackend::from_bytes(cart, &Console::default())?;
+
+    gpu_renderer::launch_web(LocalStorage::new(backend, save_key), canvas_id, 3)
+}
+
+/// Keeps the saves of a backend in `localStorage`, as hex.
+struct LocalStorage<B> {
+    backend: B,
+    storage: Option<Storage>,
+    key: String,
+}
+
+impl<B: Backend> LocalStorage<B> {
+    fn new(mut backend: B, key: &str) -> Self {
+        let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+        if storage.is_none() {
+            log::error!("localStorage is not available, saves will be lost");
+        }
+
+        let key = format!("wasmstation:{key}");
+        let save = storage
+            .as_ref()
+            .and_then(|storage| storage.get_item(&key).ok().flatten())
+            .and_then(|hex| utils::from_hex(&hex));
+        if let Some(save) = save {
+            backend.set_save_cache(Disk::new(&save));
+        }
+
+        Self {
+            backend,
+            storage,
+            key,
+        }
+    }
+}
+
+impl<B: Backend> Backend for LocalStorage<B> {
+    fn call_update(&mut self) {
+        self.backend.call_update();
+
+        if let (Some(disk), Some(storage)) = (self.backend.write_save_cache(), &self.storage) {
+            if storage
+                .set_item(&self.key, &utils::to_hex(disk.as_bytes()))
+                .is_err()
+            {
+                log::error!("failed to write {} to localStorage", self.key);
+            }
+        }
+    }
+
+    fn call_start(&mut self) {
+        self.backend.call_start();
+    }
+
+    fn read_screen(&self, framebuffer: &mut [u8; wasm4::FRAMEBUFFER_SIZE], palette: &mut [u8; 16]) {
+        self.backend.read_screen(framebuffer, palette);
+    }
+
+    fn read_system_flags(&self) -> u8 {
+        self.backend.read_system_flags()
+    }
+
+    fn set_gamepad(&mut self, gamepad: u32) {
+        self.backend.set_gamepad(gamepad);
+    }
+
+    fn set_mouse(&mut self, x: i16, y: i16, buttons: u8) {
+        self.backend.set_mouse(x, y, buttons);
+    }
+
+    /// Saves are written to `localStorage` after every update, so there is
+    /// never anything left to write.
+    fn write_save_cache(&mut self) -> Option<Disk> {
+        None
+    }
+
+    fn set_save_cache(&mut self, disk: Disk) {
+        self.backend.set_save_cache(disk);
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        self.backend.reset()
+    }
+
+    fn read_memory(&self, offset: usize, buf: &mut [u8]) {
+        self.backend.read_memory(offset, buf);
+    }
+
+    fn take_host_calls(&mut self) -> wasm4::HostCallCounts {
+        self.backend.take_host_calls()
+    }
+}